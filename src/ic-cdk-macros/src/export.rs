@@ -12,6 +12,8 @@ struct ExportAttributes {
     pub guard: Option<String>,
     #[serde(default)]
     pub manual_reply: bool,
+    #[serde(default)]
+    pub composite: bool,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -110,6 +112,13 @@ fn dfn_macro(
         ));
     }
 
+    if attrs.composite && method != MethodType::Query {
+        return Err(Error::new(
+            Span::call_site(),
+            format!("#[{}] cannot be a composite query.", method),
+        ));
+    }
+
     let is_async = signature.asyncness.is_some();
 
     let return_length = match &signature.output {
@@ -133,14 +142,19 @@ fn dfn_macro(
 
     let outer_function_ident = Ident::new(&format!("{}_{}_", name, crate::id()), Span::call_site());
 
+    // The name of the method, as recorded in the canister log.
+    let method_name = if method.is_lifecycle() {
+        method.to_string()
+    } else {
+        attrs.name.unwrap_or_else(|| name.to_string())
+    };
+
     let export_name = if method.is_lifecycle() {
         format!("canister_{}", method)
+    } else if attrs.composite {
+        format!("canister_composite_query {}", method_name)
     } else {
-        format!(
-            "canister_{0} {1}",
-            method,
-            attrs.name.unwrap_or_else(|| name.to_string())
-        )
+        format!("canister_{0} {1}", method, method_name)
     };
 
     let function_call = if is_async {
@@ -170,6 +184,18 @@ fn dfn_macro(
         quote! { let ( #( #arg_tuple, )* ) = ic_cdk::api::call::arg_data(); }
     };
 
+    // New update calls are refused while the canister is low on cycles.
+    let low_balance = if method == MethodType::Update {
+        quote! {
+            if let Err(e) = ic_cdk::cycles::check_low_balance() {
+                ic_cdk::api::call::reject(&e);
+                return;
+            }
+        }
+    } else {
+        quote! {}
+    };
+
     let guard = if let Some(guard_name) = attrs.guard {
         let guard_ident = syn::Ident::new(&guard_name, Span::call_site());
 
@@ -188,6 +214,9 @@ fn dfn_macro(
         #[export_name = #export_name]
         fn #outer_function_ident() {
             ic_cdk::setup();
+            ic_cdk::log::set_method(Some(#method_name));
+
+            #low_balance
 
             #guard
 
@@ -259,6 +288,7 @@ mod test {
             #[export_name = "canister_query query"]
             fn #fn_name() {
                 ic_cdk::setup();
+                ic_cdk::log::set_method(Some("query"));
                 ic_cdk::spawn(async {
                     let () = ic_cdk::api::call::arg_data();
                     let result = query();
@@ -296,6 +326,7 @@ mod test {
             #[export_name = "canister_query query"]
             fn #fn_name() {
                 ic_cdk::setup();
+                ic_cdk::log::set_method(Some("query"));
                 ic_cdk::spawn(async {
                     let () = ic_cdk::api::call::arg_data();
                     let result = query();
@@ -333,6 +364,7 @@ mod test {
             #[export_name = "canister_query query"]
             fn #fn_name() {
                 ic_cdk::setup();
+                ic_cdk::log::set_method(Some("query"));
                 ic_cdk::spawn(async {
                     let () = ic_cdk::api::call::arg_data();
                     let result = query();
@@ -370,6 +402,7 @@ mod test {
             #[export_name = "canister_query query"]
             fn #fn_name() {
                 ic_cdk::setup();
+                ic_cdk::log::set_method(Some("query"));
                 ic_cdk::spawn(async {
                     let (a, ) = ic_cdk::api::call::arg_data();
                     let result = query(a);
@@ -407,6 +440,7 @@ mod test {
             #[export_name = "canister_query query"]
             fn #fn_name() {
                 ic_cdk::setup();
+                ic_cdk::log::set_method(Some("query"));
                 ic_cdk::spawn(async {
                     let (a, b, ) = ic_cdk::api::call::arg_data();
                     let result = query(a, b);
@@ -444,6 +478,7 @@ mod test {
             #[export_name = "canister_query query"]
             fn #fn_name() {
                 ic_cdk::setup();
+                ic_cdk::log::set_method(Some("query"));
                 ic_cdk::spawn(async {
                     let (a, b, ) = ic_cdk::api::call::arg_data();
                     let result = query(a, b);
@@ -481,6 +516,7 @@ mod test {
             #[export_name = "canister_query custom_query"]
             fn #fn_name() {
                 ic_cdk::setup();
+                ic_cdk::log::set_method(Some("custom_query"));
                 ic_cdk::spawn(async {
                     let () = ic_cdk::api::call::arg_data();
                     let result = query();
@@ -498,4 +534,93 @@ mod test {
             _ => panic!("not a function"),
         };
     }
+
+    #[test]
+    fn ic_query_composite() {
+        let generated = ic_query(
+            quote!(composite = true),
+            quote! {
+                async fn query() {}
+            },
+        )
+        .unwrap();
+        let parsed = syn::parse2::<syn::File>(generated).unwrap();
+        let fn_name = match parsed.items[0] {
+            syn::Item::Fn(ref f) => &f.sig.ident,
+            _ => panic!("Incorrect parsed AST."),
+        };
+
+        let expected = quote! {
+            #[export_name = "canister_composite_query query"]
+            fn #fn_name() {
+                ic_cdk::setup();
+                ic_cdk::log::set_method(Some("query"));
+                ic_cdk::spawn(async {
+                    let () = ic_cdk::api::call::arg_data();
+                    let result = query().await;
+                    ic_cdk::api::call::reply(())
+                });
+            }
+        };
+        let expected = syn::parse2::<syn::ItemFn>(expected).unwrap();
+
+        match &parsed.items[0] {
+            syn::Item::Fn(f) => {
+                assert_eq!(*f, expected);
+            }
+            _ => panic!("not a function"),
+        };
+    }
+
+    #[test]
+    fn ic_update_composite() {
+        let generated = ic_update(
+            quote!(composite = true),
+            quote! {
+                fn update() {}
+            },
+        );
+        assert!(generated.is_err());
+    }
+
+    #[test]
+    fn ic_update_checks_low_balance() {
+        let generated = ic_update(
+            quote!(),
+            quote! {
+                fn update() {}
+            },
+        )
+        .unwrap();
+        let parsed = syn::parse2::<syn::File>(generated).unwrap();
+        let fn_name = match parsed.items[0] {
+            syn::Item::Fn(ref f) => &f.sig.ident,
+            _ => panic!("Incorrect parsed AST."),
+        };
+
+        let expected = quote! {
+            #[export_name = "canister_update update"]
+            fn #fn_name() {
+                ic_cdk::setup();
+                ic_cdk::log::set_method(Some("update"));
+                if let Err(e) = ic_cdk::cycles::check_low_balance() {
+                    ic_cdk::api::call::reject(&e);
+                    return;
+                }
+                ic_cdk::spawn(async {
+                    let () = ic_cdk::api::call::arg_data();
+                    let result = update();
+                    ic_cdk::api::call::reply(())
+                });
+            }
+        };
+        let expected = syn::parse2::<syn::ItemFn>(expected).unwrap();
+
+        match &parsed.items[0] {
+            syn::Item::Fn(f) => {
+                assert_eq!(*f, expected);
+            }
+            _ => panic!("not a function"),
+        };
+    }
 }
@@ -122,6 +122,24 @@ where
 /// }
 /// ```
 ///
+/// Setting `composite` to `true` exports the function as `canister_composite_query <name>`
+/// instead. A composite query can call query methods of other canisters on the same subnet,
+/// without attaching cycles, and is only available to ingress queries, not to other canisters.
+///
+/// ```rust
+/// # use ic_cdk_macros::query;
+/// # use ic_cdk::export::Principal;
+/// #[query(composite = true)]
+/// async fn total_supply(ledgers: Vec<Principal>) -> u64 {
+///     let mut total = 0;
+///     for ledger in ledgers {
+///         let (supply,): (u64,) = ic_cdk::call(ledger, "total_supply", ()).await.unwrap();
+///         total += supply;
+///     }
+///     total
+/// }
+/// ```
+///
 /// [`call::reply`]: ic_cdk::api::call::reply
 #[proc_macro_attribute]
 pub fn query(attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -187,7 +205,12 @@ pub fn query(attr: TokenStream, item: TokenStream) -> TokenStream {
 /// }
 /// ```
 ///
+/// While the cycle balance of the canister is below the threshold set with
+/// [`cycles::set_low_balance_threshold`], new calls are trapped or rejected before the guard
+/// runs.
+///
 /// [`call::reply`]: ic_cdk::api::call::reply
+/// [`cycles::set_low_balance_threshold`]: ic_cdk::cycles::set_low_balance_threshold
 #[proc_macro_attribute]
 pub fn update(attr: TokenStream, item: TokenStream) -> TokenStream {
     handle_debug_and_errors(export::ic_update, "ic_update", attr, item)
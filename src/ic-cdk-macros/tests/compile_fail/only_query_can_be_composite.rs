@@ -0,0 +1,6 @@
+use ic_cdk_macros::*;
+
+#[update(composite = true)]
+fn update() {}
+
+fn main() {}
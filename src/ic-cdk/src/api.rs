@@ -2,62 +2,95 @@
 use crate::export::Principal;
 use std::convert::TryFrom;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod backend;
 pub mod call;
+pub mod management_canister;
+#[cfg(feature = "randomness")]
+pub mod randomness;
 pub mod stable;
 
 mod ic0;
+pub(crate) mod sys;
 
 /// Prints the given message.
 pub fn print<S: std::convert::AsRef<str>>(s: S) {
-    let s = s.as_ref();
-    unsafe {
-        ic0::debug_print(s.as_ptr() as i32, s.len() as i32);
-    }
+    sys::debug_print(s.as_ref().as_bytes());
 }
 
 /// Traps with the given message.
 pub fn trap(message: &str) -> ! {
-    unsafe {
-        ic0::trap(message.as_ptr() as i32, message.len() as i32);
-    }
+    sys::trap(message.as_bytes());
     unreachable!()
 }
 
 /// Get current timestamp
 pub fn time() -> u64 {
-    unsafe { ic0::time() as u64 }
+    sys::time()
 }
 
 /// Returns the caller of the current call.
 pub fn caller() -> Principal {
-    let len: u32 = unsafe { ic0::msg_caller_size() as u32 };
-    let mut bytes = vec![0u8; len as usize];
-    unsafe {
-        ic0::msg_caller_copy(bytes.as_mut_ptr() as i32, 0, len as i32);
-    }
+    let mut bytes = vec![0u8; sys::msg_caller_size()];
+    sys::msg_caller_copy(&mut bytes, 0);
     Principal::try_from(&bytes).unwrap()
 }
 
 /// Returns the canister id as a blob.
 pub fn id() -> Principal {
-    let len: u32 = unsafe { ic0::canister_self_size() as u32 };
-    let mut bytes = vec![0u8; len as usize];
-    unsafe {
-        ic0::canister_self_copy(bytes.as_mut_ptr() as i32, 0, len as i32);
-    }
+    let mut bytes = vec![0u8; sys::canister_self_size()];
+    sys::canister_self_copy(&mut bytes, 0);
     Principal::try_from(&bytes).unwrap()
 }
 
 /// Get the amount of funds available in the canister.
 pub fn canister_balance() -> u64 {
-    unsafe { ic0::canister_cycle_balance() as u64 }
+    sys::canister_cycle_balance()
 }
 
 /// Get the amount of funds available in the canister.
 pub fn canister_balance128() -> u128 {
-    let mut recv = 0u128;
-    unsafe { ic0::canister_cycle_balance128(&mut recv as *mut u128 as i32) }
-    recv
+    sys::canister_cycle_balance128()
+}
+
+/// The run status of a canister, as returned by [canister_status].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CanisterStatus {
+    /// The canister accepts new calls.
+    Running,
+    /// The canister is being stopped: it rejects new calls and only processes the
+    /// responses to the calls it made.
+    Stopping,
+    /// The canister is stopped.
+    Stopped,
+}
+
+/// Returns the run status of this canister.
+///
+/// Long-running work, such as chains of inter-canister calls, should not be started while
+/// the canister is [stopping](CanisterStatus::Stopping), as it keeps the canister from stopping.
+pub fn canister_status() -> CanisterStatus {
+    match sys::canister_status() {
+        1 => CanisterStatus::Running,
+        2 => CanisterStatus::Stopping,
+        3 => CanisterStatus::Stopped,
+        status => trap(&format!("Unexpected canister status {}", status)),
+    }
+}
+
+/// Returns whether `principal` is a controller of this canister.
+///
+/// ```rust,no_run
+/// fn controllers_only() -> Result<(), String> {
+///     if ic_cdk::api::is_controller(&ic_cdk::caller()) {
+///         Ok(())
+///     } else {
+///         Err("The caller is not a controller.".to_string())
+///     }
+/// }
+/// ```
+pub fn is_controller(principal: &Principal) -> bool {
+    sys::is_controller(principal.as_slice())
 }
 
 /// Sets the certified data of this canister.
@@ -79,7 +112,7 @@ pub fn canister_balance128() -> u128 {
 /// * This function traps if it's called from an illegal context
 ///   (e.g., from a query call).
 pub fn set_certified_data(data: &[u8]) {
-    unsafe { ic0::certified_data_set(data.as_ptr() as i32, data.len() as i32) }
+    sys::certified_data_set(data)
 }
 
 /// When called from a query call, returns the data certificate authenticating
@@ -87,15 +120,12 @@ pub fn set_certified_data(data: &[u8]) {
 ///
 /// Returns None if called not from a query call.
 pub fn data_certificate() -> Option<Vec<u8>> {
-    if unsafe { ic0::data_certificate_present() } == 0 {
+    if !sys::data_certificate_present() {
         return None;
     }
 
-    let n = unsafe { ic0::data_certificate_size() };
-    let mut buf = vec![0u8; n as usize];
-    unsafe {
-        ic0::data_certificate_copy(buf.as_mut_ptr() as i32, 0i32, n);
-    }
+    let mut buf = vec![0u8; sys::data_certificate_size()];
+    sys::data_certificate_copy(&mut buf, 0);
     Some(buf)
 }
 
@@ -112,5 +142,5 @@ pub fn instruction_counter() -> u64 {
 /// 0 : instruction counter. The number of WebAssembly instructions the system has determined that the canister has executed.
 #[inline]
 pub fn performance_counter(counter_type: u32) -> u64 {
-    unsafe { ic0::performance_counter(counter_type as i32) as u64 }
+    sys::performance_counter(counter_type)
 }
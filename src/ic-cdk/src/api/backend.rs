@@ -0,0 +1,291 @@
+//! Pluggable System API backend for running canister code outside of a canister.
+//!
+//! The System API is only available to WebAssembly modules running on the IC.
+//! On every other target, calls made through [api](crate::api), [call](crate::api::call),
+//! [stable](crate::api::stable) and [storage](crate::storage) are routed to a [SystemApi]
+//! implementation installed for the current thread, which makes that code testable with a
+//! plain `cargo test`.
+//!
+//! Every thread starts with a fresh [MockSystemApi]. Tests that need to control the
+//! environment or inspect what the code did install their own instance:
+//!
+//! ```rust
+//! use ic_cdk::api::backend::{set_system_api, MockSystemApi};
+//! use ic_cdk::export::Principal;
+//! use std::rc::Rc;
+//!
+//! let mock = Rc::new(MockSystemApi::new());
+//! mock.set_caller(Principal::anonymous());
+//! mock.set_time(1_000_000_000);
+//! set_system_api(mock.clone());
+//!
+//! assert_eq!(ic_cdk::api::time(), 1_000_000_000);
+//! ic_cdk::api::call::reply_raw(b"done");
+//! assert_eq!(mock.reply(), Some(b"done".to_vec()));
+//! ```
+//!
+//! This module is not available when compiling for `wasm32`, where the real System API is used.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod mock;
+#[cfg(test)]
+mod tests;
+
+pub use mock::{MockSystemApi, OutgoingCall};
+
+/// The System API, with Rust types in place of raw pointers.
+///
+/// Each method corresponds to the `ic0` import of the same name. Buffers are passed as slices,
+/// `*_copy` methods fill the whole `dst` slice starting at `offset`, and 128-bit amounts of
+/// cycles are passed as a single `u128`.
+///
+/// Every method has a default implementation that panics, so a backend only needs to implement
+/// the parts of the API the code under test actually uses.
+#[allow(unused_variables)]
+pub trait SystemApi {
+    /// Size of the argument data of the current message.
+    fn msg_arg_data_size(&self) -> usize {
+        unsupported("msg_arg_data_size")
+    }
+    /// Copies the argument data of the current message.
+    fn msg_arg_data_copy(&self, dst: &mut [u8], offset: usize) {
+        unsupported("msg_arg_data_copy")
+    }
+    /// Size of the caller principal.
+    fn msg_caller_size(&self) -> usize {
+        unsupported("msg_caller_size")
+    }
+    /// Copies the caller principal.
+    fn msg_caller_copy(&self, dst: &mut [u8], offset: usize) {
+        unsupported("msg_caller_copy")
+    }
+    /// Reject code of the response being handled, `0` for a reply.
+    fn msg_reject_code(&self) -> i32 {
+        unsupported("msg_reject_code")
+    }
+    /// Size of the reject message of the response being handled.
+    fn msg_reject_msg_size(&self) -> usize {
+        unsupported("msg_reject_msg_size")
+    }
+    /// Copies the reject message of the response being handled.
+    fn msg_reject_msg_copy(&self, dst: &mut [u8], offset: usize) {
+        unsupported("msg_reject_msg_copy")
+    }
+    /// Appends data to the reply being built.
+    fn msg_reply_data_append(&self, data: &[u8]) {
+        unsupported("msg_reply_data_append")
+    }
+    /// Replies to the current message with the data appended so far.
+    fn msg_reply(&self) {
+        unsupported("msg_reply")
+    }
+    /// Rejects the current message.
+    fn msg_reject(&self, message: &[u8]) {
+        unsupported("msg_reject")
+    }
+    /// Cycles attached to the current message and still available.
+    fn msg_cycles_available(&self) -> u64 {
+        unsupported("msg_cycles_available")
+    }
+    /// Like [msg_cycles_available](SystemApi::msg_cycles_available), with 128-bit cycles.
+    fn msg_cycles_available128(&self) -> u128 {
+        unsupported("msg_cycles_available128")
+    }
+    /// Cycles refunded with the response being handled.
+    fn msg_cycles_refunded(&self) -> u64 {
+        unsupported("msg_cycles_refunded")
+    }
+    /// Like [msg_cycles_refunded](SystemApi::msg_cycles_refunded), with 128-bit cycles.
+    fn msg_cycles_refunded128(&self) -> u128 {
+        unsupported("msg_cycles_refunded128")
+    }
+    /// Moves up to `max_amount` cycles from the message to the canister balance.
+    fn msg_cycles_accept(&self, max_amount: u64) -> u64 {
+        unsupported("msg_cycles_accept")
+    }
+    /// Like [msg_cycles_accept](SystemApi::msg_cycles_accept), with 128-bit cycles.
+    fn msg_cycles_accept128(&self, max_amount: u128) -> u128 {
+        unsupported("msg_cycles_accept128")
+    }
+    /// Size of the principal of this canister.
+    fn canister_self_size(&self) -> usize {
+        unsupported("canister_self_size")
+    }
+    /// Copies the principal of this canister.
+    fn canister_self_copy(&self, dst: &mut [u8], offset: usize) {
+        unsupported("canister_self_copy")
+    }
+    /// Cycle balance of this canister.
+    fn canister_cycle_balance(&self) -> u64 {
+        unsupported("canister_cycle_balance")
+    }
+    /// Like [canister_cycle_balance](SystemApi::canister_cycle_balance), with 128-bit cycles.
+    fn canister_cycle_balance128(&self) -> u128 {
+        unsupported("canister_cycle_balance128")
+    }
+    /// Run status of this canister: 1 (running), 2 (stopping) or 3 (stopped).
+    fn canister_status(&self) -> u32 {
+        unsupported("canister_status")
+    }
+    /// Size of the name of the method being inspected.
+    fn msg_method_name_size(&self) -> usize {
+        unsupported("msg_method_name_size")
+    }
+    /// Copies the name of the method being inspected.
+    fn msg_method_name_copy(&self, dst: &mut [u8], offset: usize) {
+        unsupported("msg_method_name_copy")
+    }
+    /// Accepts the ingress message being inspected.
+    fn accept_message(&self) {
+        unsupported("accept_message")
+    }
+    /// Starts building an inter-canister call.
+    ///
+    /// The callbacks and their environments are opaque values; `usize::MAX` is used for
+    /// the callbacks of one-way calls.
+    fn call_new(
+        &self,
+        callee: &[u8],
+        method: &[u8],
+        reply_fun: usize,
+        reply_env: usize,
+        reject_fun: usize,
+        reject_env: usize,
+    ) {
+        unsupported("call_new")
+    }
+    /// Sets the cleanup callback of the call being built.
+    fn call_on_cleanup(&self, fun: usize, env: usize) {
+        unsupported("call_on_cleanup")
+    }
+    /// Appends data to the argument of the call being built.
+    fn call_data_append(&self, data: &[u8]) {
+        unsupported("call_data_append")
+    }
+    /// Attaches cycles to the call being built.
+    fn call_cycles_add(&self, amount: u64) {
+        unsupported("call_cycles_add")
+    }
+    /// Like [call_cycles_add](SystemApi::call_cycles_add), with 128-bit cycles.
+    fn call_cycles_add128(&self, amount: u128) {
+        unsupported("call_cycles_add128")
+    }
+    /// Sends the call being built. Returns `0` on success, or a reject code.
+    fn call_perform(&self) -> i32 {
+        unsupported("call_perform")
+    }
+    /// Size of the stable memory, in pages.
+    fn stable_size(&self) -> u32 {
+        unsupported("stable_size")
+    }
+    /// Grows the stable memory. Returns the previous size in pages, or `-1` on failure.
+    fn stable_grow(&self, new_pages: u32) -> i32 {
+        unsupported("stable_grow")
+    }
+    /// Writes to the stable memory.
+    fn stable_write(&self, offset: u32, src: &[u8]) {
+        unsupported("stable_write")
+    }
+    /// Reads from the stable memory.
+    fn stable_read(&self, dst: &mut [u8], offset: u32) {
+        unsupported("stable_read")
+    }
+    /// Like [stable_size](SystemApi::stable_size), with 64-bit addressing.
+    fn stable64_size(&self) -> u64 {
+        unsupported("stable64_size")
+    }
+    /// Like [stable_grow](SystemApi::stable_grow), with 64-bit addressing.
+    fn stable64_grow(&self, new_pages: u64) -> i64 {
+        unsupported("stable64_grow")
+    }
+    /// Like [stable_write](SystemApi::stable_write), with 64-bit addressing.
+    fn stable64_write(&self, offset: u64, src: &[u8]) {
+        unsupported("stable64_write")
+    }
+    /// Like [stable_read](SystemApi::stable_read), with 64-bit addressing.
+    fn stable64_read(&self, dst: &mut [u8], offset: u64) {
+        unsupported("stable64_read")
+    }
+    /// Sets the certified data of this canister.
+    fn certified_data_set(&self, data: &[u8]) {
+        unsupported("certified_data_set")
+    }
+    /// Whether a data certificate is available.
+    fn data_certificate_present(&self) -> bool {
+        unsupported("data_certificate_present")
+    }
+    /// Size of the data certificate.
+    fn data_certificate_size(&self) -> usize {
+        unsupported("data_certificate_size")
+    }
+    /// Copies the data certificate.
+    fn data_certificate_copy(&self, dst: &mut [u8], offset: usize) {
+        unsupported("data_certificate_copy")
+    }
+    /// Current time, in nanoseconds since 1970-01-01.
+    fn time(&self) -> u64 {
+        unsupported("time")
+    }
+    /// Sets the global timer to fire at `timestamp`, or deactivates it if `timestamp` is `0`.
+    /// Returns the previous value of the timer.
+    fn global_timer_set(&self, timestamp: u64) -> u64 {
+        unsupported("global_timer_set")
+    }
+    /// Value of the given performance counter.
+    fn performance_counter(&self, counter_type: u32) -> u64 {
+        unsupported("performance_counter")
+    }
+    /// Whether `principal` is a controller of this canister.
+    fn is_controller(&self, principal: &[u8]) -> bool {
+        unsupported("is_controller")
+    }
+    /// Prints a debug message.
+    fn debug_print(&self, message: &[u8]) {
+        unsupported("debug_print")
+    }
+    /// Traps with the given message.
+    ///
+    /// The implementation is expected to diverge, usually by panicking.
+    fn trap(&self, message: &[u8]) {
+        unsupported("trap")
+    }
+}
+
+fn unsupported(name: &str) -> ! {
+    panic!("{} is not supported by this System API backend.", name)
+}
+
+thread_local! {
+    static SYSTEM_API: RefCell<Rc<dyn SystemApi>> = RefCell::new(Rc::new(MockSystemApi::new()));
+}
+
+/// Installs the System API backend used by the current thread.
+pub fn set_system_api(api: Rc<dyn SystemApi>) {
+    SYSTEM_API.with(|cell| *cell.borrow_mut() = api);
+}
+
+/// Polls `future` once, as a canister would when the message that created it ends.
+#[cfg(test)]
+pub(crate) fn poll_once<F: std::future::Future>(future: F) -> Option<F::Output> {
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    fn raw_waker() -> RawWaker {
+        static VTABLE: RawWakerVTable =
+            RawWakerVTable::new(|_| raw_waker(), |_| {}, |_| {}, |_| {});
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    let waker = unsafe { Waker::from_raw(raw_waker()) };
+    let mut future = Box::pin(future);
+    match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
+
+/// Runs `f` with the System API backend of the current thread.
+pub(crate) fn with_system_api<R>(f: impl FnOnce(&dyn SystemApi) -> R) -> R {
+    // Clone the handle so that the backend may call `set_system_api` re-entrantly.
+    let api = SYSTEM_API.with(|cell| cell.borrow().clone());
+    f(&*api)
+}
@@ -0,0 +1,508 @@
+use super::SystemApi;
+use crate::api::call::RejectionCode;
+use crate::export::Principal;
+use std::cell::RefCell;
+
+const WASM_PAGE_SIZE_IN_BYTES: usize = 64 * 1024; // 64KB
+
+// The 32-bit stable memory API can address at most 4GiB.
+const MAX_STABLE32_PAGES: u64 = 1 << 16;
+
+/// An inter-canister call recorded by [MockSystemApi].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutgoingCall {
+    /// The callee.
+    pub callee: Principal,
+    /// The method called on the callee.
+    pub method: String,
+    /// The raw argument of the call.
+    pub args: Vec<u8>,
+    /// The cycles attached to the call.
+    pub cycles: u128,
+    /// Whether the call was sent as a one-way message.
+    pub oneway: bool,
+}
+
+#[derive(Default)]
+struct State {
+    caller: Vec<u8>,
+    id: Vec<u8>,
+    arg_data: Vec<u8>,
+    method_name: String,
+    time: u64,
+    global_timer: u64,
+    status: u32,
+    balance: u128,
+    cycles_available: u128,
+    cycles_refunded: u128,
+    reject_code: i32,
+    reject_message: String,
+    reply_buffer: Vec<u8>,
+    reply: Option<Vec<u8>>,
+    rejection: Option<String>,
+    message_accepted: bool,
+    pending_call: Option<OutgoingCall>,
+    calls: Vec<OutgoingCall>,
+    call_perform_result: i32,
+    stable_memory: Vec<u8>,
+    max_stable_pages: u64,
+    certified_data: Vec<u8>,
+    data_certificate: Option<Vec<u8>>,
+    instruction_counter: u64,
+    controllers: Vec<Vec<u8>>,
+    debug_prints: Vec<String>,
+}
+
+/// An in-memory [SystemApi] that lets tests set up the environment of a message
+/// and inspect its effects.
+///
+/// All setters and getters take `&self`, so the mock can be shared through an `Rc` with
+/// [set_system_api](super::set_system_api).
+///
+/// Inter-canister calls are recorded (see [calls](MockSystemApi::calls)) but never delivered,
+/// so the futures of successful calls stay pending.
+pub struct MockSystemApi {
+    state: RefCell<State>,
+}
+
+impl Default for MockSystemApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockSystemApi {
+    /// Creates a mock for a running canister with an empty stable memory, called by the
+    /// anonymous principal.
+    pub fn new() -> Self {
+        let id =
+            Principal::from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01]);
+        Self {
+            state: RefCell::new(State {
+                caller: Principal::anonymous().as_slice().to_vec(),
+                id: id.as_slice().to_vec(),
+                status: 1,
+                max_stable_pages: u64::MAX / WASM_PAGE_SIZE_IN_BYTES as u64,
+                ..State::default()
+            }),
+        }
+    }
+
+    /// Sets the caller of the current message.
+    pub fn set_caller(&self, caller: Principal) {
+        self.state.borrow_mut().caller = caller.as_slice().to_vec();
+    }
+
+    /// Sets the principal of the canister itself.
+    pub fn set_id(&self, id: Principal) {
+        self.state.borrow_mut().id = id.as_slice().to_vec();
+    }
+
+    /// Sets the raw argument of the current message.
+    pub fn set_arg_data(&self, arg_data: Vec<u8>) {
+        self.state.borrow_mut().arg_data = arg_data;
+    }
+
+    /// Sets the method name returned by [method_name](crate::api::call::method_name).
+    pub fn set_method_name(&self, method_name: &str) {
+        self.state.borrow_mut().method_name = method_name.to_string();
+    }
+
+    /// Sets the current time, in nanoseconds since 1970-01-01.
+    pub fn set_time(&self, time: u64) {
+        self.state.borrow_mut().time = time;
+    }
+
+    /// Returns the deadline of the global timer, `0` if it is not set.
+    pub fn global_timer(&self) -> u64 {
+        self.state.borrow().global_timer
+    }
+
+    /// Sets the run status of the canister: 1 (running), 2 (stopping) or 3 (stopped).
+    pub fn set_canister_status(&self, status: u32) {
+        self.state.borrow_mut().status = status;
+    }
+
+    /// Sets the cycle balance of the canister.
+    pub fn set_balance(&self, balance: u128) {
+        self.state.borrow_mut().balance = balance;
+    }
+
+    /// Returns the cycle balance of the canister.
+    pub fn balance(&self) -> u128 {
+        self.state.borrow().balance
+    }
+
+    /// Sets the cycles attached to the current message.
+    pub fn set_msg_cycles_available(&self, cycles: u128) {
+        self.state.borrow_mut().cycles_available = cycles;
+    }
+
+    /// Sets the cycles refunded with the response being handled.
+    pub fn set_msg_cycles_refunded(&self, cycles: u128) {
+        self.state.borrow_mut().cycles_refunded = cycles;
+    }
+
+    /// Sets the reject code and message of the response being handled.
+    pub fn set_reject(&self, code: RejectionCode, message: &str) {
+        let mut state = self.state.borrow_mut();
+        state.reject_code = code as i32;
+        state.reject_message = message.to_string();
+    }
+
+    /// Sets the value returned by `call_perform`, `0` meaning that the call was sent.
+    pub fn set_call_perform_result(&self, code: i32) {
+        self.state.borrow_mut().call_perform_result = code;
+    }
+
+    /// Replaces the content of the stable memory, rounding it up to whole pages.
+    pub fn set_stable_memory(&self, mut bytes: Vec<u8>) {
+        let pages = (bytes.len() + WASM_PAGE_SIZE_IN_BYTES - 1) / WASM_PAGE_SIZE_IN_BYTES;
+        bytes.resize(pages * WASM_PAGE_SIZE_IN_BYTES, 0);
+        self.state.borrow_mut().stable_memory = bytes;
+    }
+
+    /// Sets the number of pages beyond which growing the stable memory fails.
+    pub fn set_max_stable_pages(&self, pages: u64) {
+        self.state.borrow_mut().max_stable_pages = pages;
+    }
+
+    /// Returns a copy of the stable memory.
+    pub fn stable_memory(&self) -> Vec<u8> {
+        self.state.borrow().stable_memory.clone()
+    }
+
+    /// Sets the data certificate, making it available as in a query call.
+    pub fn set_data_certificate(&self, certificate: Option<Vec<u8>>) {
+        self.state.borrow_mut().data_certificate = certificate;
+    }
+
+    /// Returns the certified data set by the canister.
+    pub fn certified_data(&self) -> Vec<u8> {
+        self.state.borrow().certified_data.clone()
+    }
+
+    /// Sets the value of the instruction counter.
+    pub fn set_instruction_counter(&self, count: u64) {
+        self.state.borrow_mut().instruction_counter = count;
+    }
+
+    /// Sets the controllers of the canister, none by default.
+    pub fn set_controllers(&self, controllers: Vec<Principal>) {
+        self.state.borrow_mut().controllers = controllers
+            .iter()
+            .map(|controller| controller.as_slice().to_vec())
+            .collect();
+    }
+
+    /// Returns the reply to the current message, if any.
+    pub fn reply(&self) -> Option<Vec<u8>> {
+        self.state.borrow().reply.clone()
+    }
+
+    /// Returns the reject message of the current message, if it was rejected.
+    pub fn rejection(&self) -> Option<String> {
+        self.state.borrow().rejection.clone()
+    }
+
+    /// Whether the ingress message was accepted by `canister_inspect_message`.
+    pub fn message_accepted(&self) -> bool {
+        self.state.borrow().message_accepted
+    }
+
+    /// Returns the messages printed so far.
+    pub fn debug_prints(&self) -> Vec<String> {
+        self.state.borrow().debug_prints.clone()
+    }
+
+    /// Returns the inter-canister calls sent so far.
+    pub fn calls(&self) -> Vec<OutgoingCall> {
+        self.state.borrow().calls.clone()
+    }
+
+    /// Clears the reply, rejection and accepted status so that another message can be handled.
+    pub fn reset_response(&self) {
+        let mut state = self.state.borrow_mut();
+        state.reply_buffer.clear();
+        state.reply = None;
+        state.rejection = None;
+        state.message_accepted = false;
+    }
+
+    fn respond(&self, f: impl FnOnce(&mut State)) {
+        let responded = {
+            let state = self.state.borrow();
+            state.reply.is_some() || state.rejection.is_some()
+        };
+        if responded {
+            self.trap(b"the current message has already been responded to");
+        }
+        f(&mut self.state.borrow_mut());
+    }
+
+    fn with_pending_call(&self, f: impl FnOnce(&mut OutgoingCall)) {
+        let mut state = self.state.borrow_mut();
+        match state.pending_call.as_mut() {
+            Some(call) => f(call),
+            None => {
+                drop(state);
+                self.trap(b"no call is being built");
+            }
+        }
+    }
+
+    fn add_cycles(&self, amount: u128) {
+        let mut state = self.state.borrow_mut();
+        if state.balance < amount {
+            drop(state);
+            self.trap(b"the canister does not have enough cycles");
+            return;
+        }
+        state.balance -= amount;
+        drop(state);
+        self.with_pending_call(|call| call.cycles += amount);
+    }
+
+    fn grow(&self, new_pages: u64, max_pages: u64) -> Option<u64> {
+        let mut state = self.state.borrow_mut();
+        let old_pages = (state.stable_memory.len() / WASM_PAGE_SIZE_IN_BYTES) as u64;
+        let max_pages = max_pages.min(state.max_stable_pages);
+        match old_pages.checked_add(new_pages) {
+            Some(pages) if pages <= max_pages => {
+                state
+                    .stable_memory
+                    .resize(pages as usize * WASM_PAGE_SIZE_IN_BYTES, 0);
+                Some(old_pages)
+            }
+            _ => None,
+        }
+    }
+
+    fn write(&self, offset: u64, src: &[u8]) {
+        let mut state = self.state.borrow_mut();
+        let offset = offset as usize;
+        if offset + src.len() > state.stable_memory.len() {
+            drop(state);
+            self.trap(b"stable memory out of bounds");
+            return;
+        }
+        state.stable_memory[offset..offset + src.len()].copy_from_slice(src);
+    }
+
+    fn read(&self, dst: &mut [u8], offset: u64) {
+        let state = self.state.borrow();
+        let offset = offset as usize;
+        if offset + dst.len() > state.stable_memory.len() {
+            drop(state);
+            self.trap(b"stable memory out of bounds");
+            return;
+        }
+        dst.copy_from_slice(&state.stable_memory[offset..offset + dst.len()]);
+    }
+}
+
+fn copy(src: &[u8], dst: &mut [u8], offset: usize) {
+    dst.copy_from_slice(&src[offset..offset + dst.len()]);
+}
+
+impl SystemApi for MockSystemApi {
+    fn msg_arg_data_size(&self) -> usize {
+        self.state.borrow().arg_data.len()
+    }
+    fn msg_arg_data_copy(&self, dst: &mut [u8], offset: usize) {
+        copy(&self.state.borrow().arg_data, dst, offset)
+    }
+    fn msg_caller_size(&self) -> usize {
+        self.state.borrow().caller.len()
+    }
+    fn msg_caller_copy(&self, dst: &mut [u8], offset: usize) {
+        copy(&self.state.borrow().caller, dst, offset)
+    }
+    fn msg_reject_code(&self) -> i32 {
+        self.state.borrow().reject_code
+    }
+    fn msg_reject_msg_size(&self) -> usize {
+        self.state.borrow().reject_message.len()
+    }
+    fn msg_reject_msg_copy(&self, dst: &mut [u8], offset: usize) {
+        copy(self.state.borrow().reject_message.as_bytes(), dst, offset)
+    }
+    fn msg_reply_data_append(&self, data: &[u8]) {
+        self.respond(|state| state.reply_buffer.extend_from_slice(data))
+    }
+    fn msg_reply(&self) {
+        self.respond(|state| state.reply = Some(std::mem::take(&mut state.reply_buffer)))
+    }
+    fn msg_reject(&self, message: &[u8]) {
+        self.respond(|state| {
+            state.rejection = Some(String::from_utf8_lossy(message).to_string());
+        })
+    }
+    fn msg_cycles_available(&self) -> u64 {
+        self.msg_cycles_available128() as u64
+    }
+    fn msg_cycles_available128(&self) -> u128 {
+        self.state.borrow().cycles_available
+    }
+    fn msg_cycles_refunded(&self) -> u64 {
+        self.msg_cycles_refunded128() as u64
+    }
+    fn msg_cycles_refunded128(&self) -> u128 {
+        self.state.borrow().cycles_refunded
+    }
+    fn msg_cycles_accept(&self, max_amount: u64) -> u64 {
+        self.msg_cycles_accept128(max_amount as u128) as u64
+    }
+    fn msg_cycles_accept128(&self, max_amount: u128) -> u128 {
+        let mut state = self.state.borrow_mut();
+        let accepted = max_amount.min(state.cycles_available);
+        state.cycles_available -= accepted;
+        state.balance += accepted;
+        accepted
+    }
+    fn canister_self_size(&self) -> usize {
+        self.state.borrow().id.len()
+    }
+    fn canister_self_copy(&self, dst: &mut [u8], offset: usize) {
+        copy(&self.state.borrow().id, dst, offset)
+    }
+    fn canister_cycle_balance(&self) -> u64 {
+        self.balance() as u64
+    }
+    fn canister_cycle_balance128(&self) -> u128 {
+        self.balance()
+    }
+    fn canister_status(&self) -> u32 {
+        self.state.borrow().status
+    }
+    fn msg_method_name_size(&self) -> usize {
+        self.state.borrow().method_name.len()
+    }
+    fn msg_method_name_copy(&self, dst: &mut [u8], offset: usize) {
+        copy(self.state.borrow().method_name.as_bytes(), dst, offset)
+    }
+    fn accept_message(&self) {
+        self.state.borrow_mut().message_accepted = true;
+    }
+    fn call_new(
+        &self,
+        callee: &[u8],
+        method: &[u8],
+        reply_fun: usize,
+        _reply_env: usize,
+        _reject_fun: usize,
+        _reject_env: usize,
+    ) {
+        self.state.borrow_mut().pending_call = Some(OutgoingCall {
+            callee: Principal::from_slice(callee),
+            method: String::from_utf8_lossy(method).to_string(),
+            args: Vec::new(),
+            cycles: 0,
+            oneway: reply_fun == usize::MAX,
+        });
+    }
+    fn call_on_cleanup(&self, _fun: usize, _env: usize) {
+        self.with_pending_call(|_| ())
+    }
+    fn call_data_append(&self, data: &[u8]) {
+        self.with_pending_call(|call| call.args.extend_from_slice(data))
+    }
+    fn call_cycles_add(&self, amount: u64) {
+        self.add_cycles(amount as u128)
+    }
+    fn call_cycles_add128(&self, amount: u128) {
+        self.add_cycles(amount)
+    }
+    fn call_perform(&self) -> i32 {
+        self.with_pending_call(|_| ());
+        let mut state = self.state.borrow_mut();
+        let call = state.pending_call.take().unwrap();
+        let result = state.call_perform_result;
+        if result == 0 {
+            state.calls.push(call);
+        } else {
+            state.balance += call.cycles;
+        }
+        result
+    }
+    fn stable_size(&self) -> u32 {
+        self.stable64_size() as u32
+    }
+    fn stable_grow(&self, new_pages: u32) -> i32 {
+        match self.grow(new_pages as u64, MAX_STABLE32_PAGES) {
+            Some(old_pages) => old_pages as i32,
+            None => -1,
+        }
+    }
+    fn stable_write(&self, offset: u32, src: &[u8]) {
+        self.write(offset as u64, src)
+    }
+    fn stable_read(&self, dst: &mut [u8], offset: u32) {
+        self.read(dst, offset as u64)
+    }
+    fn stable64_size(&self) -> u64 {
+        (self.state.borrow().stable_memory.len() / WASM_PAGE_SIZE_IN_BYTES) as u64
+    }
+    fn stable64_grow(&self, new_pages: u64) -> i64 {
+        match self.grow(new_pages, u64::MAX) {
+            Some(old_pages) => old_pages as i64,
+            None => -1,
+        }
+    }
+    fn stable64_write(&self, offset: u64, src: &[u8]) {
+        self.write(offset, src)
+    }
+    fn stable64_read(&self, dst: &mut [u8], offset: u64) {
+        self.read(dst, offset)
+    }
+    fn certified_data_set(&self, data: &[u8]) {
+        if data.len() > 32 {
+            self.trap(b"certified data cannot be longer than 32 bytes");
+        }
+        self.state.borrow_mut().certified_data = data.to_vec();
+    }
+    fn data_certificate_present(&self) -> bool {
+        self.state.borrow().data_certificate.is_some()
+    }
+    fn data_certificate_size(&self) -> usize {
+        self.state
+            .borrow()
+            .data_certificate
+            .as_ref()
+            .map_or(0, |c| c.len())
+    }
+    fn data_certificate_copy(&self, dst: &mut [u8], offset: usize) {
+        let state = self.state.borrow();
+        copy(
+            state.data_certificate.as_deref().unwrap_or(&[]),
+            dst,
+            offset,
+        )
+    }
+    fn time(&self) -> u64 {
+        self.state.borrow().time
+    }
+    fn global_timer_set(&self, timestamp: u64) -> u64 {
+        std::mem::replace(&mut self.state.borrow_mut().global_timer, timestamp)
+    }
+    fn performance_counter(&self, counter_type: u32) -> u64 {
+        match counter_type {
+            0 => self.state.borrow().instruction_counter,
+            _ => 0,
+        }
+    }
+    fn is_controller(&self, principal: &[u8]) -> bool {
+        self.state
+            .borrow()
+            .controllers
+            .iter()
+            .any(|controller| controller == principal)
+    }
+    fn debug_print(&self, message: &[u8]) {
+        let message = String::from_utf8_lossy(message).to_string();
+        self.state.borrow_mut().debug_prints.push(message);
+    }
+    fn trap(&self, message: &[u8]) {
+        panic!("{}", String::from_utf8_lossy(message))
+    }
+}
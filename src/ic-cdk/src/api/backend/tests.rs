@@ -0,0 +1,172 @@
+use super::*;
+use crate::api::call::{self, RejectionCode};
+use crate::api::{self, stable};
+use crate::export::Principal;
+
+fn install_mock() -> Rc<MockSystemApi> {
+    let mock = Rc::new(MockSystemApi::new());
+    set_system_api(mock.clone());
+    mock
+}
+
+#[test]
+fn exposes_message_context() {
+    let mock = install_mock();
+    let caller = Principal::from_slice(&[1, 2, 3]);
+    mock.set_caller(caller);
+    mock.set_time(42);
+    mock.set_arg_data(vec![1, 2, 3, 4]);
+    mock.set_method_name("greet");
+
+    assert_eq!(api::caller(), caller);
+    assert_eq!(api::time(), 42);
+    assert_eq!(call::arg_data_raw(), vec![1, 2, 3, 4]);
+    assert_eq!(call::arg_data_raw_size(), 4);
+    assert_eq!(call::method_name(), "greet");
+}
+
+#[test]
+fn records_replies() {
+    let mock = install_mock();
+    call::reply((42u64,));
+    assert_eq!(mock.reply(), Some(candid::encode_args((42u64,)).unwrap()));
+    assert_eq!(mock.rejection(), None);
+
+    mock.reset_response();
+    call::reply_raw(&[]);
+    assert_eq!(mock.reply(), Some(vec![]));
+}
+
+#[test]
+fn records_rejects() {
+    let mock = install_mock();
+    call::reject("nope");
+    assert_eq!(mock.rejection(), Some("nope".to_string()));
+    assert_eq!(mock.reply(), None);
+}
+
+#[test]
+#[should_panic(expected = "already been responded to")]
+fn traps_on_second_response() {
+    install_mock();
+    call::reply_raw(b"first");
+    call::reply_raw(b"second");
+}
+
+#[test]
+#[should_panic(expected = "Goodbye")]
+fn trap_panics() {
+    install_mock();
+    api::trap("Goodbye");
+}
+
+#[test]
+fn exposes_canister_status_and_controllers() {
+    let mock = install_mock();
+    let controller = Principal::from_slice(&[1]);
+    mock.set_controllers(vec![controller]);
+    assert_eq!(api::canister_status(), api::CanisterStatus::Running);
+    assert!(api::is_controller(&controller));
+    assert!(!api::is_controller(&Principal::anonymous()));
+
+    mock.set_canister_status(2);
+    assert_eq!(api::canister_status(), api::CanisterStatus::Stopping);
+}
+
+#[test]
+fn records_debug_prints() {
+    let mock = install_mock();
+    api::print("hello");
+    api::print("world");
+    assert_eq!(mock.debug_prints(), vec!["hello", "world"]);
+}
+
+#[test]
+fn accepts_cycles() {
+    let mock = install_mock();
+    mock.set_balance(1_000);
+    mock.set_msg_cycles_available(300);
+
+    assert_eq!(call::msg_cycles_accept128(200), 200);
+    assert_eq!(call::msg_cycles_available128(), 100);
+    assert_eq!(call::msg_cycles_accept(500), 100);
+    assert_eq!(api::canister_balance128(), 1_300);
+}
+
+#[test]
+fn reports_reject_of_response() {
+    let mock = install_mock();
+    mock.set_reject(RejectionCode::CanisterReject, "rejected");
+    assert_eq!(call::reject_code(), RejectionCode::CanisterReject);
+    assert_eq!(call::reject_message(), "rejected");
+}
+
+#[test]
+fn records_outgoing_calls() {
+    let mock = install_mock();
+    mock.set_balance(500);
+    let callee = Principal::from_slice(&[7]);
+
+    call::notify_raw(callee, "ping", &[1, 2], 100).unwrap();
+    let _pending = call::call_raw128(callee, "pong", &[3], 50);
+
+    assert_eq!(
+        mock.calls(),
+        vec![
+            OutgoingCall {
+                callee,
+                method: "ping".to_string(),
+                args: vec![1, 2],
+                cycles: 100,
+                oneway: true,
+            },
+            OutgoingCall {
+                callee,
+                method: "pong".to_string(),
+                args: vec![3],
+                cycles: 50,
+                oneway: false,
+            },
+        ]
+    );
+    assert_eq!(mock.balance(), 350);
+}
+
+#[test]
+fn refunds_cycles_of_failed_calls() {
+    let mock = install_mock();
+    mock.set_balance(500);
+    mock.set_call_perform_result(RejectionCode::SysTransient as i32);
+
+    let result = call::notify_raw(Principal::from_slice(&[7]), "ping", &[], 100);
+
+    assert_eq!(result, Err(RejectionCode::SysTransient));
+    assert!(mock.calls().is_empty());
+    assert_eq!(mock.balance(), 500);
+}
+
+#[test]
+fn stable_memory() {
+    let mock = install_mock();
+    assert_eq!(stable::stable_size(), 0);
+    assert_eq!(stable::stable_grow(1).unwrap(), 0);
+    stable::stable_write(10, &[1, 2, 3]);
+
+    let mut buf = [0u8; 3];
+    stable::stable_read(10, &mut buf);
+    assert_eq!(buf, [1, 2, 3]);
+    assert_eq!(mock.stable_memory()[10..13], [1, 2, 3]);
+
+    mock.set_max_stable_pages(1);
+    assert!(stable::stable64_grow(1).is_err());
+}
+
+#[test]
+fn certified_data() {
+    let mock = install_mock();
+    assert_eq!(api::data_certificate(), None);
+    api::set_certified_data(&[1; 32]);
+    assert_eq!(mock.certified_data(), vec![1; 32]);
+    mock.set_data_certificate(Some(vec![9, 9]));
+    assert_eq!(api::data_certificate(), Some(vec![9, 9]));
+}
@@ -1,5 +1,5 @@
 //! APIs to make and manage calls in the canister.
-use crate::api::{ic0, trap};
+use crate::api::{sys, trap};
 use crate::export::Principal;
 use candid::utils::{ArgumentDecoder, ArgumentEncoder};
 use candid::{decode_args, encode_args, write_args, CandidType, Deserialize};
@@ -7,9 +7,16 @@ use serde::ser::Error;
 use std::future::Future;
 use std::marker::PhantomData;
 use std::pin::Pin;
-use std::sync::atomic::Ordering;
 use std::task::{Context, Poll, Waker};
 
+mod builder;
+mod retry;
+pub use builder::{Call, DecoderConfig};
+pub use retry::{Backoff, RetryPolicy};
+
+#[cfg(test)]
+mod tests;
+
 #[cfg(target_arch = "wasm32-unknown-unknown")]
 #[allow(dead_code)]
 mod rc {
@@ -163,10 +170,85 @@ impl From<u32> for RejectionCode {
     }
 }
 
+/// The error of an inter-canister call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallError {
+    /// The call could not be sent, and the callee never saw it.
+    SyncPerformFailed {
+        /// The code returned by `ic0.call_perform`.
+        code: RejectionCode,
+    },
+    /// The callee, or the system on its behalf, rejected the call.
+    Rejected {
+        /// The rejection code.
+        code: RejectionCode,
+        /// The rejection message.
+        message: String,
+    },
+    /// The call succeeded, but its reply could not be decoded as the expected types.
+    CandidDecodeFailed {
+        /// Describes the decoding error.
+        message: String,
+    },
+    /// A callback of this call trapped, and the call is being cleaned up.
+    ///
+    /// The future of the call is dropped without being polled in that case, so this
+    /// error is only seen by code that drives futures on its own executor.
+    CleanupInvoked,
+}
+
+impl CallError {
+    /// The rejection code that best describes the error.
+    ///
+    /// Decoding failures are reported as [RejectionCode::CanisterError], and cleanups as
+    /// [RejectionCode::NoError].
+    pub fn reject_code(&self) -> RejectionCode {
+        match self {
+            CallError::SyncPerformFailed { code } | CallError::Rejected { code, .. } => *code,
+            CallError::CandidDecodeFailed { .. } => RejectionCode::CanisterError,
+            CallError::CleanupInvoked => RejectionCode::NoError,
+        }
+    }
+}
+
+impl std::fmt::Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::SyncPerformFailed { code } => {
+                write!(f, "Couldn't send message ({:?})", code)
+            }
+            CallError::Rejected { code, message } => {
+                write!(f, "The call was rejected ({:?}): {}", code, message)
+            }
+            CallError::CandidDecodeFailed { message } => {
+                write!(f, "Failed to decode the reply: {}", message)
+            }
+            CallError::CleanupInvoked => f.write_str("cleanup"),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// Converts to the `(RejectionCode, String)` pair that [CallResult] used to carry.
+impl From<CallError> for (RejectionCode, String) {
+    fn from(err: CallError) -> Self {
+        let code = err.reject_code();
+        match err {
+            CallError::SyncPerformFailed { .. } => (code, "Couldn't send message".to_string()),
+            CallError::Rejected { message, .. } | CallError::CandidDecodeFailed { message } => {
+                (code, message)
+            }
+            CallError::CleanupInvoked => (code, "cleanup".to_string()),
+        }
+    }
+}
+
 /// The result of a Call.
 ///
-/// Errors on the IC have two components; a Code and a message associated with it.
-pub type CallResult<R> = Result<R, (RejectionCode, String)>;
+/// See [CallError] for the ways a call can fail. Code written against the former
+/// `(RejectionCode, String)` error can convert it with `.map_err(Into::into)`.
+pub type CallResult<R> = Result<R, CallError>;
 
 // Internal state for the Future when sending a call.
 struct CallFutureState<R: serde::de::DeserializeOwned> {
@@ -181,7 +263,7 @@ struct CallFuture<R: serde::de::DeserializeOwned> {
 }
 
 impl<R: serde::de::DeserializeOwned> Future for CallFuture<R> {
-    type Output = Result<R, (RejectionCode, String)>;
+    type Output = CallResult<R>;
 
     fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
         let self_ref = Pin::into_ref(self);
@@ -206,7 +288,10 @@ fn callback(state_ptr: *const InnerCell<CallFutureState<Vec<u8>>>) {
     {
         state.borrow_mut().result = Some(match reject_code() {
             RejectionCode::NoError => Ok(arg_data_raw()),
-            n => Err((n, reject_message())),
+            code => Err(CallError::Rejected {
+                code,
+                message: reject_message(),
+            }),
         });
     }
     let w = state.borrow_mut().waker.take();
@@ -230,14 +315,12 @@ fn cleanup(state_ptr: *const InnerCell<CallFutureState<Vec<u8>>>) {
     //
     // Borrowing does not trap - the rollback from the
     // previous trap ensures that the WasmCell can be borrowed again.
-    state.borrow_mut().result = Some(Err((RejectionCode::NoError, "cleanup".to_string())));
+    state.borrow_mut().result = Some(Err(CallError::CleanupInvoked));
     let w = state.borrow_mut().waker.take();
     if let Some(waker) = w {
-        // Flag that we do not want to actually wake the task - we
+        // We do not want to actually wake the task - we
         // want to drop it *without* executing it.
-        crate::futures::CLEANUP.store(true, Ordering::Relaxed);
-        waker.wake();
-        crate::futures::CLEANUP.store(false, Ordering::Relaxed);
+        crate::futures::cleanup(waker);
     }
 }
 
@@ -245,11 +328,7 @@ fn add_payment(payment: u128) {
     if payment == 0 {
         return;
     }
-    let high = (payment >> 64) as u64;
-    let low = (payment & u64::MAX as u128) as u64;
-    unsafe {
-        ic0::call_cycles_add128(high as i64, low as i64);
-    }
+    sys::call_cycles_add128(payment);
 }
 
 /// Sends a one-way message with `payment` cycles attached to it that invokes `method` with
@@ -298,27 +377,22 @@ pub fn notify_raw(
     args_raw: &[u8],
     payment: u128,
 ) -> Result<(), RejectionCode> {
-    let callee = id.as_slice();
     // We set all callbacks to -1, which is guaranteed to be invalid callback index.
     // The system will still deliver the reply, but it will trap immediately because the callback
     // is not a valid function. See
     // https://www.joachim-breitner.de/blog/789-Zero-downtime_upgrades_of_Internet_Computer_canisters#one-way-calls
     // for more context.
-    let err_code = unsafe {
-        ic0::call_new(
-            callee.as_ptr() as i32,
-            callee.len() as i32,
-            method.as_ptr() as i32,
-            method.len() as i32,
-            /* reply_fun = */ -1,
-            /* reply_env = */ -1,
-            /* reject_fun = */ -1,
-            /* reject_env = */ -1,
-        );
-        add_payment(payment);
-        ic0::call_data_append(args_raw.as_ptr() as i32, args_raw.len() as i32);
-        ic0::call_perform()
-    };
+    sys::call_new(
+        id.as_slice(),
+        method.as_bytes(),
+        /* reply_fun = */ usize::MAX,
+        /* reply_env = */ usize::MAX,
+        /* reject_fun = */ usize::MAX,
+        /* reject_env = */ usize::MAX,
+    );
+    add_payment(payment);
+    sys::call_data_append(args_raw);
+    let err_code = sys::call_perform();
     match err_code {
         0 => Ok(()),
         c => Err(RejectionCode::from(c)),
@@ -334,9 +408,7 @@ pub fn call_raw(
 ) -> impl Future<Output = CallResult<Vec<u8>>> {
     call_raw_internal(id, method, args_raw, move || {
         if payment > 0 {
-            unsafe {
-                ic0::call_cycles_add(payment as i64);
-            }
+            sys::call_cycles_add(payment);
         }
     })
 }
@@ -358,43 +430,48 @@ fn call_raw_internal(
     method: &str,
     args_raw: &[u8],
     payment_func: impl FnOnce(),
-) -> impl Future<Output = CallResult<Vec<u8>>> {
-    let callee = id.as_slice();
+) -> CallFuture<Vec<u8>> {
     let state = WasmCell::new(CallFutureState {
         result: None,
         waker: None,
     });
     let state_ptr = WasmCell::into_raw(state.clone());
-    let err_code = unsafe {
-        ic0::call_new(
-            callee.as_ptr() as i32,
-            callee.len() as i32,
-            method.as_ptr() as i32,
-            method.len() as i32,
-            callback as usize as i32,
-            state_ptr as i32,
-            callback as usize as i32,
-            state_ptr as i32,
-        );
-
-        ic0::call_data_append(args_raw.as_ptr() as i32, args_raw.len() as i32);
-        payment_func();
-        ic0::call_on_cleanup(cleanup as usize as i32, state_ptr as i32);
-        ic0::call_perform()
-    };
+    sys::call_new(
+        id.as_slice(),
+        method.as_bytes(),
+        callback as usize,
+        state_ptr as usize,
+        callback as usize,
+        state_ptr as usize,
+    );
+    sys::call_data_append(args_raw);
+    payment_func();
+    sys::call_on_cleanup(cleanup as usize, state_ptr as usize);
+    let err_code = sys::call_perform();
 
     // 0 is a special error code meaning call_simple call succeeded.
     if err_code != 0 {
         let mut state = state.borrow_mut();
-        state.result = Some(Err((
-            RejectionCode::from(err_code),
-            "Couldn't send message".to_string(),
-        )));
+        state.result = Some(Err(CallError::SyncPerformFailed {
+            code: RejectionCode::from(err_code),
+        }));
     }
     CallFuture { state }
 }
 
+fn decode_reply<R: for<'a> ArgumentDecoder<'a>>(bytes: &[u8]) -> CallResult<R> {
+    decode_args(bytes).map_err(|err| CallError::CandidDecodeFailed {
+        message: err.to_string(),
+    })
+}
+
 /// Performs an asynchronous call to another canister via ic0.
+///
+/// A reply that cannot be decoded as `R` results in [CallError::CandidDecodeFailed].
+///
+/// Calls can be made from update methods and from composite queries, declared with
+/// `#[query(composite = true)]`. A composite query can only call query methods of canisters
+/// on the same subnet, and cannot attach cycles.
 pub fn call<T: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(
     id: Principal,
     method: &str,
@@ -404,7 +481,7 @@ pub fn call<T: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(
     let fut = call_raw(id, method, &args_raw, 0);
     async {
         let bytes = fut.await?;
-        decode_args(&bytes).map_err(|err| trap(&format!("{:?}", err)))
+        decode_reply(&bytes)
     }
 }
 
@@ -419,7 +496,7 @@ pub fn call_with_payment<T: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(
     let fut = call_raw(id, method, &args_raw, cycles);
     async {
         let bytes = fut.await?;
-        decode_args(&bytes).map_err(|err| trap(&format!("{:?}", err)))
+        decode_reply(&bytes)
     }
 }
 
@@ -434,7 +511,7 @@ pub fn call_with_payment128<T: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(
     let fut = call_raw128(id, method, &args_raw, cycles);
     async {
         let bytes = fut.await?;
-        decode_args(&bytes).map_err(|err| trap(&format!("{:?}", err)))
+        decode_reply(&bytes)
     }
 }
 
@@ -453,26 +530,19 @@ pub fn result<T: for<'a> ArgumentDecoder<'a>>() -> Result<T, String> {
 
 /// Returns the rejection code for the call.
 pub fn reject_code() -> RejectionCode {
-    let code = unsafe { ic0::msg_reject_code() };
-    RejectionCode::from(code)
+    RejectionCode::from(sys::msg_reject_code())
 }
 
 /// Returns the rejection message.
 pub fn reject_message() -> String {
-    let len: u32 = unsafe { ic0::msg_reject_msg_size() as u32 };
-    let mut bytes = vec![0u8; len as usize];
-    unsafe {
-        ic0::msg_reject_msg_copy(bytes.as_mut_ptr() as i32, 0, len as i32);
-    }
+    let mut bytes = vec![0u8; sys::msg_reject_msg_size()];
+    sys::msg_reject_msg_copy(&mut bytes, 0);
     String::from_utf8_lossy(&bytes).to_string()
 }
 
 /// Rejects the current call with the message.
 pub fn reject(message: &str) {
-    let err_message = message.as_bytes();
-    unsafe {
-        ic0::msg_reject(err_message.as_ptr() as i32, err_message.len() as i32);
-    }
+    sys::msg_reject(message.as_bytes());
 }
 
 /// An io::Writer for message replies.
@@ -480,9 +550,7 @@ pub struct CallReplyWriter;
 
 impl std::io::Write for CallReplyWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        unsafe {
-            ic0::msg_reply_data_append(buf.as_ptr() as i32, buf.len() as i32);
-        }
+        sys::msg_reply_data_append(buf);
         Ok(buf.len())
     }
 
@@ -494,43 +562,33 @@ impl std::io::Write for CallReplyWriter {
 /// Replies to the current call with a candid argument.
 pub fn reply<T: ArgumentEncoder>(reply: T) {
     write_args(&mut CallReplyWriter, reply).expect("Could not encode reply.");
-    unsafe {
-        ic0::msg_reply();
-    }
+    sys::msg_reply();
 }
 
 /// Returns the amount of cycles that were transferred by the caller
 /// of the current call, and is still available in this message.
 pub fn msg_cycles_available() -> u64 {
-    unsafe { ic0::msg_cycles_available() as u64 }
+    sys::msg_cycles_available()
 }
 
 /// Returns the amount of cycles that were transferred by the caller
 /// of the current call, and is still available in this message.
 pub fn msg_cycles_available128() -> u128 {
-    let mut recv = 0u128;
-    unsafe {
-        ic0::msg_cycles_available128(&mut recv as *mut u128 as i32);
-    }
-    recv
+    sys::msg_cycles_available128()
 }
 
 /// Returns the amount of cycles that came back with the response as a refund.
 ///
 /// The refund has already been added to the canister balance automatically.
 pub fn msg_cycles_refunded() -> u64 {
-    unsafe { ic0::msg_cycles_refunded() as u64 }
+    sys::msg_cycles_refunded()
 }
 
 /// Returns the amount of cycles that came back with the response as a refund.
 ///
 /// The refund has already been added to the canister balance automatically.
 pub fn msg_cycles_refunded128() -> u128 {
-    let mut recv = 0u128;
-    unsafe {
-        ic0::msg_cycles_refunded128(&mut recv as *mut u128 as i32);
-    }
-    recv
+    sys::msg_cycles_refunded128()
 }
 
 /// Moves cycles from the call to the canister balance.
@@ -538,46 +596,34 @@ pub fn msg_cycles_refunded128() -> u128 {
 /// The actual amount moved will be returned.
 pub fn msg_cycles_accept(max_amount: u64) -> u64 {
     // TODO: should we assert the u64 input is within the range of i64?
-    unsafe { ic0::msg_cycles_accept(max_amount as i64) as u64 }
+    sys::msg_cycles_accept(max_amount)
 }
 
 /// Moves cycles from the call to the canister balance.
 ///
 /// The actual amount moved will be returned.
 pub fn msg_cycles_accept128(max_amount: u128) -> u128 {
-    let high = (max_amount >> 64) as u64;
-    let low = (max_amount & u64::MAX as u128) as u64;
-    let mut recv = 0u128;
-    unsafe {
-        ic0::msg_cycles_accept128(high as i64, low as i64, &mut recv as *mut u128 as i32);
-    }
-    recv
+    sys::msg_cycles_accept128(max_amount)
 }
 
 /// Returns the argument data as bytes.
 pub fn arg_data_raw() -> Vec<u8> {
-    unsafe {
-        let len: usize = ic0::msg_arg_data_size() as usize;
-        let mut bytes = Vec::with_capacity(len);
-        ic0::msg_arg_data_copy(bytes.as_mut_ptr() as i32, 0, len as i32);
-        bytes.set_len(len);
-        bytes
-    }
+    let mut bytes = vec![0u8; sys::msg_arg_data_size()];
+    sys::msg_arg_data_copy(&mut bytes, 0);
+    bytes
 }
 
 /// Get the len of the raw-argument-data-bytes.
 pub fn arg_data_raw_size() -> usize {
-    unsafe { ic0::msg_arg_data_size() as usize }
+    sys::msg_arg_data_size()
 }
 
 /// Replies with the bytes passed
 pub fn reply_raw(buf: &[u8]) {
-    unsafe {
-        if !buf.is_empty() {
-            ic0::msg_reply_data_append(buf.as_ptr() as i32, buf.len() as i32)
-        };
-        ic0::msg_reply();
+    if !buf.is_empty() {
+        sys::msg_reply_data_append(buf);
     }
+    sys::msg_reply();
 }
 
 /// Returns the argument data in the current call. Traps if the data cannot be
@@ -593,18 +639,13 @@ pub fn arg_data<R: for<'a> ArgumentDecoder<'a>>() -> R {
 
 /// Accepts the ingress message.
 pub fn accept_message() {
-    unsafe {
-        ic0::accept_message();
-    }
+    sys::accept_message();
 }
 
 /// Returns the name of current canister method.
 pub fn method_name() -> String {
-    let len: u32 = unsafe { ic0::msg_method_name_size() as u32 };
-    let mut bytes = vec![0u8; len as usize];
-    unsafe {
-        ic0::msg_method_name_copy(bytes.as_mut_ptr() as i32, 0, len as i32);
-    }
+    let mut bytes = vec![0u8; sys::msg_method_name_size()];
+    sys::msg_method_name_copy(&mut bytes, 0);
     String::from_utf8_lossy(&bytes).to_string()
 }
 
@@ -613,7 +654,7 @@ pub fn method_name() -> String {
 /// Supported counter type:
 /// 0 : instruction counter. The number of WebAssembly instructions the system has determined that the canister has executed.
 pub fn performance_counter(counter_type: u32) -> u64 {
-    unsafe { ic0::performance_counter(counter_type as i32) as u64 }
+    sys::performance_counter(counter_type)
 }
 
 /// Pretends to have the Candid type `T`, but unconditionally errors
@@ -0,0 +1,197 @@
+use super::{
+    add_payment, call_raw_internal, decode_reply, notify_raw, retry, CallError, CallFuture,
+    CallResult, RejectionCode, RetryPolicy,
+};
+use crate::export::Principal;
+use candid::encode_args;
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use std::borrow::Cow;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[cfg(test)]
+mod tests;
+
+/// Limits applied when decoding the reply of a [Call].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecoderConfig {
+    max_reply_bytes: Option<usize>,
+}
+
+impl DecoderConfig {
+    /// No limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuses to decode replies longer than `bytes`, failing with
+    /// [CallError::CandidDecodeFailed] instead.
+    pub fn with_max_reply_bytes(mut self, bytes: usize) -> Self {
+        self.max_reply_bytes = Some(bytes);
+        self
+    }
+}
+
+/// An inter-canister call, configured step by step.
+///
+/// The call is sent when the `Call` is first polled, and resolves to the reply decoded
+/// as `R`. Use [oneway](Call::oneway) to send it without waiting for a reply.
+///
+/// ```rust,no_run
+/// use ic_cdk::api::call::{Call, CallResult};
+/// # async fn example(ledger: ic_cdk::export::Principal) -> CallResult<()> {
+/// let (balance,): (u64,) = Call::new(ledger, "balance_of")
+///     .with_args((ic_cdk::caller(),))
+///     .with_cycles(1_000_000)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// As with [call](super::call), a reply that cannot be decoded as `R`, or that exceeds the
+/// limits of the [DecoderConfig], results in [CallError::CandidDecodeFailed].
+pub struct Call<'a, R = ()> {
+    id: Principal,
+    method: &'a str,
+    args: Option<Cow<'a, [u8]>>,
+    cycles: u128,
+    decoder_config: DecoderConfig,
+    retry: Option<RetryPolicy>,
+    attempts: u32,
+    sent: Option<CallFuture<Vec<u8>>>,
+    // The rounds left to wait before the next attempt, and the call waiting for the current one.
+    rounds: u32,
+    round: Option<CallFuture<Vec<u8>>>,
+    // `fn() -> R` keeps the builder `Unpin` whatever `R` is.
+    _marker: PhantomData<fn() -> R>,
+}
+
+impl<'a, R> Call<'a, R> {
+    /// Prepares a call to `method` of the canister `id`, with no arguments and no cycles.
+    pub fn new(id: Principal, method: &'a str) -> Self {
+        Call {
+            id,
+            method,
+            args: None,
+            cycles: 0,
+            decoder_config: DecoderConfig::default(),
+            retry: None,
+            attempts: 0,
+            sent: None,
+            rounds: 0,
+            round: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the arguments of the call, encoded in Candid.
+    pub fn with_args<T: ArgumentEncoder>(mut self, args: T) -> Self {
+        let args_raw = encode_args(args).expect("Failed to encode arguments.");
+        self.args = Some(Cow::Owned(args_raw));
+        self
+    }
+
+    /// Sets the arguments of the call as raw bytes, skipping Candid serialization.
+    pub fn with_raw_args(mut self, args_raw: &'a [u8]) -> Self {
+        self.args = Some(Cow::Borrowed(args_raw));
+        self
+    }
+
+    /// Attaches `cycles` to the call.
+    pub fn with_cycles(mut self, cycles: u128) -> Self {
+        self.cycles = cycles;
+        self
+    }
+
+    /// Sets the limits applied when decoding the reply.
+    pub fn with_decoder_config(mut self, config: DecoderConfig) -> Self {
+        self.decoder_config = config;
+        self
+    }
+
+    /// Marks the call as idempotent, and retries it according to `policy` if it fails.
+    ///
+    /// A call rejected by the system may have been executed by the callee, so only use this
+    /// for calls that can safely run more than once.
+    pub fn retry_idempotent(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Sends the call as a one-way message, ignoring the reply.
+    ///
+    /// See [notify_raw](super::notify_raw) for the caveats of one-way calls.
+    pub fn oneway(self) -> Result<(), RejectionCode> {
+        notify_raw(self.id, self.method, &self.args_raw(), self.cycles)
+    }
+
+    fn args_raw(&self) -> Cow<'_, [u8]> {
+        match &self.args {
+            Some(args) => Cow::Borrowed(args.as_ref()),
+            None => Cow::Owned(encode_args(()).expect("Failed to encode arguments.")),
+        }
+    }
+
+    fn send(&self) -> CallFuture<Vec<u8>> {
+        let cycles = self.cycles;
+        call_raw_internal(self.id, self.method, &self.args_raw(), move || {
+            add_payment(cycles)
+        })
+    }
+}
+
+impl<'a, R: for<'de> ArgumentDecoder<'de>> Future for Call<'a, R> {
+    type Output = CallResult<R>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let bytes = loop {
+            if let Some(round) = this.round.as_mut() {
+                match Pin::new(round).poll(context) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(result) => {
+                        if result.is_err() {
+                            this.rounds = 0;
+                        }
+                        this.round = None;
+                    }
+                }
+            }
+            if this.rounds > 0 {
+                this.rounds -= 1;
+                this.round = Some(retry::wait_round());
+                continue;
+            }
+            if this.sent.is_none() {
+                this.sent = Some(this.send());
+                this.attempts += 1;
+            }
+            let sent = this.sent.as_mut().unwrap();
+            match Pin::new(sent).poll(context) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Ok(bytes)) => break bytes,
+                Poll::Ready(Err(err)) => match &this.retry {
+                    Some(policy) if policy.should_retry(&err, this.attempts) => {
+                        this.sent = None;
+                        this.rounds = policy.rounds(this.attempts);
+                    }
+                    _ => return Poll::Ready(Err(err)),
+                },
+            }
+        };
+        if let Some(max) = this.decoder_config.max_reply_bytes {
+            if bytes.len() > max {
+                return Poll::Ready(Err(CallError::CandidDecodeFailed {
+                    message: format!(
+                        "Reply of {} bytes exceeds the limit of {} bytes.",
+                        bytes.len(),
+                        max
+                    ),
+                }));
+            }
+        }
+        Poll::Ready(decode_reply(&bytes))
+    }
+}
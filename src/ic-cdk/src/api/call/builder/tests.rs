@@ -0,0 +1,169 @@
+use super::*;
+use crate::api::backend::{poll_once, set_system_api, MockSystemApi, OutgoingCall};
+use std::rc::Rc;
+
+fn install_mock() -> Rc<MockSystemApi> {
+    let mock = Rc::new(MockSystemApi::new());
+    set_system_api(mock.clone());
+    mock
+}
+
+fn callee() -> Principal {
+    Principal::from_slice(&[9, 9])
+}
+
+#[test]
+fn call_is_sent_when_polled() {
+    let mock = install_mock();
+    let call = Call::<()>::new(callee(), "update")
+        .with_raw_args(b"raw")
+        .with_cycles(5);
+    assert!(mock.calls().is_empty());
+
+    mock.set_balance(100);
+    assert_eq!(poll_once(call), None);
+    assert_eq!(
+        mock.calls(),
+        vec![OutgoingCall {
+            callee: callee(),
+            method: "update".to_string(),
+            args: b"raw".to_vec(),
+            cycles: 5,
+            oneway: false,
+        }]
+    );
+}
+
+#[test]
+fn arguments_default_to_empty_tuple() {
+    let mock = install_mock();
+    Call::<()>::new(callee(), "notify").oneway().unwrap();
+    Call::<()>::new(callee(), "notify")
+        .with_args((1u8, "two"))
+        .oneway()
+        .unwrap();
+
+    let calls = mock.calls();
+    assert_eq!(calls[0].args, encode_args(()).unwrap());
+    assert_eq!(calls[1].args, encode_args((1u8, "two")).unwrap());
+    assert!(calls.iter().all(|call| call.oneway));
+}
+
+#[test]
+fn perform_failure_resolves_immediately() {
+    let mock = install_mock();
+    mock.set_call_perform_result(RejectionCode::SysTransient as i32);
+
+    let result = poll_once(Call::<()>::new(callee(), "update"));
+    assert_eq!(
+        result,
+        Some(Err(CallError::SyncPerformFailed {
+            code: RejectionCode::SysTransient
+        }))
+    );
+    assert_eq!(
+        Call::<()>::new(callee(), "update").oneway(),
+        Err(RejectionCode::SysTransient)
+    );
+}
+
+#[test]
+fn idempotent_call_is_retried_until_sent() {
+    let mock = install_mock();
+    mock.set_call_perform_result(RejectionCode::SysTransient as i32);
+    let failures = Rc::new(std::cell::Cell::new(0));
+    let policy = RetryPolicy::new(3).with_predicate({
+        let (mock, failures) = (mock.clone(), failures.clone());
+        move |code| {
+            failures.set(failures.get() + 1);
+            if failures.get() == 2 {
+                mock.set_call_perform_result(0);
+            }
+            code == RejectionCode::SysTransient
+        }
+    });
+
+    let call = Call::<()>::new(callee(), "update").retry_idempotent(policy);
+    assert_eq!(poll_once(call), None);
+    assert_eq!(failures.get(), 2);
+    assert_eq!(mock.calls().len(), 1);
+}
+
+#[test]
+fn retries_stop_after_max_attempts_or_on_other_codes() {
+    let mock = install_mock();
+    mock.set_call_perform_result(RejectionCode::SysTransient as i32);
+    let attempts = Rc::new(std::cell::Cell::new(0));
+    let counting = |attempts: &Rc<std::cell::Cell<u32>>, retried| {
+        let attempts = attempts.clone();
+        RetryPolicy::new(3).with_predicate(move |code| {
+            attempts.set(attempts.get() + 1);
+            code == retried
+        })
+    };
+    let expected = Some(Err(CallError::SyncPerformFailed {
+        code: RejectionCode::SysTransient,
+    }));
+
+    let call = Call::<()>::new(callee(), "update")
+        .retry_idempotent(counting(&attempts, RejectionCode::SysTransient));
+    assert_eq!(poll_once(call), expected);
+    assert_eq!(attempts.get(), 2);
+
+    attempts.set(0);
+    let call = Call::<()>::new(callee(), "update")
+        .retry_idempotent(counting(&attempts, RejectionCode::CanisterError));
+    assert_eq!(poll_once(call), expected);
+    assert_eq!(attempts.get(), 1);
+}
+
+#[cfg(feature = "retry-backoff")]
+#[test]
+fn backoff_waits_rounds_with_self_calls() {
+    use super::super::Backoff;
+
+    let mock = install_mock();
+    mock.set_id(Principal::from_slice(&[1]));
+    mock.set_call_perform_result(RejectionCode::SysTransient as i32);
+    let policy = RetryPolicy::new(3)
+        .with_backoff(Backoff::Rounds { initial: 2, max: 3 })
+        .with_predicate({
+            let mock = mock.clone();
+            move |code| {
+                mock.set_call_perform_result(0);
+                code == RejectionCode::SysTransient
+            }
+        });
+    assert_eq!(
+        (1..=4)
+            .map(|attempts| policy.rounds(attempts))
+            .collect::<Vec<_>>(),
+        [2, 3, 3, 3]
+    );
+
+    let call = Call::<()>::new(callee(), "update").retry_idempotent(policy);
+    assert_eq!(poll_once(call), None);
+    let calls = mock.calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].callee, Principal::from_slice(&[1]));
+    assert_eq!(calls[0].method, "<ic-cdk internal> round");
+}
+
+#[cfg(feature = "retry-backoff")]
+#[test]
+fn backoff_ends_when_a_round_fails() {
+    use super::super::Backoff;
+
+    let mock = install_mock();
+    mock.set_id(Principal::from_slice(&[1]));
+    mock.set_call_perform_result(RejectionCode::SysTransient as i32);
+    let policy = RetryPolicy::new(2).with_backoff(Backoff::Rounds { initial: 5, max: 5 });
+
+    let call = Call::<()>::new(callee(), "update").retry_idempotent(policy);
+    assert_eq!(
+        poll_once(call),
+        Some(Err(CallError::SyncPerformFailed {
+            code: RejectionCode::SysTransient
+        }))
+    );
+}
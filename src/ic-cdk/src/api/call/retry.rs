@@ -0,0 +1,133 @@
+use super::{call_raw_internal, CallError, CallFuture, RejectionCode};
+use std::fmt;
+use std::rc::Rc;
+
+/// How long to wait before retrying a failed call.
+///
+/// The variants depend on the enabled features, so matches must have a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backoff {
+    /// Retry as soon as the failure is known.
+    ///
+    /// A call rejected by the system is only known to have failed once its reject arrives,
+    /// at least a round after it was sent, but a call that could not be sent at all is
+    /// retried in the same message, where it is likely to fail again.
+    None,
+    /// Wait `initial` rounds before the first retry, doubling the number of rounds before
+    /// each further retry up to `max`.
+    ///
+    /// Each round is waited for with a call the canister makes to itself, so that the call
+    /// context of the retrying method stays open and it still replies to its own caller.
+    /// These calls are charged like any other call, and the wait ends early if one of them
+    /// fails.
+    ///
+    /// Requires the `retry-backoff` feature, with which the canister exports the method it
+    /// calls on itself.
+    #[cfg(feature = "retry-backoff")]
+    Rounds {
+        /// The rounds to wait before the first retry.
+        initial: u32,
+        /// The most rounds to wait between two attempts.
+        max: u32,
+    },
+}
+
+/// When and how often to retry an idempotent [Call](super::Call) that failed.
+///
+/// By default, calls that fail with [RejectionCode::SysTransient], such as when the queue
+/// to the callee is full, are retried right away.
+///
+/// ```rust,no_run
+/// use ic_cdk::api::call::{Call, RejectionCode, RetryPolicy};
+/// # async fn example(ledger: ic_cdk::export::Principal) {
+/// let policy = RetryPolicy::new(5).with_predicate(|code| {
+///     matches!(code, RejectionCode::SysTransient | RejectionCode::CanisterError)
+/// });
+/// let result: ic_cdk::api::call::CallResult<(u64,)> =
+///     Call::new(ledger, "total_supply").retry_idempotent(policy).await;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Backoff,
+    predicate: Rc<dyn Fn(RejectionCode) -> bool>,
+}
+
+impl RetryPolicy {
+    /// Makes at most `max_attempts` attempts in total, including the first one.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts,
+            backoff: Backoff::None,
+            predicate: Rc::new(|code| code == RejectionCode::SysTransient),
+        }
+    }
+
+    /// Sets how long to wait between attempts.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the rejection codes to retry.
+    ///
+    /// Only calls that could not be sent or were rejected are retried; a reply that cannot
+    /// be decoded never is.
+    pub fn with_predicate(mut self, predicate: impl Fn(RejectionCode) -> bool + 'static) -> Self {
+        self.predicate = Rc::new(predicate);
+        self
+    }
+
+    /// Whether to make another attempt after `attempts` attempts, the last of which failed
+    /// with `err`.
+    pub(super) fn should_retry(&self, err: &CallError, attempts: u32) -> bool {
+        let code = match err {
+            CallError::SyncPerformFailed { code } | CallError::Rejected { code, .. } => *code,
+            CallError::CandidDecodeFailed { .. } | CallError::CleanupInvoked => return false,
+        };
+        attempts < self.max_attempts && (self.predicate)(code)
+    }
+
+    /// The rounds to wait before the attempt following `attempts` failed attempts.
+    #[cfg_attr(not(feature = "retry-backoff"), allow(unused_variables))]
+    pub(super) fn rounds(&self, attempts: u32) -> u32 {
+        match self.backoff {
+            Backoff::None => 0,
+            #[cfg(feature = "retry-backoff")]
+            Backoff::Rounds { initial, max } => {
+                let factor = 1u32.checked_shl(attempts - 1).unwrap_or(u32::MAX);
+                initial.checked_mul(factor).unwrap_or(max).min(max)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff", &self.backoff)
+            .finish()
+    }
+}
+
+// The method the canister calls on itself to wait for a round.
+const ROUND_METHOD: &str = "<ic-cdk internal> round";
+
+/// Calls the canister itself, completing in a later round.
+pub(super) fn wait_round() -> CallFuture<Vec<u8>> {
+    call_raw_internal(crate::api::id(), ROUND_METHOD, &[], || {})
+}
+
+// Replies right away: the call itself is what takes a round.
+#[cfg(feature = "retry-backoff")]
+#[cfg_attr(
+    target_arch = "wasm32",
+    export_name = "canister_query <ic-cdk internal> round"
+)]
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+extern "C" fn round() {
+    super::reply_raw(&[]);
+}
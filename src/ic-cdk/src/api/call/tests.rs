@@ -0,0 +1,46 @@
+use super::*;
+
+#[test]
+fn undecodable_reply_is_an_error() {
+    let result: CallResult<(u64,)> = decode_reply(b"not candid");
+    match result {
+        Err(CallError::CandidDecodeFailed { .. }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn converts_to_legacy_tuple() {
+    let cases = vec![
+        (
+            CallError::SyncPerformFailed {
+                code: RejectionCode::SysTransient,
+            },
+            (RejectionCode::SysTransient, "Couldn't send message"),
+        ),
+        (
+            CallError::Rejected {
+                code: RejectionCode::CanisterReject,
+                message: "no".to_string(),
+            },
+            (RejectionCode::CanisterReject, "no"),
+        ),
+        (
+            CallError::CandidDecodeFailed {
+                message: "bad reply".to_string(),
+            },
+            (RejectionCode::CanisterError, "bad reply"),
+        ),
+        (
+            CallError::CleanupInvoked,
+            (RejectionCode::NoError, "cleanup"),
+        ),
+    ];
+    for (err, (code, message)) in cases {
+        assert_eq!(err.reject_code(), code);
+        assert_eq!(
+            <(RejectionCode, String)>::from(err),
+            (code, message.to_string())
+        );
+    }
+}
@@ -68,12 +68,15 @@ I: from canister_init or canister_post_upgrade
 G: from canister_pre_upgrade
 U: from canister_update …
 Q: from canister_query …
+CQ: from canister_composite_query …
 Ry: from a reply callback
 Rt: from a reject callback
 C: from a cleanup callback
+CRy, CRt, CC: from a reply, reject or cleanup callback of a composite query
 s: the (start) module initialization function
 F: from canister_inspect_message
 H: from canister_heartbeat
+T: from canister_global_timer
 * = I G U Q Ry Rt C F H (NB: Not (start))
 */
 ic0_module! {
@@ -85,9 +88,9 @@ ic0_module! {
     ic0.msg_reject_msg_size : () -> i32;                                        // Rt
     ic0.msg_reject_msg_copy : (dst : i32, offset : i32, size : i32) -> ();      // Rt
 
-    ic0.msg_reply_data_append : (src : i32, size : i32) -> ();                  // U Q Ry Rt
-    ic0.msg_reply : () -> ();                                                   // U Q Ry Rt
-    ic0.msg_reject : (src : i32, size : i32) -> ();                             // U Q Ry Rt
+    ic0.msg_reply_data_append : (src : i32, size : i32) -> ();                  // U Q Ry Rt CQ CRy CRt
+    ic0.msg_reply : () -> ();                                                   // U Q Ry Rt CQ CRy CRt
+    ic0.msg_reject : (src : i32, size : i32) -> ();                             // U Q Ry Rt CQ CRy CRt
 
     ic0.msg_cycles_available : () -> i64;                                       // U Rt Ry
     ic0.msg_cycles_available128 : (dst : i32) -> ();                            // U Rt Ry
@@ -107,7 +110,7 @@ ic0_module! {
     ic0.msg_method_name_copy : (dst : i32, offset : i32, size : i32) -> ();     // F
     ic0.accept_message : () -> ();                                              // F
 
-    ic0.call_new :                                                              // U Ry Rt H
+    ic0.call_new :                                                              // U Ry Rt H T CQ CRy CRt
       ( callee_src  : i32,
         callee_size : i32,
         name_src : i32,
@@ -117,11 +120,11 @@ ic0_module! {
         reject_fun : i32,
         reject_env : i32
       ) -> ();
-    ic0.call_on_cleanup : (fun : i32, env : i32) -> ();                         // U Ry Rt H
-    ic0.call_data_append : (src : i32, size : i32) -> ();                       // U Ry Rt H
-    ic0.call_cycles_add : (amount : i64) -> ();                                 // U Ry Rt H
-    ic0.call_cycles_add128 : (amount_high : i64, amount_low: i64) -> ();        // U Ry Rt H
-    ic0.call_perform : () -> ( err_code : i32 );                                // U Ry Rt H
+    ic0.call_on_cleanup : (fun : i32, env : i32) -> ();                         // U Ry Rt H T CQ CRy CRt
+    ic0.call_data_append : (src : i32, size : i32) -> ();                       // U Ry Rt H T CQ CRy CRt
+    ic0.call_cycles_add : (amount : i64) -> ();                                 // U Ry Rt H T
+    ic0.call_cycles_add128 : (amount_high : i64, amount_low: i64) -> ();        // U Ry Rt H T
+    ic0.call_perform : () -> ( err_code : i32 );                                // U Ry Rt H T CQ CRy CRt
 
     ic0.stable_size : () -> (page_count : i32);                                 // *
     ic0.stable_grow : (new_pages : i32) -> (old_page_count : i32);              // *
@@ -138,7 +141,9 @@ ic0_module! {
     ic0.data_certificate_copy : (dst: i32, offset: i32, size: i32) -> ();       // *
 
     ic0.time : () -> (timestamp : i64);                                         // *
+    ic0.global_timer_set : (timestamp : i64) -> i64;                            // I G U Ry Rt C T
     ic0.performance_counter : (counter_type : i32) -> (counter : i64);          // * s
+    ic0.is_controller : (src : i32, size : i32) -> (result : i32);              // * s
 
     ic0.debug_print : (src : i32, size : i32) -> ();                            // * s
     ic0.trap : (src : i32, size : i32) -> ();                                   // * s
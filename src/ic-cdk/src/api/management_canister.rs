@@ -0,0 +1,108 @@
+//! Typed interface to the [management canister](https://smartcontracts.org/docs/interface-spec/index.html#ic-management-canister).
+//!
+//! The management canister (`aaaaa-aa`) is not a real canister but a facade for
+//! system-level operations such as creating, installing and controlling other canisters.
+//! Every function in this module performs an inter-canister call, so it can only be used
+//! where [call](super::call::call) is allowed.
+use crate::api::call::{call, call_with_payment128, CallResult};
+use crate::export::Principal;
+
+pub mod ecdsa;
+pub mod http_request;
+mod types;
+pub use types::*;
+
+/// Register a new canister and get its canister id.
+///
+/// `cycles` are attached to the call and credited to the new canister, minus the
+/// canister creation fee.
+///
+/// See [IC method `create_canister`](https://smartcontracts.org/docs/interface-spec/index.html#ic-create_canister).
+pub async fn create_canister(
+    arg: CreateCanisterArgument,
+    cycles: u128,
+) -> CallResult<CanisterIdRecord> {
+    let (record,) = call_with_payment128(
+        Principal::management_canister(),
+        "create_canister",
+        (arg,),
+        cycles,
+    )
+    .await?;
+    Ok(record)
+}
+
+/// Update the settings of a canister.
+///
+/// See [IC method `update_settings`](https://smartcontracts.org/docs/interface-spec/index.html#ic-update_settings).
+pub async fn update_settings(arg: UpdateSettingsArgument) -> CallResult<()> {
+    call(Principal::management_canister(), "update_settings", (arg,)).await
+}
+
+/// Install code into a canister.
+///
+/// See [IC method `install_code`](https://smartcontracts.org/docs/interface-spec/index.html#ic-install_code).
+pub async fn install_code(arg: InstallCodeArgument) -> CallResult<()> {
+    call(Principal::management_canister(), "install_code", (arg,)).await
+}
+
+/// Remove a canister's code and state, making the canister empty again.
+///
+/// See [IC method `uninstall_code`](https://smartcontracts.org/docs/interface-spec/index.html#ic-uninstall_code).
+pub async fn uninstall_code(arg: CanisterIdRecord) -> CallResult<()> {
+    call(Principal::management_canister(), "uninstall_code", (arg,)).await
+}
+
+/// Start a canister.
+///
+/// See [IC method `start_canister`](https://smartcontracts.org/docs/interface-spec/index.html#ic-start_canister).
+pub async fn start_canister(arg: CanisterIdRecord) -> CallResult<()> {
+    call(Principal::management_canister(), "start_canister", (arg,)).await
+}
+
+/// Stop a canister.
+///
+/// See [IC method `stop_canister`](https://smartcontracts.org/docs/interface-spec/index.html#ic-stop_canister).
+pub async fn stop_canister(arg: CanisterIdRecord) -> CallResult<()> {
+    call(Principal::management_canister(), "stop_canister", (arg,)).await
+}
+
+/// Get status information about the canister.
+///
+/// Only controllers of the canister can call this method.
+///
+/// See [IC method `canister_status`](https://smartcontracts.org/docs/interface-spec/index.html#ic-canister_status).
+pub async fn canister_status(arg: CanisterIdRecord) -> CallResult<CanisterStatusResponse> {
+    let (status,) = call(Principal::management_canister(), "canister_status", (arg,)).await?;
+    Ok(status)
+}
+
+/// Delete a canister from the IC.
+///
+/// The canister must be stopped first.
+///
+/// See [IC method `delete_canister`](https://smartcontracts.org/docs/interface-spec/index.html#ic-delete_canister).
+pub async fn delete_canister(arg: CanisterIdRecord) -> CallResult<()> {
+    call(Principal::management_canister(), "delete_canister", (arg,)).await
+}
+
+/// Deposit the attached `cycles` into the specified canister.
+///
+/// See [IC method `deposit_cycles`](https://smartcontracts.org/docs/interface-spec/index.html#ic-deposit_cycles).
+pub async fn deposit_cycles(arg: CanisterIdRecord, cycles: u128) -> CallResult<()> {
+    call_with_payment128(
+        Principal::management_canister(),
+        "deposit_cycles",
+        (arg,),
+        cycles,
+    )
+    .await
+}
+
+/// Get 32 pseudo-random bytes.
+///
+/// See [IC method `raw_rand`](https://smartcontracts.org/docs/interface-spec/index.html#ic-raw_rand).
+pub async fn raw_rand() -> CallResult<Vec<u8>> {
+    let (bytes,) = call(Principal::management_canister(), "raw_rand", ()).await?;
+    Ok(bytes)
+}
@@ -0,0 +1,131 @@
+//! Threshold ECDSA through the management canister.
+//!
+//! Every canister controls a tree of secp256k1 keys, rooted at a key derived from the
+//! canister id. [ecdsa_public_key] fetches the public key at a derivation path, and
+//! [sign_with_ecdsa] signs a message hash with the corresponding private key, which is
+//! never revealed.
+//!
+//! With the `ecdsa-derivation` feature, [derive_public_key] computes the public keys of
+//! a canister locally from its root key, saving a call per key.
+use crate::api::call::{call, call_with_payment128};
+use crate::export::Principal;
+
+mod types;
+pub use types::*;
+
+#[cfg(test)]
+mod tests;
+
+/// Return a SEC1 encoded ECDSA public key for the given canister using the given derivation path.
+///
+/// See [IC method `ecdsa_public_key`](https://smartcontracts.org/docs/interface-spec/index.html#ic-ecdsa_public_key).
+pub async fn ecdsa_public_key(
+    arg: EcdsaPublicKeyArgument,
+) -> Result<EcdsaPublicKeyResponse, EcdsaError> {
+    let (response,) = call(Principal::management_canister(), "ecdsa_public_key", (arg,)).await?;
+    Ok(response)
+}
+
+/// Return a new ECDSA signature of the given `message_hash` that can be separately verified
+/// against a derived ECDSA public key.
+///
+/// The fee returned by [sign_with_ecdsa_fee] is attached to the call. The message hash is
+/// checked to be 32 bytes long before any cycles are spent.
+///
+/// See [IC method `sign_with_ecdsa`](https://smartcontracts.org/docs/interface-spec/index.html#ic-sign_with_ecdsa).
+pub async fn sign_with_ecdsa(
+    arg: SignWithEcdsaArgument,
+) -> Result<SignWithEcdsaResponse, EcdsaError> {
+    if arg.message_hash.len() != 32 {
+        return Err(EcdsaError::InvalidMessageHash {
+            length: arg.message_hash.len(),
+        });
+    }
+    let cycles = sign_with_ecdsa_fee(&arg.key_id);
+    let (response,) = call_with_payment128(
+        Principal::management_canister(),
+        "sign_with_ecdsa",
+        (arg,),
+        cycles,
+    )
+    .await?;
+    Ok(response)
+}
+
+/// The cycles to attach to a [sign_with_ecdsa] call using the given key.
+///
+/// Signing with the production key `key_1` costs more than with the test keys.
+/// Cycles that are not charged are refunded.
+pub fn sign_with_ecdsa_fee(key_id: &EcdsaKeyId) -> u128 {
+    match key_id.name.as_str() {
+        "key_1" => 26_153_846_153,
+        _ => 10_000_000_000,
+    }
+}
+
+/// Derive the public key at `derivation_path` below `parent` locally.
+///
+/// `parent` is usually the root key of a canister, returned by [ecdsa_public_key] with an
+/// empty derivation path. Deriving from it gives the same result as calling
+/// [ecdsa_public_key] with the full path.
+#[cfg(feature = "ecdsa-derivation")]
+pub fn derive_public_key(
+    parent: &EcdsaPublicKeyResponse,
+    derivation_path: &[Vec<u8>],
+) -> Result<EcdsaPublicKeyResponse, EcdsaError> {
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+    let mut chain_code: [u8; 32] = std::convert::TryFrom::try_from(&parent.chain_code[..])
+        .map_err(|_| EcdsaError::InvalidPublicKey)?;
+    let mut public_key = k256::PublicKey::from_sec1_bytes(&parent.public_key)
+        .map_err(|_| EcdsaError::InvalidPublicKey)?;
+    for index in derivation_path {
+        let (next_key, next_chain_code) = derivation::ckd_pub(&public_key, &chain_code, index);
+        public_key = next_key;
+        chain_code = next_chain_code;
+    }
+    Ok(EcdsaPublicKeyResponse {
+        public_key: public_key.to_encoded_point(true).as_bytes().to_vec(),
+        chain_code: chain_code.to_vec(),
+    })
+}
+
+// Public child key derivation as done by the IC: BIP32 with arbitrary byte strings as
+// indices, retrying as in SLIP-10 if the derived offset is out of range.
+#[cfg(feature = "ecdsa-derivation")]
+mod derivation {
+    use hmac::{Hmac, Mac};
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+    use k256::elliptic_curve::PrimeField;
+    use k256::{FieldBytes, ProjectivePoint, PublicKey, Scalar};
+    use sha2::Sha512;
+
+    pub(super) fn ckd_pub(
+        parent: &PublicKey,
+        chain_code: &[u8; 32],
+        index: &[u8],
+    ) -> (PublicKey, [u8; 32]) {
+        let mut input = parent.to_encoded_point(true).as_bytes().to_vec();
+        input.extend_from_slice(index);
+        loop {
+            let mut hmac = Hmac::<Sha512>::new_from_slice(chain_code)
+                .expect("HMAC accepts keys of any length");
+            hmac.update(&input);
+            let output = hmac.finalize().into_bytes();
+            let mut next_chain_code = [0u8; 32];
+            next_chain_code.copy_from_slice(&output[32..]);
+
+            let offset: Option<Scalar> =
+                Scalar::from_repr(FieldBytes::clone_from_slice(&output[..32])).into();
+            if let Some(offset) = offset {
+                let point = parent.to_projective() + ProjectivePoint::GENERATOR * offset;
+                if let Ok(key) = PublicKey::from_affine(point.to_affine()) {
+                    return (key, next_chain_code);
+                }
+            }
+            input = vec![0x01];
+            input.extend_from_slice(&next_chain_code);
+            input.extend_from_slice(index);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use super::*;
+use crate::api::backend::{poll_once, set_system_api, MockSystemApi};
+use std::rc::Rc;
+
+fn key_id(name: &str) -> EcdsaKeyId {
+    EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: name.to_string(),
+    }
+}
+
+#[test]
+fn fee_depends_on_key() {
+    assert_eq!(sign_with_ecdsa_fee(&key_id("key_1")), 26_153_846_153);
+    assert_eq!(sign_with_ecdsa_fee(&key_id("test_key_1")), 10_000_000_000);
+}
+
+#[test]
+fn invalid_message_hash_is_rejected_before_calling() {
+    let mock = Rc::new(MockSystemApi::new());
+    set_system_api(mock.clone());
+    let arg = SignWithEcdsaArgument {
+        message_hash: vec![0; 20],
+        derivation_path: vec![],
+        key_id: key_id("key_1"),
+    };
+    let result = poll_once(sign_with_ecdsa(arg));
+    assert_eq!(
+        result,
+        Some(Err(EcdsaError::InvalidMessageHash { length: 20 }))
+    );
+    assert!(mock.calls().is_empty());
+}
+
+#[cfg(feature = "ecdsa-derivation")]
+mod derivation {
+    use super::*;
+
+    fn root() -> EcdsaPublicKeyResponse {
+        // The generator point of secp256k1.
+        let mut public_key = vec![0x02];
+        public_key.extend_from_slice(&[
+            0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87,
+            0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b,
+            0x16, 0xf8, 0x17, 0x98,
+        ]);
+        EcdsaPublicKeyResponse {
+            public_key,
+            chain_code: vec![7; 32],
+        }
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // With 4-byte indices below 2^31, the derivation is that of BIP32, whose test vector 1
+    // gives the keys below: m/0H, m/0H/1, m/0H/1/2H/2 and m/0H/1/2H/2/1000000000.
+    #[test]
+    fn matches_bip32_test_vector() {
+        let key = |public_key: &str, chain_code: &str| EcdsaPublicKeyResponse {
+            public_key: hex(public_key),
+            chain_code: hex(chain_code),
+        };
+        let m_0h = key(
+            "035a784662a4a20a65bf6aab9ae98a6c068a81c52e4b032c0fb5400c706cfccc56",
+            "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+        );
+        let m_0h_1 = key(
+            "03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c",
+            "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+        );
+        assert_eq!(
+            derive_public_key(&m_0h, &[1u32.to_be_bytes().to_vec()]),
+            Ok(m_0h_1)
+        );
+
+        let m_0h_1_2h = key(
+            "0357bfe1e341d01c69fe5654309956cbea516822fba8a601743a012a7896ee8dc2",
+            "04466b9cc8e161e966409ca52986c584f07e9dc81f735db683c3ff6ec7b1503f",
+        );
+        let m_0h_1_2h_2_1000000000 = key(
+            "022a471424da5e657499d1ff51cb43c47481a03b1e77f951fe64cec9f5a48f7011",
+            "c783e67b921d2beb8f6b389cc646d7263b4145701dadd2161548a8b078e65e9e",
+        );
+        let path = [
+            2u32.to_be_bytes().to_vec(),
+            1_000_000_000u32.to_be_bytes().to_vec(),
+        ];
+        assert_eq!(
+            derive_public_key(&m_0h_1_2h, &path),
+            Ok(m_0h_1_2h_2_1000000000)
+        );
+    }
+
+    #[test]
+    fn empty_path_returns_parent() {
+        assert_eq!(derive_public_key(&root(), &[]), Ok(root()));
+    }
+
+    #[test]
+    fn derivation_is_incremental() {
+        let path = vec![b"user".to_vec(), vec![1, 2, 3]];
+        let full = derive_public_key(&root(), &path).unwrap();
+        let step = derive_public_key(&root(), &path[..1]).unwrap();
+        assert_eq!(derive_public_key(&step, &path[1..]), Ok(full.clone()));
+        assert_eq!(full.public_key.len(), 33);
+        assert_ne!(full.public_key, step.public_key);
+        assert_ne!(full.chain_code, step.chain_code);
+    }
+
+    #[test]
+    fn malformed_parent_is_rejected() {
+        let mut parent = root();
+        parent.chain_code.pop();
+        assert_eq!(
+            derive_public_key(&parent, &[]),
+            Err(EcdsaError::InvalidPublicKey)
+        );
+        let mut parent = root();
+        parent.public_key.truncate(20);
+        assert_eq!(
+            derive_public_key(&parent, &[]),
+            Err(EcdsaError::InvalidPublicKey)
+        );
+    }
+}
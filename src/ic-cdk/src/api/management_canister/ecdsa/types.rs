@@ -0,0 +1,100 @@
+use crate::api::call::CallError;
+use crate::api::management_canister::CanisterId;
+use candid::{CandidType, Deserialize};
+
+/// Elliptic curve of a threshold ECDSA key.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EcdsaCurve {
+    /// secp256k1, as used by Bitcoin and Ethereum.
+    #[serde(rename = "secp256k1")]
+    Secp256k1,
+}
+
+/// Identifies a threshold ECDSA key held by the IC.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EcdsaKeyId {
+    /// See [EcdsaCurve].
+    pub curve: EcdsaCurve,
+    /// Name of the key, e.g. `"key_1"` on mainnet, `"test_key_1"` for testing on mainnet
+    /// or `"dfx_test_key"` on a local replica.
+    pub name: String,
+}
+
+/// Argument type of [ecdsa_public_key](super::ecdsa_public_key).
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EcdsaPublicKeyArgument {
+    /// Canister whose key is requested, the caller if `None`.
+    pub canister_id: Option<CanisterId>,
+    /// Path from the key of the canister to the requested key.
+    pub derivation_path: Vec<Vec<u8>>,
+    /// See [EcdsaKeyId].
+    pub key_id: EcdsaKeyId,
+}
+
+/// Response type of [ecdsa_public_key](super::ecdsa_public_key).
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EcdsaPublicKeyResponse {
+    /// The public key, in SEC1 compressed form (33 bytes).
+    pub public_key: Vec<u8>,
+    /// The chain code, used to derive keys further down the path.
+    pub chain_code: Vec<u8>,
+}
+
+/// Argument type of [sign_with_ecdsa](super::sign_with_ecdsa).
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignWithEcdsaArgument {
+    /// The 32-byte hash of the message to sign.
+    pub message_hash: Vec<u8>,
+    /// Path from the key of the calling canister to the signing key.
+    pub derivation_path: Vec<Vec<u8>>,
+    /// See [EcdsaKeyId].
+    pub key_id: EcdsaKeyId,
+}
+
+/// Response type of [sign_with_ecdsa](super::sign_with_ecdsa).
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignWithEcdsaResponse {
+    /// The signature, as the 64-byte concatenation of `r` and `s`.
+    pub signature: Vec<u8>,
+}
+
+/// Errors of the threshold ECDSA functions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EcdsaError {
+    /// The message hash is not 32 bytes long. The call was not made.
+    InvalidMessageHash {
+        /// Length of the given hash.
+        length: usize,
+    },
+    /// The public key or chain code to derive from is malformed.
+    InvalidPublicKey,
+    /// The call to the management canister failed.
+    Call(CallError),
+}
+
+impl std::fmt::Display for EcdsaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EcdsaError::InvalidMessageHash { length } => {
+                write!(f, "message hash must be 32 bytes long, got {}", length)
+            }
+            EcdsaError::InvalidPublicKey => f.write_str("invalid public key or chain code"),
+            EcdsaError::Call(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for EcdsaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EcdsaError::Call(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CallError> for EcdsaError {
+    fn from(err: CallError) -> Self {
+        EcdsaError::Call(err)
+    }
+}
@@ -0,0 +1,83 @@
+//! HTTPS outcalls through the management canister.
+//!
+//! ```rust,no_run
+//! use ic_cdk::api::management_canister::http_request::*;
+//!
+//! async fn fetch() -> Vec<u8> {
+//!     let arg = CanisterHttpRequestArgument {
+//!         url: "https://example.com/rates.json".to_string(),
+//!         max_response_bytes: Some(10_000),
+//!         method: HttpMethod::Get,
+//!         headers: vec![],
+//!         body: None,
+//!         transform: Some(TransformContext::new("transform", vec![])),
+//!     };
+//!     http_request(arg).await.expect("outcall failed").body
+//! }
+//! ```
+use crate::api::call::{call_with_payment128, CallResult};
+use crate::export::Principal;
+
+mod types;
+pub use types::*;
+
+#[cfg(test)]
+mod tests;
+
+// The limit applied when a request does not set `max_response_bytes`.
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 2_000_000;
+
+// HTTPS outcalls are performed by every node of the subnet; the cost estimate assumes
+// a 13-node application subnet.
+const SUBNET_SIZE: u128 = 13;
+
+/// Make an HTTP request to a given URL and return the response.
+///
+/// The cycles returned by [http_request_required_cycles] are attached to the call.
+/// Cycles that are not used are refunded.
+///
+/// See [IC method `http_request`](https://smartcontracts.org/docs/interface-spec/index.html#ic-http_request).
+pub async fn http_request(arg: CanisterHttpRequestArgument) -> CallResult<HttpResponse> {
+    let cycles = http_request_required_cycles(&arg);
+    http_request_with_cycles(arg, cycles).await
+}
+
+/// Like [http_request], attaching the given amount of `cycles` instead of the estimate.
+pub async fn http_request_with_cycles(
+    arg: CanisterHttpRequestArgument,
+    cycles: u128,
+) -> CallResult<HttpResponse> {
+    let (response,) = call_with_payment128(
+        Principal::management_canister(),
+        "http_request",
+        (arg,),
+        cycles,
+    )
+    .await?;
+    Ok(response)
+}
+
+/// Estimate the cycles needed to perform the given request.
+///
+/// The cost is made of a fixed fee plus a fee per byte of the request (URL, headers, body
+/// and transform) and per byte of `max_response_bytes`, defaulting to 2MB if unset.
+pub fn http_request_required_cycles(arg: &CanisterHttpRequestArgument) -> u128 {
+    let max_response_bytes = arg.max_response_bytes.unwrap_or(DEFAULT_MAX_RESPONSE_BYTES) as u128;
+    let request_bytes = request_size(arg) as u128;
+    (3_000_000 + 60_000 * SUBNET_SIZE) * SUBNET_SIZE
+        + 400 * SUBNET_SIZE * request_bytes
+        + 800 * SUBNET_SIZE * max_response_bytes
+}
+
+fn request_size(arg: &CanisterHttpRequestArgument) -> usize {
+    let headers: usize = arg
+        .headers
+        .iter()
+        .map(|header| header.name.len() + header.value.len())
+        .sum();
+    let body = arg.body.as_ref().map_or(0, |body| body.len());
+    let transform = arg.transform.as_ref().map_or(0, |transform| {
+        transform.function.0.method.len() + transform.context.len()
+    });
+    arg.url.len() + headers + body + transform
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+fn request(max_response_bytes: Option<u64>) -> CanisterHttpRequestArgument {
+    CanisterHttpRequestArgument {
+        url: "https://example.com".to_string(),
+        max_response_bytes,
+        method: HttpMethod::Post,
+        headers: vec![HttpHeader {
+            name: "Accept".to_string(),
+            value: "*/*".to_string(),
+        }],
+        body: Some(b"hello".to_vec()),
+        transform: Some(TransformContext::new("transform", b"ctx".to_vec())),
+    }
+}
+
+#[test]
+fn request_size_counts_every_part() {
+    // url (19) + header (6 + 3) + body (5) + transform (9 + 3)
+    assert_eq!(request_size(&request(None)), 45);
+}
+
+#[test]
+fn required_cycles_grow_with_sizes() {
+    let base = (3_000_000 + 60_000 * 13) * 13;
+    assert_eq!(
+        http_request_required_cycles(&request(Some(1000))),
+        base + 400 * 13 * 45 + 800 * 13 * 1000
+    );
+    assert_eq!(
+        http_request_required_cycles(&request(None)),
+        base + 400 * 13 * 45 + 800 * 13 * 2_000_000
+    );
+}
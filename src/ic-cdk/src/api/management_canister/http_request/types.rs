@@ -0,0 +1,125 @@
+use candid::{types::reference::Func, CandidType, Deserialize, Nat};
+
+/// HTTP method of an outcall.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HttpMethod {
+    /// GET
+    #[serde(rename = "get")]
+    Get,
+    /// POST
+    #[serde(rename = "post")]
+    Post,
+    /// HEAD
+    #[serde(rename = "head")]
+    Head,
+}
+
+/// An HTTP header.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HttpHeader {
+    /// Name of the header.
+    pub name: String,
+    /// Value of the header.
+    pub value: String,
+}
+
+/// Argument type of [http_request](super::http_request).
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterHttpRequestArgument {
+    /// The requested URL.
+    pub url: String,
+    /// The maximal size of the response in bytes, headers included.
+    ///
+    /// If `None`, the limit of 2MB is used. The response cost is charged for the
+    /// maximum, so setting a tight bound makes the call cheaper.
+    pub max_response_bytes: Option<u64>,
+    /// The method of the request.
+    pub method: HttpMethod,
+    /// Headers of the request.
+    pub headers: Vec<HttpHeader>,
+    /// Body of the request.
+    pub body: Option<Vec<u8>>,
+    /// Function applied to the response before consensus, see [TransformContext].
+    pub transform: Option<TransformContext>,
+}
+
+/// The response of an HTTP outcall.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// The status code.
+    pub status: Nat,
+    /// Headers of the response.
+    pub headers: Vec<HttpHeader>,
+    /// Body of the response.
+    pub body: Vec<u8>,
+}
+
+/// Argument type of a transform function.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TransformArgs {
+    /// The raw response received from the server.
+    pub response: HttpResponse,
+    /// The context given in the [TransformContext] of the request.
+    pub context: Vec<u8>,
+}
+
+/// A reference to a query method of this canister used as a transform function.
+///
+/// Its Candid type is `func (TransformArgs) -> (HttpResponse) query`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct TransformFunc(pub Func);
+
+impl CandidType for TransformFunc {
+    fn _ty() -> candid::types::Type {
+        candid::types::Type::Func(candid::types::Function {
+            modes: vec![candid::parser::types::FuncMode::Query],
+            args: vec![TransformArgs::_ty()],
+            rets: vec![HttpResponse::_ty()],
+        })
+    }
+
+    fn idl_serialize<S>(&self, serializer: S) -> Result<(), S::Error>
+    where
+        S: candid::types::Serializer,
+    {
+        self.0.idl_serialize(serializer)
+    }
+}
+
+/// The transform function of a request and the context passed to it.
+///
+/// Responses received by the different replicas usually differ in details such as
+/// timestamps or request ids. The transform function, a query method of the calling
+/// canister taking [TransformArgs] and returning an [HttpResponse], strips them so
+/// that the replicas can agree on the response.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TransformContext {
+    /// The transform function.
+    pub function: TransformFunc,
+    /// Context passed to the transform function along with the response.
+    pub context: Vec<u8>,
+}
+
+impl TransformContext {
+    /// Uses the query method `method` of this canister as the transform function.
+    ///
+    /// The method must be exported with the signature
+    /// `(TransformArgs) -> (HttpResponse) query`, e.g.
+    ///
+    /// ```rust,ignore
+    /// #[query]
+    /// fn transform(args: TransformArgs) -> HttpResponse {
+    ///     HttpResponse { headers: vec![], ..args.response }
+    /// }
+    /// ```
+    pub fn new(method: &str, context: Vec<u8>) -> Self {
+        TransformContext {
+            function: TransformFunc(Func {
+                principal: crate::api::id(),
+                method: method.to_string(),
+            }),
+            context,
+        }
+    }
+}
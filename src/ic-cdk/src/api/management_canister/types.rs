@@ -0,0 +1,125 @@
+use crate::export::Principal;
+use candid::{CandidType, Deserialize, Nat};
+
+/// Canister ID is Principal.
+pub type CanisterId = Principal;
+
+/// Argument type of [create_canister](super::create_canister).
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CreateCanisterArgument {
+    /// See [CanisterSettings].
+    pub settings: Option<CanisterSettings>,
+}
+
+/// A wrapper of canister id.
+///
+/// It is used as the argument of most management canister methods
+/// and as the result of [create_canister](super::create_canister).
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CanisterIdRecord {
+    /// Principal of the canister.
+    pub canister_id: CanisterId,
+}
+
+/// Canister settings.
+///
+/// Every field is optional; a `None` value leaves the corresponding setting
+/// unchanged (or set to its default when creating a canister).
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CanisterSettings {
+    /// A list of principals who are allowed to manage the canister.
+    ///
+    /// Must contain at most 10 principals.
+    pub controllers: Option<Vec<Principal>>,
+    /// Amount of compute that this canister has guaranteed, in percent (0-100).
+    pub compute_allocation: Option<Nat>,
+    /// Amount of memory (in bytes) that this canister is allowed to use.
+    pub memory_allocation: Option<Nat>,
+    /// The number of seconds the canister can run before it is frozen by the
+    /// system due to lack of cycles.
+    pub freezing_threshold: Option<Nat>,
+}
+
+/// Argument type of [update_settings](super::update_settings).
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UpdateSettingsArgument {
+    /// Principal of the canister.
+    pub canister_id: CanisterId,
+    /// See [CanisterSettings].
+    pub settings: CanisterSettings,
+}
+
+/// The mode with which a canister is installed.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CanisterInstallMode {
+    /// A fresh install of a new canister.
+    #[serde(rename = "install")]
+    Install,
+    /// Reinstalling a canister that was already installed, wiping its state.
+    #[serde(rename = "reinstall")]
+    Reinstall,
+    /// Upgrade an existing canister, keeping its stable memory.
+    #[serde(rename = "upgrade")]
+    Upgrade,
+}
+
+/// WASM module.
+pub type WasmModule = Vec<u8>;
+
+/// Argument type of [install_code](super::install_code).
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct InstallCodeArgument {
+    /// See [CanisterInstallMode].
+    pub mode: CanisterInstallMode,
+    /// Principal of the canister.
+    pub canister_id: CanisterId,
+    /// Code to be installed.
+    pub wasm_module: WasmModule,
+    /// The argument passed to the `canister_init` or `canister_post_upgrade`
+    /// entry point, usually Candid-encoded.
+    pub arg: Vec<u8>,
+}
+
+/// Status of a canister.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CanisterStatusType {
+    /// The canister is running.
+    #[serde(rename = "running")]
+    Running,
+    /// The canister is stopping.
+    #[serde(rename = "stopping")]
+    Stopping,
+    /// The canister is stopped.
+    #[serde(rename = "stopped")]
+    Stopped,
+}
+
+/// Like [CanisterSettings], but with every field resolved to its current value.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DefiniteCanisterSettings {
+    /// Controllers of the canister.
+    pub controllers: Vec<Principal>,
+    /// Compute allocation, in percent.
+    pub compute_allocation: Nat,
+    /// Memory allocation, in bytes.
+    pub memory_allocation: Nat,
+    /// Freezing threshold, in seconds.
+    pub freezing_threshold: Nat,
+}
+
+/// Result type of [canister_status](super::canister_status).
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterStatusResponse {
+    /// See [CanisterStatusType].
+    pub status: CanisterStatusType,
+    /// See [DefiniteCanisterSettings].
+    pub settings: DefiniteCanisterSettings,
+    /// A SHA256 hash of the installed module, if any.
+    pub module_hash: Option<Vec<u8>>,
+    /// The memory size taken by the canister, in bytes.
+    pub memory_size: Nat,
+    /// The cycle balance of the canister.
+    pub cycles: Nat,
+    /// The amount of cycles the canister burns per day while idle.
+    pub idle_cycles_burned_per_day: Nat,
+}
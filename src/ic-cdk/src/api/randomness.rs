@@ -0,0 +1,182 @@
+//! Cryptographically secure randomness for canisters.
+//!
+//! The only source of randomness on the IC is the `raw_rand` method of the management
+//! canister, which returns 32 fresh bytes per call. This module uses them to seed a ChaCha20
+//! generator kept in canister memory, which then serves any amount of random bytes
+//! synchronously.
+//!
+//! ```rust,no_run
+//! use ic_cdk::api::randomness;
+//!
+//! async fn new_token() -> [u8; 16] {
+//!     if !randomness::is_seeded() {
+//!         randomness::reseed().await.expect("raw_rand failed");
+//!     }
+//!     let mut token = [0u8; 16];
+//!     randomness::fill_bytes(&mut token).unwrap();
+//!     token
+//! }
+//! ```
+//!
+//! The generator lives on the heap, so it is lost on upgrade: call [reseed] again after an
+//! upgrade, e.g. from a timer set in `#[post_upgrade]`. Reseeding periodically limits the
+//! output that depends on a single seed.
+//!
+//! With the `custom-getrandom` feature, the generator is also registered as the
+//! [getrandom](https://docs.rs/getrandom) backend of the canister, so that crates such as
+//! `rand` or `uuid` work unmodified once the generator is seeded.
+use crate::api::call::CallError;
+use rand_chacha::rand_core::{self, CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::cell::RefCell;
+use std::num::NonZeroU32;
+
+#[cfg(test)]
+mod tests;
+
+thread_local! {
+    static RNG: RefCell<Option<ChaCha20Rng>> = RefCell::new(None);
+}
+
+/// Error returned when randomness is requested before the generator has been seeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NotSeeded;
+
+impl std::fmt::Display for NotSeeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the random number generator has not been seeded")
+    }
+}
+
+impl std::error::Error for NotSeeded {}
+
+// The custom error code reported through `rand_core` and `getrandom` while not seeded.
+const NOT_SEEDED_CODE: u32 = rand_core::Error::CUSTOM_START + 1;
+
+/// Error returned by [reseed].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReseedError {
+    /// `raw_rand` returned fewer than 32 bytes. The generator was left unchanged.
+    ShortSeed {
+        /// The number of bytes returned.
+        length: usize,
+    },
+    /// The call to `raw_rand` failed.
+    Call(CallError),
+}
+
+impl std::fmt::Display for ReseedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReseedError::ShortSeed { length } => {
+                write!(f, "raw_rand returned {} bytes, expected 32", length)
+            }
+            ReseedError::Call(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ReseedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReseedError::Call(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CallError> for ReseedError {
+    fn from(err: CallError) -> Self {
+        ReseedError::Call(err)
+    }
+}
+
+/// Fetches 32 bytes from `raw_rand` and uses them to seed the generator, replacing
+/// the previous seed if any.
+pub async fn reseed() -> Result<(), ReseedError> {
+    let bytes = super::management_canister::raw_rand().await?;
+    set_seed(seed_from(&bytes)?);
+    Ok(())
+}
+
+// Takes the seed from the output of `raw_rand`, refusing to pad a short one with known bytes.
+fn seed_from(bytes: &[u8]) -> Result<[u8; 32], ReseedError> {
+    let mut seed = [0u8; 32];
+    match bytes.get(..32) {
+        Some(bytes) => seed.copy_from_slice(bytes),
+        None => {
+            return Err(ReseedError::ShortSeed {
+                length: bytes.len(),
+            })
+        }
+    }
+    Ok(seed)
+}
+
+/// Seeds the generator with the given bytes, replacing the previous seed if any.
+///
+/// The seed must be unpredictable for the output to be; use [reseed] unless the seed comes
+/// from another source of randomness.
+pub fn set_seed(seed: [u8; 32]) {
+    RNG.with(|rng| *rng.borrow_mut() = Some(ChaCha20Rng::from_seed(seed)));
+}
+
+/// Whether the generator has been seeded.
+pub fn is_seeded() -> bool {
+    RNG.with(|rng| rng.borrow().is_some())
+}
+
+/// Fills `dest` with random bytes.
+pub fn fill_bytes(dest: &mut [u8]) -> Result<(), NotSeeded> {
+    RNG.with(|rng| match rng.borrow_mut().as_mut() {
+        Some(rng) => {
+            rng.fill_bytes(dest);
+            Ok(())
+        }
+        None => Err(NotSeeded),
+    })
+}
+
+/// A handle to the generator of the canister, for use with the `rand` crate.
+///
+/// All handles draw from the same generator. Drawing from an unseeded generator traps,
+/// except through [try_fill_bytes](RngCore::try_fill_bytes) which returns an error.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CanisterRng;
+
+impl RngCore for CanisterRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(err) = fill_bytes(dest) {
+            crate::trap(&err.to_string());
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        fill_bytes(dest).map_err(|_| NonZeroU32::new(NOT_SEEDED_CODE).unwrap().into())
+    }
+}
+
+impl CryptoRng for CanisterRng {}
+
+#[cfg(all(feature = "custom-getrandom", target_arch = "wasm32"))]
+mod custom_getrandom {
+    use super::{fill_bytes, NonZeroU32, NOT_SEEDED_CODE};
+
+    fn fill(dest: &mut [u8]) -> Result<(), getrandom::Error> {
+        fill_bytes(dest).map_err(|_| NonZeroU32::new(NOT_SEEDED_CODE).unwrap().into())
+    }
+
+    getrandom::register_custom_getrandom!(fill);
+}
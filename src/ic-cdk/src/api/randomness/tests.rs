@@ -0,0 +1,51 @@
+use super::*;
+
+// Tests may share a thread, and with it the generator.
+fn unseed() {
+    RNG.with(|rng| *rng.borrow_mut() = None);
+}
+
+#[test]
+fn unseeded_generator_returns_error() {
+    unseed();
+    let mut buf = [0u8; 8];
+    assert!(!is_seeded());
+    assert_eq!(fill_bytes(&mut buf), Err(NotSeeded));
+    assert!(CanisterRng.try_fill_bytes(&mut buf).is_err());
+}
+
+#[test]
+fn output_is_determined_by_seed() {
+    set_seed([1; 32]);
+    assert!(is_seeded());
+    let mut first = [0u8; 32];
+    fill_bytes(&mut first).unwrap();
+    let mut second = [0u8; 32];
+    fill_bytes(&mut second).unwrap();
+    assert_ne!(first, second);
+
+    set_seed([1; 32]);
+    let mut again = [0u8; 32];
+    CanisterRng.fill_bytes(&mut again);
+    assert_eq!(first, again);
+
+    set_seed([2; 32]);
+    fill_bytes(&mut again).unwrap();
+    assert_ne!(first, again);
+}
+
+#[test]
+#[should_panic(expected = "has not been seeded")]
+fn canister_rng_traps_when_unseeded() {
+    unseed();
+    CanisterRng.next_u64();
+}
+
+#[test]
+fn short_seed_is_rejected() {
+    assert_eq!(
+        seed_from(&[7; 31]),
+        Err(ReseedError::ShortSeed { length: 31 })
+    );
+    assert_eq!(seed_from(&[7; 32]), Ok([7; 32]));
+}
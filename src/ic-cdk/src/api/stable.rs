@@ -2,12 +2,34 @@
 //!
 //! You can check the [Internet Computer Specification](https://smartcontracts.org/docs/interface-spec/index.html#system-api-stable-memory)
 //! for a in-depth explanation of stable memory.
+pub mod btreemap;
 mod canister;
+mod cell;
+mod checksummed;
+mod faulty_memory;
+pub(crate) mod layout;
+pub mod log;
+mod memory_manager;
+mod storable;
 #[cfg(test)]
 mod tests;
+pub mod vec;
+mod vector_memory;
 
+pub use self::log::StableLog;
+pub use btreemap::StableBTreeMap;
 pub use canister::CanisterStableMemory;
+pub use cell::StableCell;
+pub(crate) use checksummed::{crc32, Crc32Hasher};
+pub use checksummed::{
+    ChecksumAlgorithm, ChecksumError, ChecksummedReader, ChecksummedWriter, MAX_BLOCK_SIZE,
+};
+pub use faulty_memory::FaultyMemory;
+pub use memory_manager::{MemoryId, MemoryManager, VirtualMemory, MAX_NUM_MEMORIES};
 use std::{error, fmt, io};
+pub use storable::{BoundedStorable, Storable};
+pub use vec::StableVec;
+pub use vector_memory::VectorMemory;
 
 const WASM_PAGE_SIZE_IN_BYTES: usize = 64 * 1024; // 64KB
 
@@ -121,11 +143,8 @@ pub fn stable64_read(offset: u64, buf: &mut [u8]) {
 /// This will map the whole memory (even if not all of it has been written to).
 pub fn stable_bytes() -> Vec<u8> {
     let size = (stable_size() as usize) << 16;
-    let mut vec = Vec::with_capacity(size);
-    unsafe {
-        super::ic0::stable_read(vec.as_ptr() as i32, 0, size as i32);
-        vec.set_len(size);
-    }
+    let mut vec = vec![0u8; size];
+    stable_read(0, &mut vec);
     vec
 }
 
@@ -138,10 +157,10 @@ pub fn stable_bytes() -> Vec<u8> {
 /// and keep offsets and total capacity.
 pub struct StableWriter<M: StableMemory = CanisterStableMemory> {
     /// The offset of the next write.
-    offset: usize,
+    offset: u64,
 
     /// The capacity, in pages.
-    capacity: u32,
+    capacity: u64,
 
     /// The stable memory to write data to.
     memory: M,
@@ -155,8 +174,8 @@ impl Default for StableWriter {
 
 impl<M: StableMemory> StableWriter<M> {
     /// Creates a new `StableWriter` which writes to the selected memory
-    pub fn with_memory(memory: M, offset: usize) -> Self {
-        let capacity = memory.stable_size();
+    pub fn with_memory(memory: M, offset: u64) -> Self {
+        let capacity = memory.stable64_size();
 
         Self {
             offset,
@@ -165,9 +184,19 @@ impl<M: StableMemory> StableWriter<M> {
         }
     }
 
+    /// Returns the offset of the next write.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the size of the memory, in WASM pages, as last seen by the writer.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
     /// Attempts to grow the memory by adding new pages.
-    pub fn grow(&mut self, new_pages: u32) -> Result<(), StableMemoryError> {
-        let old_page_count = self.memory.stable_grow(new_pages)?;
+    pub fn grow(&mut self, new_pages: u64) -> Result<(), StableMemoryError> {
+        let old_page_count = self.memory.stable64_grow(new_pages)?;
         self.capacity = old_page_count + new_pages;
         Ok(())
     }
@@ -177,9 +206,12 @@ impl<M: StableMemory> StableWriter<M> {
     /// The only condition where this will
     /// error out is if it cannot grow the memory.
     pub fn write(&mut self, buf: &[u8]) -> Result<usize, StableMemoryError> {
-        let required_capacity_bytes = self.offset + buf.len();
-        let required_capacity_pages = ((required_capacity_bytes + WASM_PAGE_SIZE_IN_BYTES - 1)
-            / WASM_PAGE_SIZE_IN_BYTES) as u32;
+        let required_capacity_bytes = self
+            .offset
+            .checked_add(buf.len() as u64)
+            .ok_or(StableMemoryError::OutOfMemory)?;
+        let page_size = WASM_PAGE_SIZE_IN_BYTES as u64;
+        let required_capacity_pages = (required_capacity_bytes + page_size - 1) / page_size;
         let current_pages = self.capacity;
         let additional_pages_required = required_capacity_pages.saturating_sub(current_pages);
 
@@ -187,8 +219,8 @@ impl<M: StableMemory> StableWriter<M> {
             self.grow(additional_pages_required)?;
         }
 
-        self.memory.stable_write(self.offset as u32, buf);
-        self.offset += buf.len();
+        self.memory.stable64_write(self.offset, buf);
+        self.offset += buf.len() as u64;
         Ok(buf.len())
     }
 }
@@ -205,6 +237,16 @@ impl<M: StableMemory> io::Write for StableWriter<M> {
     }
 }
 
+impl<M: StableMemory> io::Seek for StableWriter<M> {
+    /// Moves the offset of the next write. Seeking past the end of the memory is allowed;
+    /// the memory grows on the next write.
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let end = self.capacity * WASM_PAGE_SIZE_IN_BYTES as u64;
+        self.offset = seek_offset(self.offset, end, pos)?;
+        Ok(self.offset)
+    }
+}
+
 /// A writer to the stable memory which first writes the bytes to an in memory buffer and flushes
 /// the buffer to stable memory each time it becomes full.
 ///
@@ -243,15 +285,22 @@ impl<M: StableMemory> io::Write for BufferedStableWriter<M> {
     }
 }
 
+impl<M: StableMemory> io::Seek for BufferedStableWriter<M> {
+    /// Flushes the buffer, then moves the offset of the next write.
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
 /// A reader to the stable memory.
 ///
 /// Keeps an offset and reads off stable memory consecutively.
 pub struct StableReader<M: StableMemory = CanisterStableMemory> {
     /// The offset of the next read.
-    offset: usize,
+    offset: u64,
 
     /// The capacity, in pages.
-    capacity: u32,
+    capacity: u64,
 
     /// The stable memory to read data from.
     memory: M,
@@ -265,8 +314,8 @@ impl Default for StableReader {
 
 impl<M: StableMemory> StableReader<M> {
     /// Creates a new `StableReader` which reads from the selected memory
-    pub fn with_memory(memory: M, offset: usize) -> Self {
-        let capacity = memory.stable_size();
+    pub fn with_memory(memory: M, offset: u64) -> Self {
+        let capacity = memory.stable64_size();
 
         Self {
             offset,
@@ -275,21 +324,28 @@ impl<M: StableMemory> StableReader<M> {
         }
     }
 
+    /// Returns the offset of the next read.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the size of the memory, in WASM pages, when the reader was created.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
     /// Reads data from the stable memory location specified by an offset.
     pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, StableMemoryError> {
-        let capacity_bytes = self.capacity as usize * WASM_PAGE_SIZE_IN_BYTES;
-        let read_buf = if buf.len() + self.offset > capacity_bytes {
-            if self.offset < capacity_bytes {
-                &mut buf[..capacity_bytes - self.offset]
-            } else {
-                return Err(StableMemoryError::OutOfBounds);
-            }
-        } else {
-            buf
-        };
-        self.memory.stable_read(self.offset as u32, read_buf);
-        self.offset += read_buf.len();
-        Ok(read_buf.len())
+        let capacity_bytes = self.capacity * WASM_PAGE_SIZE_IN_BYTES as u64;
+        if self.offset >= capacity_bytes && !buf.is_empty() {
+            return Err(StableMemoryError::OutOfBounds);
+        }
+        let available = capacity_bytes.saturating_sub(self.offset);
+        let read_len = std::cmp::min(buf.len() as u64, available) as usize;
+        let read_buf = &mut buf[..read_len];
+        self.memory.stable64_read(self.offset, read_buf);
+        self.offset += read_len as u64;
+        Ok(read_len)
     }
 }
 
@@ -299,6 +355,15 @@ impl<M: StableMemory> io::Read for StableReader<M> {
     }
 }
 
+impl<M: StableMemory> io::Seek for StableReader<M> {
+    /// Moves the offset of the next read. Reads past the end of the memory return no bytes.
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let end = self.capacity * WASM_PAGE_SIZE_IN_BYTES as u64;
+        self.offset = seek_offset(self.offset, end, pos)?;
+        Ok(self.offset)
+    }
+}
+
 /// A reader to the stable memory which reads bytes a chunk at a time as each chunk is required.
 pub struct BufferedStableReader<M: StableMemory = CanisterStableMemory> {
     inner: io::BufReader<StableReader<M>>,
@@ -325,3 +390,30 @@ impl<M: StableMemory> io::Read for BufferedStableReader<M> {
         self.inner.read(buf)
     }
 }
+
+impl<M: StableMemory> io::Seek for BufferedStableReader<M> {
+    /// Discards the buffer, then moves the offset of the next read.
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+// Resolves a seek from `current`, in a memory of `end` bytes.
+fn seek_offset(current: u64, end: u64, pos: io::SeekFrom) -> io::Result<u64> {
+    let (base, delta) = match pos {
+        io::SeekFrom::Start(offset) => return Ok(offset),
+        io::SeekFrom::End(delta) => (end, delta),
+        io::SeekFrom::Current(delta) => (current, delta),
+    };
+    let offset = if delta >= 0 {
+        base.checked_add(delta as u64)
+    } else {
+        base.checked_sub(delta.unsigned_abs())
+    };
+    offset.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
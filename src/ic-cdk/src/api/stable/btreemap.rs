@@ -0,0 +1,588 @@
+//! A B-tree map stored in stable memory.
+use super::{layout, BoundedStorable, StableMemory};
+use node::{Layout, Node, B};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+mod node;
+#[cfg(test)]
+mod tests;
+
+const MAGIC: &[u8; 3] = b"BTR";
+const LAYOUT_VERSION: u8 = 1;
+
+// Header layout, at offset 0 of the memory:
+//
+// | magic (3) | version (1) | max key size (4) | max value size (4) | root address (8) |
+// | length (8) | free list head (8) | end of allocated nodes (8) | reserved (20) |
+//
+// All integers are little-endian. Nodes follow the header; freed nodes are chained in a
+// free list through their first 8 bytes.
+const MAX_KEY_SIZE_OFFSET: u64 = 4;
+const ROOT_OFFSET: u64 = 12;
+const HEADER_SIZE: u64 = 64;
+
+// Address 0 holds the header, so it never denotes a node.
+const NULL: u64 = 0;
+
+/// An ordered map whose entries live in stable memory rather than on the heap.
+///
+/// Keys and values are encoded with [Storable](super::Storable), within the bounds set by
+/// [BoundedStorable]. Every operation reads and writes the nodes it needs directly in
+/// `memory`, so the map can grow beyond the heap and nothing needs to be serialized in
+/// `pre_upgrade`: calling [init](StableBTreeMap::init) on the same memory after an upgrade
+/// only reads a small header.
+///
+/// ```rust
+/// use ic_cdk::api::stable::{CanisterStableMemory, MemoryId, MemoryManager, StableBTreeMap};
+///
+/// let manager = MemoryManager::init(CanisterStableMemory::default());
+/// let mut balances: StableBTreeMap<u64, u128, _> =
+///     StableBTreeMap::init(manager.get(MemoryId::new(0)));
+/// balances.insert(1, 100);
+/// assert_eq!(balances.get(&1), Some(100));
+/// ```
+///
+/// The map takes the whole of `memory`; use a [MemoryManager](super::MemoryManager) to store
+/// several structures in stable memory.
+pub struct StableBTreeMap<K, V, M: StableMemory> {
+    memory: M,
+    layout: Layout,
+    root: u64,
+    len: u64,
+    free_list: u64,
+    // Nodes are allocated below this address.
+    end: u64,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V, M> StableBTreeMap<K, V, M>
+where
+    K: BoundedStorable + Ord,
+    V: BoundedStorable,
+    M: StableMemory,
+{
+    /// Loads the map stored in `memory`, or initializes an empty one if `memory` is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `memory` is not empty and does not hold a map, or holds a map whose key
+    /// or value bounds differ from those of `K` and `V`.
+    pub fn init(memory: M) -> Self {
+        let layout = Layout {
+            max_key_size: K::MAX_SIZE,
+            max_value_size: V::MAX_SIZE,
+        };
+        if memory.stable64_size() == 0 {
+            let map = StableBTreeMap {
+                memory,
+                layout,
+                root: NULL,
+                len: 0,
+                free_list: NULL,
+                end: HEADER_SIZE,
+                _marker: PhantomData,
+            };
+            map.ensure_size(HEADER_SIZE);
+            layout::write_magic(&map.memory, MAGIC, LAYOUT_VERSION);
+            layout::write_u32(&map.memory, MAX_KEY_SIZE_OFFSET, layout.max_key_size);
+            layout::write_u32(&map.memory, MAX_KEY_SIZE_OFFSET + 4, layout.max_value_size);
+            map.save_header();
+            return map;
+        }
+
+        layout::check_magic(&memory, MAGIC, LAYOUT_VERSION, "StableBTreeMap");
+        let stored = Layout {
+            max_key_size: layout::read_u32(&memory, MAX_KEY_SIZE_OFFSET),
+            max_value_size: layout::read_u32(&memory, MAX_KEY_SIZE_OFFSET + 4),
+        };
+        if stored != layout {
+            panic!(
+                "StableBTreeMap bounds mismatch: stored {:?}, expected {:?}.",
+                stored, layout
+            );
+        }
+
+        StableBTreeMap {
+            root: layout::read_u64(&memory, ROOT_OFFSET),
+            len: layout::read_u64(&memory, ROOT_OFFSET + 8),
+            free_list: layout::read_u64(&memory, ROOT_OFFSET + 16),
+            end: layout::read_u64(&memory, ROOT_OFFSET + 24),
+            memory,
+            layout,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The number of entries in the map.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the value associated with `key`, if any.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut address = self.root;
+        while address != NULL {
+            let mut node = self.load(address);
+            match node.keys.binary_search(key) {
+                Ok(idx) => return Some(V::from_bytes(node.values.swap_remove(idx))),
+                Err(_) if node.is_leaf() => return None,
+                Err(idx) => address = node.children[idx],
+            }
+        }
+        None
+    }
+
+    /// Whether the map contains `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts an entry, returning the value previously associated with `key`, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoding of the key or the value exceeds its bound, or if stable memory
+    /// cannot grow to hold a new node.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let key_size = key.to_bytes().len();
+        assert!(
+            key_size <= K::MAX_SIZE as usize,
+            "Key of {} bytes exceeds the bound of {} bytes.",
+            key_size,
+            K::MAX_SIZE
+        );
+        let value = value.to_bytes().into_owned();
+        assert!(
+            value.len() <= V::MAX_SIZE as usize,
+            "Value of {} bytes exceeds the bound of {} bytes.",
+            value.len(),
+            V::MAX_SIZE
+        );
+
+        if let Some(previous) = self.replace(&key, &value) {
+            return Some(V::from_bytes(previous));
+        }
+
+        let mut root = if self.root == NULL {
+            let root = Node::new(self.allocate());
+            self.root = root.address;
+            root
+        } else {
+            self.load(self.root)
+        };
+        if root.is_full() {
+            let mut new_root = Node::new(self.allocate());
+            new_root.children.push(root.address);
+            self.root = new_root.address;
+            self.split_child(&mut new_root, 0, root);
+            root = new_root;
+        }
+        self.insert_nonfull(root, key, value);
+        self.len += 1;
+        self.save_header();
+        None
+    }
+
+    /// Removes `key` from the map, returning its value if it was present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if self.root == NULL {
+            return None;
+        }
+        let mut root = self.load(self.root);
+        let removed = self.remove_from(&mut root, key);
+
+        // Merging may have moved the last entries of the root into its only child.
+        if root.keys.is_empty() {
+            self.root = root.children.first().copied().unwrap_or(NULL);
+            self.free(root.address);
+        }
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        self.save_header();
+        removed.map(V::from_bytes)
+    }
+
+    /// Iterates over the entries of the map, in key order.
+    pub fn iter(&self) -> Iter<'_, K, V, M> {
+        Iter::new(self, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Iterates over the entries whose keys fall within `range`, in key order.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V, M>
+    where
+        K: Clone,
+    {
+        Iter::new(self, range.start_bound(), range.end_bound().cloned())
+    }
+
+    // Replaces the value of `key` if it is already present.
+    fn replace(&self, key: &K, value: &[u8]) -> Option<Vec<u8>> {
+        let mut address = self.root;
+        while address != NULL {
+            let mut node = self.load(address);
+            match node.keys.binary_search(key) {
+                Ok(idx) => {
+                    let previous = std::mem::replace(&mut node.values[idx], value.to_vec());
+                    self.save(&node);
+                    return Some(previous);
+                }
+                Err(_) if node.is_leaf() => return None,
+                Err(idx) => address = node.children[idx],
+            }
+        }
+        None
+    }
+
+    // Inserts an absent key in the subtree of `node`, which is not full. Full nodes are
+    // split on the way down, so that the leaf always has room.
+    fn insert_nonfull(&mut self, mut node: Node<K>, key: K, value: Vec<u8>) {
+        loop {
+            let idx = node.keys.binary_search(&key).unwrap_err();
+            if node.is_leaf() {
+                node.keys.insert(idx, key);
+                node.values.insert(idx, value);
+                self.save(&node);
+                return;
+            }
+            let child = self.load(node.children[idx]);
+            node = if child.is_full() {
+                let (left, right) = self.split_child(&mut node, idx, child);
+                if key > node.keys[idx] {
+                    right
+                } else {
+                    left
+                }
+            } else {
+                child
+            };
+        }
+    }
+
+    // Splits the full child at `idx` of `parent` around its median entry, which moves up
+    // into `parent`.
+    fn split_child(
+        &mut self,
+        parent: &mut Node<K>,
+        idx: usize,
+        mut child: Node<K>,
+    ) -> (Node<K>, Node<K>) {
+        let mut right = Node::new(self.allocate());
+        right.keys = child.keys.split_off(B);
+        right.values = child.values.split_off(B);
+        if !child.is_leaf() {
+            right.children = child.children.split_off(B);
+        }
+        parent.keys.insert(idx, child.keys.pop().unwrap());
+        parent.values.insert(idx, child.values.pop().unwrap());
+        parent.children.insert(idx + 1, right.address);
+        self.save(&child);
+        self.save(&right);
+        self.save(parent);
+        (child, right)
+    }
+
+    // Removes `key` from the subtree of `node`. Nodes are refilled on the way down so that
+    // each node visited, but the root, has at least `B` entries before losing one.
+    fn remove_from(&mut self, node: &mut Node<K>, key: &K) -> Option<Vec<u8>> {
+        match node.keys.binary_search(key) {
+            Ok(idx) if node.is_leaf() => {
+                node.keys.remove(idx);
+                let value = node.values.remove(idx);
+                self.save(node);
+                Some(value)
+            }
+            Ok(idx) => {
+                let mut left = self.load(node.children[idx]);
+                if left.keys.len() >= B {
+                    // Replace the entry with its predecessor.
+                    let predecessor = self.last_key(&left);
+                    let value = self.remove_from(&mut left, &predecessor).unwrap();
+                    return Some(self.replace_entry(node, idx, predecessor, value));
+                }
+                let mut right = self.load(node.children[idx + 1]);
+                if right.keys.len() >= B {
+                    // Replace the entry with its successor.
+                    let successor = self.first_key(&right);
+                    let value = self.remove_from(&mut right, &successor).unwrap();
+                    return Some(self.replace_entry(node, idx, successor, value));
+                }
+                let mut merged = self.merge(node, idx, left, right);
+                self.remove_from(&mut merged, key)
+            }
+            Err(_) if node.is_leaf() => None,
+            Err(idx) => {
+                let mut child = self.load(node.children[idx]);
+                if child.keys.len() < B {
+                    child = self.refill(node, idx, child);
+                }
+                self.remove_from(&mut child, key)
+            }
+        }
+    }
+
+    fn replace_entry(&mut self, node: &mut Node<K>, idx: usize, key: K, value: Vec<u8>) -> Vec<u8> {
+        node.keys[idx] = key;
+        let previous = std::mem::replace(&mut node.values[idx], value);
+        self.save(node);
+        previous
+    }
+
+    fn last_key(&self, node: &Node<K>) -> K {
+        let mut address = node.address;
+        loop {
+            let mut node = self.load(address);
+            match node.children.last() {
+                Some(child) => address = *child,
+                None => return node.keys.pop().unwrap(),
+            }
+        }
+    }
+
+    fn first_key(&self, node: &Node<K>) -> K {
+        let mut address = node.address;
+        loop {
+            let mut node = self.load(address);
+            match node.children.first() {
+                Some(child) => address = *child,
+                None => return node.keys.swap_remove(0),
+            }
+        }
+    }
+
+    // Gives the child at `idx` of `parent`, which has `B - 1` entries, one more entry by
+    // borrowing from a sibling, or by merging it with a sibling. Returns the node that
+    // now covers the keys of the child.
+    fn refill(&mut self, parent: &mut Node<K>, idx: usize, mut child: Node<K>) -> Node<K> {
+        let mut left = if idx > 0 {
+            Some(self.load(parent.children[idx - 1]))
+        } else {
+            None
+        };
+        if let Some(left) = left.as_mut().filter(|left| left.keys.len() >= B) {
+            let key = std::mem::replace(&mut parent.keys[idx - 1], left.keys.pop().unwrap());
+            let value = std::mem::replace(&mut parent.values[idx - 1], left.values.pop().unwrap());
+            child.keys.insert(0, key);
+            child.values.insert(0, value);
+            if !left.is_leaf() {
+                child.children.insert(0, left.children.pop().unwrap());
+            }
+            self.save(left);
+            self.save(parent);
+            self.save(&child);
+            return child;
+        }
+
+        if idx + 1 < parent.children.len() {
+            let mut right = self.load(parent.children[idx + 1]);
+            if right.keys.len() < B {
+                return self.merge(parent, idx, child, right);
+            }
+            let key = std::mem::replace(&mut parent.keys[idx], right.keys.remove(0));
+            let value = std::mem::replace(&mut parent.values[idx], right.values.remove(0));
+            child.keys.push(key);
+            child.values.push(value);
+            if !right.is_leaf() {
+                child.children.push(right.children.remove(0));
+            }
+            self.save(&right);
+            self.save(parent);
+            self.save(&child);
+            return child;
+        }
+
+        self.merge(parent, idx - 1, left.unwrap(), child)
+    }
+
+    // Merges the children at `idx` and `idx + 1` of `parent`, along with the entry at `idx`
+    // that separates them, into the left one.
+    fn merge(
+        &mut self,
+        parent: &mut Node<K>,
+        idx: usize,
+        mut left: Node<K>,
+        right: Node<K>,
+    ) -> Node<K> {
+        left.keys.push(parent.keys.remove(idx));
+        left.values.push(parent.values.remove(idx));
+        parent.children.remove(idx + 1);
+        left.keys.extend(right.keys);
+        left.values.extend(right.values);
+        left.children.extend(right.children);
+        self.free(right.address);
+        self.save(&left);
+        self.save(parent);
+        left
+    }
+
+    fn load(&self, address: u64) -> Node<K> {
+        Node::load(&self.memory, address, self.layout)
+    }
+
+    fn save(&self, node: &Node<K>) {
+        node.save(&self.memory, self.layout)
+    }
+
+    fn allocate(&mut self) -> u64 {
+        if self.free_list != NULL {
+            let address = self.free_list;
+            let mut next = [0u8; 8];
+            self.memory.stable64_read(address, &mut next);
+            self.free_list = u64::from_le_bytes(next);
+            return address;
+        }
+        let address = self.end;
+        self.end += self.layout.node_size();
+        self.ensure_size(self.end);
+        address
+    }
+
+    fn free(&mut self, address: u64) {
+        self.memory
+            .stable64_write(address, &self.free_list.to_le_bytes());
+        self.free_list = address;
+    }
+
+    // Grows the memory to at least `bytes` bytes. A failure traps, which rolls back the
+    // whole message, so a half-done insertion is never persisted.
+    fn ensure_size(&self, bytes: u64) {
+        if layout::ensure_size(&self.memory, bytes).is_err() {
+            panic!("StableBTreeMap: out of stable memory.");
+        }
+    }
+
+    fn save_header(&self) {
+        let mut header = [0u8; 32];
+        header[..8].copy_from_slice(&self.root.to_le_bytes());
+        header[8..16].copy_from_slice(&self.len.to_le_bytes());
+        header[16..24].copy_from_slice(&self.free_list.to_le_bytes());
+        header[24..].copy_from_slice(&self.end.to_le_bytes());
+        self.memory.stable64_write(ROOT_OFFSET, &header);
+    }
+}
+
+// The entries of a node that are yet to be visited, along with the children that follow them.
+struct Cursor<K> {
+    entries: std::iter::Zip<std::vec::IntoIter<K>, std::vec::IntoIter<Vec<u8>>>,
+    children: std::vec::IntoIter<u64>,
+}
+
+/// An iterator over the entries of a [StableBTreeMap], in key order.
+///
+/// Nodes are loaded from stable memory as the iteration reaches them.
+pub struct Iter<'a, K, V, M: StableMemory> {
+    map: &'a StableBTreeMap<K, V, M>,
+    // The path from the root to the node holding the next entry.
+    stack: Vec<Cursor<K>>,
+    end: Bound<K>,
+}
+
+impl<'a, K, V, M> Iter<'a, K, V, M>
+where
+    K: BoundedStorable + Ord,
+    V: BoundedStorable,
+    M: StableMemory,
+{
+    fn new(map: &'a StableBTreeMap<K, V, M>, start: Bound<&K>, end: Bound<K>) -> Self {
+        let mut iter = Iter {
+            map,
+            stack: Vec::new(),
+            end,
+        };
+        let mut address = map.root;
+        while address != NULL {
+            let node = map.load(address);
+            // The index of the first entry of the node within the range, and whether the
+            // child before it may hold entries within the range.
+            let (idx, descend) = match start {
+                Bound::Unbounded => (0, true),
+                Bound::Included(key) => match node.keys.binary_search(key) {
+                    Ok(idx) => (idx, false),
+                    Err(idx) => (idx, true),
+                },
+                Bound::Excluded(key) => match node.keys.binary_search(key) {
+                    Ok(idx) => (idx + 1, true),
+                    Err(idx) => (idx, true),
+                },
+            };
+            let child = node.children.get(idx).copied();
+            iter.push(node, idx);
+            match child {
+                Some(child) if descend => address = child,
+                _ => break,
+            }
+        }
+        iter
+    }
+
+    // Pushes a cursor on `node` positioned at the entry at `idx`.
+    fn push(&mut self, node: Node<K>, idx: usize) {
+        let mut entries = node.keys.into_iter().zip(node.values);
+        let mut children = node.children.into_iter();
+        for _ in 0..idx {
+            entries.next();
+        }
+        for _ in 0..=idx {
+            children.next();
+        }
+        self.stack.push(Cursor { entries, children });
+    }
+
+    // Pushes cursors from the node at `address` down to its leftmost leaf.
+    fn push_leftmost(&mut self, mut address: u64) {
+        loop {
+            let node = self.map.load(address);
+            let first_child = node.children.first().copied();
+            self.push(node, 0);
+            match first_child {
+                Some(child) => address = child,
+                None => return,
+            }
+        }
+    }
+
+    fn in_range(&self, key: &K) -> bool {
+        match &self.end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<'a, K, V, M> Iterator for Iter<'a, K, V, M>
+where
+    K: BoundedStorable + Ord,
+    V: BoundedStorable,
+    M: StableMemory,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let cursor = self.stack.last_mut()?;
+            let (key, value) = match cursor.entries.next() {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            let next_child = cursor.children.next();
+            if !self.in_range(&key) {
+                self.stack.clear();
+                return None;
+            }
+            if let Some(child) = next_child {
+                self.push_leftmost(child);
+            }
+            return Some((key, V::from_bytes(value)));
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use super::super::{StableMemory, Storable};
+use std::convert::TryInto;
+
+/// The minimum degree of the tree: nodes other than the root hold between `B - 1` and
+/// `2 * B - 1` entries.
+pub(super) const B: usize = 6;
+pub(super) const CAPACITY: usize = 2 * B - 1;
+
+const LEAF: u8 = 0;
+const INTERNAL: u8 = 1;
+
+/// The sizes of the slots reserved for keys and values, fixing the size of every node.
+///
+/// A node is stored as
+///
+/// | kind (1) | number of entries (2) | CAPACITY * (key length (4) | key (max key size)) |
+/// | CAPACITY * (value length (4) | value (max value size)) | (CAPACITY + 1) * child address (8) |
+///
+/// with unused slots left as they are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Layout {
+    pub max_key_size: u32,
+    pub max_value_size: u32,
+}
+
+impl Layout {
+    fn keys_offset(&self) -> usize {
+        3
+    }
+
+    fn values_offset(&self) -> usize {
+        self.keys_offset() + CAPACITY * (4 + self.max_key_size as usize)
+    }
+
+    fn children_offset(&self) -> usize {
+        self.values_offset() + CAPACITY * (4 + self.max_value_size as usize)
+    }
+
+    pub fn node_size(&self) -> u64 {
+        (self.children_offset() + (CAPACITY + 1) * 8) as u64
+    }
+}
+
+/// A node loaded in memory. Values are kept encoded until they are returned.
+pub(super) struct Node<K> {
+    pub address: u64,
+    pub keys: Vec<K>,
+    pub values: Vec<Vec<u8>>,
+    // Empty for leaves, one more than the keys otherwise.
+    pub children: Vec<u64>,
+}
+
+impl<K: Storable> Node<K> {
+    /// An empty leaf, not yet saved.
+    pub fn new(address: u64) -> Self {
+        Node {
+            address,
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.keys.len() == CAPACITY
+    }
+
+    pub fn load<M: StableMemory>(memory: &M, address: u64, layout: Layout) -> Self {
+        let mut buf = vec![0u8; layout.node_size() as usize];
+        memory.stable64_read(address, &mut buf);
+        let kind = buf[0];
+        let len = u16::from_le_bytes([buf[1], buf[2]]) as usize;
+
+        let read_slots = |offset: usize, slot_size: usize| {
+            (0..len)
+                .map(|i| {
+                    let start = offset + i * (4 + slot_size);
+                    let bytes_len = read_u32(&buf[start..]) as usize;
+                    buf[start + 4..start + 4 + bytes_len].to_vec()
+                })
+                .collect::<Vec<_>>()
+        };
+        let keys = read_slots(layout.keys_offset(), layout.max_key_size as usize)
+            .into_iter()
+            .map(K::from_bytes)
+            .collect();
+        let values = read_slots(layout.values_offset(), layout.max_value_size as usize);
+        let children = match kind {
+            LEAF => Vec::new(),
+            INTERNAL => (0..=len)
+                .map(|i| read_u64(&buf[layout.children_offset() + i * 8..]))
+                .collect(),
+            _ => panic!("Corrupted stable BTreeMap: unknown node kind {}.", kind),
+        };
+
+        Node {
+            address,
+            keys,
+            values,
+            children,
+        }
+    }
+
+    pub fn save<M: StableMemory>(&self, memory: &M, layout: Layout) {
+        let mut buf = vec![0u8; layout.node_size() as usize];
+        buf[0] = if self.is_leaf() { LEAF } else { INTERNAL };
+        buf[1..3].copy_from_slice(&(self.keys.len() as u16).to_le_bytes());
+
+        let mut write_slot = |offset: usize, bytes: &[u8]| {
+            buf[offset..offset + 4].copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+            buf[offset + 4..offset + 4 + bytes.len()].copy_from_slice(bytes);
+        };
+        for (i, key) in self.keys.iter().enumerate() {
+            let offset = layout.keys_offset() + i * (4 + layout.max_key_size as usize);
+            write_slot(offset, &key.to_bytes());
+        }
+        for (i, value) in self.values.iter().enumerate() {
+            let offset = layout.values_offset() + i * (4 + layout.max_value_size as usize);
+            write_slot(offset, value);
+        }
+        for (i, child) in self.children.iter().enumerate() {
+            let offset = layout.children_offset() + i * 8;
+            buf[offset..offset + 8].copy_from_slice(&child.to_le_bytes());
+        }
+        memory.stable64_write(self.address, &buf);
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
@@ -0,0 +1,135 @@
+use super::*;
+use crate::api::stable::tests::TestStableMemory;
+use crate::api::stable::Storable;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+// A deterministic shuffle of 0..n, so that insertions and removals hit every
+// rebalancing case.
+fn shuffled(n: u64) -> Vec<u64> {
+    let mut keys: Vec<u64> = (0..n).collect();
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    for i in (1..keys.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        keys.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+    keys
+}
+
+fn new_map() -> StableBTreeMap<u64, [u8; 8], TestStableMemory> {
+    StableBTreeMap::init(TestStableMemory::default())
+}
+
+#[test]
+fn matches_std_btreemap() {
+    let mut map = new_map();
+    let mut expected = BTreeMap::new();
+    for key in shuffled(1000) {
+        assert_eq!(map.insert(key, key.to_le_bytes()), None);
+        expected.insert(key, key.to_le_bytes());
+    }
+    assert_eq!(map.insert(7, [0; 8]), Some(7u64.to_le_bytes()));
+    expected.insert(7, [0; 8]);
+    assert_eq!(map.len(), 1000);
+    assert!(map.iter().eq(expected.clone().into_iter()));
+
+    for key in shuffled(1000).into_iter().filter(|key| key % 3 != 0) {
+        assert_eq!(map.remove(&key), expected.remove(&key));
+    }
+    assert_eq!(map.remove(&1), None);
+    assert_eq!(map.len(), expected.len() as u64);
+    assert!(map.iter().eq(expected.clone().into_iter()));
+    assert_eq!(map.get(&999), Some(999u64.to_le_bytes()));
+    assert!(!map.contains_key(&998));
+
+    for key in shuffled(1000) {
+        map.remove(&key);
+    }
+    assert!(map.is_empty());
+    assert_eq!(map.iter().next(), None);
+}
+
+#[test]
+fn freed_nodes_are_reused() {
+    let mut map = new_map();
+    for key in 0..500 {
+        map.insert(key, [0; 8]);
+    }
+    let end = map.end;
+    for key in 0..500 {
+        map.remove(&key);
+    }
+    for key in 0..500 {
+        map.insert(key, [0; 8]);
+    }
+    assert_eq!(map.end, end);
+}
+
+#[test]
+fn range_respects_bounds() {
+    let mut map = new_map();
+    for key in shuffled(200).into_iter().map(|key| key * 2) {
+        map.insert(key, [0; 8]);
+    }
+    let keys = |iter: Iter<'_, u64, [u8; 8], TestStableMemory>| {
+        iter.map(|(key, _)| key).collect::<Vec<_>>()
+    };
+    assert_eq!(keys(map.range(10..16)), vec![10, 12, 14]);
+    assert_eq!(keys(map.range(11..=16)), vec![12, 14, 16]);
+    assert_eq!(
+        keys(map.range((Bound::Excluded(100), Bound::Unbounded))).len(),
+        149
+    );
+    assert_eq!(keys(map.range(..4)), vec![0, 2]);
+    assert_eq!(keys(map.range(1000..)), Vec::<u64>::new());
+}
+
+#[test]
+fn contents_survive_reload() {
+    let memory = Rc::new(Mutex::new(Vec::new()));
+    {
+        let mut map = StableBTreeMap::init(TestStableMemory::new(memory.clone()));
+        for key in 0..100u64 {
+            map.insert(key, key as u8);
+        }
+    }
+
+    let map: StableBTreeMap<u64, u8, _> = StableBTreeMap::init(TestStableMemory::new(memory));
+    assert_eq!(map.len(), 100);
+    assert_eq!(map.get(&42), Some(42));
+}
+
+#[test]
+#[should_panic(expected = "bounds mismatch")]
+fn refuses_other_bounds() {
+    let memory = Rc::new(Mutex::new(Vec::new()));
+    StableBTreeMap::<u64, u8, _>::init(TestStableMemory::new(memory.clone()));
+    StableBTreeMap::<u64, u16, _>::init(TestStableMemory::new(memory));
+}
+
+#[test]
+#[should_panic(expected = "exceeds the bound")]
+fn refuses_oversized_values() {
+    struct Name(String);
+
+    impl Storable for Name {
+        fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+            self.0.to_bytes()
+        }
+
+        fn from_bytes(bytes: Vec<u8>) -> Self {
+            Name(String::from_bytes(bytes))
+        }
+    }
+
+    impl BoundedStorable for Name {
+        const MAX_SIZE: u32 = 4;
+    }
+
+    let mut map = StableBTreeMap::init(TestStableMemory::default());
+    map.insert(1u8, Name("bob".to_string()));
+    map.insert(2u8, Name("alice".to_string()));
+}
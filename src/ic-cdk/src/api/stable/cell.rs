@@ -0,0 +1,83 @@
+//! A single value stored in stable memory.
+use super::{layout, StableMemory, StableMemoryError, Storable};
+
+#[cfg(test)]
+mod tests;
+
+const MAGIC: &[u8; 3] = b"SCL";
+const LAYOUT_VERSION: u8 = 1;
+
+// Layout: | magic (3) | version (1) | value length (4) | value |
+const VALUE_LEN_OFFSET: u64 = 4;
+const VALUE_OFFSET: u64 = 8;
+
+/// A value kept in stable memory, such as the configuration of a canister.
+///
+/// The value is cached on the heap, so [get](StableCell::get) never touches stable memory,
+/// and written through on every [set](StableCell::set).
+///
+/// ```rust
+/// use ic_cdk::api::stable::{CanisterStableMemory, MemoryId, MemoryManager, StableCell};
+///
+/// let manager = MemoryManager::init(CanisterStableMemory::default());
+/// let mut owner = StableCell::init(manager.get(MemoryId::new(0)), String::new());
+/// owner.set("alice".to_string()).unwrap();
+/// assert_eq!(owner.get(), "alice");
+/// ```
+pub struct StableCell<T: Storable, M: StableMemory> {
+    memory: M,
+    value: T,
+}
+
+impl<T: Storable, M: StableMemory> StableCell<T, M> {
+    /// Loads the value stored in `memory`, or stores `default` if `memory` is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `memory` is not empty and does not hold a cell, or if it is empty and
+    /// cannot grow to hold `default`.
+    pub fn init(memory: M, default: T) -> Self {
+        if memory.stable64_size() == 0 {
+            write_value(&memory, &default)
+                .unwrap_or_else(|_| panic!("StableCell: out of stable memory."));
+            layout::write_magic(&memory, MAGIC, LAYOUT_VERSION);
+            return StableCell {
+                memory,
+                value: default,
+            };
+        }
+
+        layout::check_magic(&memory, MAGIC, LAYOUT_VERSION, "StableCell");
+        let len = layout::read_u32(&memory, VALUE_LEN_OFFSET);
+        let mut bytes = vec![0u8; len as usize];
+        memory.stable64_read(VALUE_OFFSET, &mut bytes);
+        StableCell {
+            memory,
+            value: T::from_bytes(bytes),
+        }
+    }
+
+    /// Returns the current value.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Replaces the value, returning the previous one.
+    ///
+    /// Fails, leaving the value unchanged, if stable memory cannot grow to hold the new value.
+    pub fn set(&mut self, value: T) -> Result<T, StableMemoryError> {
+        write_value(&self.memory, &value)?;
+        Ok(std::mem::replace(&mut self.value, value))
+    }
+}
+
+fn write_value<T: Storable, M: StableMemory>(
+    memory: &M,
+    value: &T,
+) -> Result<(), StableMemoryError> {
+    let bytes = value.to_bytes();
+    layout::ensure_size(memory, VALUE_OFFSET + bytes.len() as u64)?;
+    memory.stable64_write(VALUE_OFFSET, &bytes);
+    layout::write_u32(memory, VALUE_LEN_OFFSET, bytes.len() as u32);
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use super::*;
+use crate::api::stable::tests::TestStableMemory;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+#[test]
+fn value_survives_reload() {
+    let memory = Rc::new(Mutex::new(Vec::new()));
+    let mut cell = StableCell::init(TestStableMemory::new(memory.clone()), 1u64);
+    assert_eq!(*cell.get(), 1);
+    assert_eq!(cell.set(2).unwrap(), 1);
+
+    let cell = StableCell::init(TestStableMemory::new(memory), 0u64);
+    assert_eq!(*cell.get(), 2);
+}
+
+#[test]
+fn value_may_grow_and_shrink() {
+    let memory = Rc::new(Mutex::new(Vec::new()));
+    let mut cell = StableCell::init(TestStableMemory::new(memory.clone()), String::new());
+    cell.set("x".repeat(100_000)).unwrap();
+    cell.set("short".to_string()).unwrap();
+
+    let cell = StableCell::init(TestStableMemory::new(memory), String::new());
+    assert_eq!(cell.get(), "short");
+}
+
+#[test]
+#[should_panic(expected = "does not hold a StableCell")]
+fn refuses_other_structures() {
+    let memory = Rc::new(Mutex::new(Vec::new()));
+    crate::api::stable::StableVec::<u8, _>::init(TestStableMemory::new(memory.clone()));
+    StableCell::init(TestStableMemory::new(memory), 0u8);
+}
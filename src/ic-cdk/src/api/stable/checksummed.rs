@@ -0,0 +1,415 @@
+use super::{CanisterStableMemory, StableMemory, StableMemoryError, StableReader, StableWriter};
+use std::{error, fmt, io};
+
+#[cfg(test)]
+mod tests;
+
+const MAGIC: &[u8; 3] = b"CKS";
+const LAYOUT_VERSION: u8 = 1;
+const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+/// The largest block a [ChecksummedWriter] writes, and a [ChecksummedReader] accepts.
+pub const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+// A checksummed stream, written from the offset of the underlying writer:
+//
+// | magic (3) | version (1) | algorithm (1) |
+// | block length (4) | checksum | block | ...
+// | 0 (4) | checksum of nothing |
+//
+// The checksum of a block covers its index (8 bytes, little-endian) followed by its data, so
+// that blocks left over from an older stream are not mistaken for blocks of this one. The
+// empty block marks the end of the stream, so that a stream that was not finished is told
+// apart from one that ends early by design.
+
+/// The checksum protecting each block of a checksummed stream.
+///
+/// The variants depend on the enabled features, so matches must have a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChecksumAlgorithm {
+    /// CRC-32 (IEEE), 4 bytes per block. Detects accidental corruption.
+    Crc32,
+    /// SHA-256, 32 bytes per block. Requires the `checksum-sha256` feature.
+    #[cfg(feature = "checksum-sha256")]
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    fn id(self) -> u8 {
+        match self {
+            Self::Crc32 => 0,
+            #[cfg(feature = "checksum-sha256")]
+            Self::Sha256 => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Crc32),
+            #[cfg(feature = "checksum-sha256")]
+            1 => Some(Self::Sha256),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::Crc32 => 4,
+            #[cfg(feature = "checksum-sha256")]
+            Self::Sha256 => 32,
+        }
+    }
+
+    fn compute(self, block: u64, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Crc32 => {
+                let mut crc = Crc32Hasher::new();
+                crc.update(&block.to_le_bytes());
+                crc.update(bytes);
+                crc.finish().to_le_bytes().to_vec()
+            }
+            #[cfg(feature = "checksum-sha256")]
+            Self::Sha256 => {
+                use sha2::Digest;
+                let mut sha = sha2::Sha256::new();
+                sha.update(block.to_le_bytes());
+                sha.update(bytes);
+                sha.finalize().to_vec()
+            }
+        }
+    }
+}
+
+/// An error reading a checksummed stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumError {
+    /// The memory does not start with the header of a checksummed stream, or the stream uses
+    /// an algorithm that is not enabled.
+    BadHeader,
+    /// A block does not match its checksum, or has an invalid length.
+    Corrupt {
+        /// The index of the block, starting at 0.
+        block: u64,
+        /// The offset of the block in stable memory.
+        offset: u64,
+    },
+    /// The memory ends before the block, or the stream was never finished.
+    Truncated {
+        /// The index of the block, starting at 0.
+        block: u64,
+        /// The offset of the block in stable memory.
+        offset: u64,
+    },
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadHeader => f.write_str("Not a checksummed stream"),
+            Self::Corrupt { block, offset } => {
+                write!(f, "Block {} at offset {} is corrupt", block, offset)
+            }
+            Self::Truncated { block, offset } => {
+                write!(f, "Stream truncated at block {} (offset {})", block, offset)
+            }
+        }
+    }
+}
+
+impl error::Error for ChecksumError {}
+
+/// A writer that frames the data it writes to stable memory in checksummed blocks.
+///
+/// Data is buffered until a block is full. [finish](ChecksummedWriter::finish) must be
+/// called once everything is written: it writes the last block and the end of the stream,
+/// without which a [ChecksummedReader] reports the stream as truncated.
+///
+/// ```rust,no_run
+/// use ic_cdk::api::stable::{ChecksumAlgorithm, ChecksummedReader, ChecksummedWriter};
+/// # let state = (1u64,);
+///
+/// // In `pre_upgrade`:
+/// let mut writer = ChecksummedWriter::new(ChecksumAlgorithm::Crc32).unwrap();
+/// candid::write_args(&mut writer, state).unwrap();
+/// writer.finish().unwrap();
+///
+/// // In `post_upgrade`:
+/// let (bytes, result) = ChecksummedReader::new().unwrap().recover();
+/// if let Err(err) = result {
+///     ic_cdk::println!("{}; {} bytes recovered", err, bytes.len());
+/// }
+/// ```
+#[must_use = "the stream is truncated unless `finish` is called"]
+pub struct ChecksummedWriter<M: StableMemory = CanisterStableMemory> {
+    writer: StableWriter<M>,
+    algorithm: ChecksumAlgorithm,
+    block_size: usize,
+    buffer: Vec<u8>,
+    blocks: u64,
+}
+
+impl ChecksummedWriter {
+    /// Creates a writer starting at offset 0 of the stable memory of the canister.
+    pub fn new(algorithm: ChecksumAlgorithm) -> Result<Self, StableMemoryError> {
+        Self::with_writer(StableWriter::default(), algorithm)
+    }
+}
+
+impl<M: StableMemory> ChecksummedWriter<M> {
+    /// Creates a writer that writes through `writer`, from its current position.
+    pub fn with_writer(
+        mut writer: StableWriter<M>,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<Self, StableMemoryError> {
+        let mut header = [0u8; 5];
+        header[..3].copy_from_slice(MAGIC);
+        header[3] = LAYOUT_VERSION;
+        header[4] = algorithm.id();
+        writer.write(&header)?;
+        Ok(ChecksummedWriter {
+            writer,
+            algorithm,
+            block_size: DEFAULT_BLOCK_SIZE,
+            buffer: Vec::new(),
+            blocks: 0,
+        })
+    }
+
+    /// Sets the size of the blocks written from now on (64KiB by default).
+    ///
+    /// Smaller blocks lose less data to a corruption, at the cost of more checksums.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is 0 or exceeds [MAX_BLOCK_SIZE].
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert!(
+            block_size > 0 && block_size <= MAX_BLOCK_SIZE,
+            "Invalid block size {}.",
+            block_size
+        );
+        self.block_size = block_size;
+        self
+    }
+
+    /// Writes the buffered data and the end of the stream, and returns the underlying writer,
+    /// positioned after the stream.
+    pub fn finish(mut self) -> Result<StableWriter<M>, StableMemoryError> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.write_block()?;
+        Ok(self.writer)
+    }
+
+    // Writes the buffered data as a block. An empty buffer writes the end of the stream.
+    fn write_block(&mut self) -> Result<(), StableMemoryError> {
+        let len = self.buffer.len() as u32;
+        self.writer.write(&len.to_le_bytes())?;
+        self.writer
+            .write(&self.algorithm.compute(self.blocks, &self.buffer))?;
+        self.writer.write(&self.buffer)?;
+        self.buffer.clear();
+        self.blocks += 1;
+        Ok(())
+    }
+}
+
+impl<M: StableMemory> io::Write for ChecksummedWriter<M> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.block_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == self.block_size {
+            if let Err(e) = self.write_block() {
+                self.buffer.truncate(self.block_size - len);
+                return Err(io::Error::new(io::ErrorKind::OutOfMemory, e));
+            }
+        }
+        Ok(len)
+    }
+
+    /// Does nothing: blocks are only written once full, or by
+    /// [finish](ChecksummedWriter::finish).
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A reader of the streams written by a [ChecksummedWriter], verifying each block before
+/// returning its data.
+///
+/// Reading fails with an [io::Error] wrapping a [ChecksumError] at the first block that is
+/// corrupt or missing. [recover](ChecksummedReader::recover) instead returns the data of the
+/// valid blocks along with the error.
+pub struct ChecksummedReader<M: StableMemory = CanisterStableMemory> {
+    reader: StableReader<M>,
+    algorithm: ChecksumAlgorithm,
+    // The data of the current block, and how much of it was read.
+    block: Vec<u8>,
+    read: usize,
+    next_block: u64,
+    state: Result<bool, ChecksumError>,
+}
+
+impl ChecksummedReader {
+    /// Creates a reader starting at offset 0 of the stable memory of the canister.
+    pub fn new() -> Result<Self, ChecksumError> {
+        Self::with_reader(StableReader::default())
+    }
+}
+
+impl<M: StableMemory> ChecksummedReader<M> {
+    /// Creates a reader that reads through `reader`, from its current position.
+    pub fn with_reader(mut reader: StableReader<M>) -> Result<Self, ChecksumError> {
+        let mut header = [0u8; 5];
+        read_exact(&mut reader, &mut header).map_err(|_| ChecksumError::BadHeader)?;
+        if &header[..3] != MAGIC || header[3] != LAYOUT_VERSION {
+            return Err(ChecksumError::BadHeader);
+        }
+        let algorithm = ChecksumAlgorithm::from_id(header[4]).ok_or(ChecksumError::BadHeader)?;
+        Ok(ChecksummedReader {
+            reader,
+            algorithm,
+            block: Vec::new(),
+            read: 0,
+            next_block: 0,
+            state: Ok(false),
+        })
+    }
+
+    /// Reads the whole stream, returning the data of the blocks that could be verified,
+    /// and the error that stopped the reading, if any.
+    pub fn recover(mut self) -> (Vec<u8>, Result<(), ChecksumError>) {
+        let mut data = Vec::new();
+        loop {
+            data.extend_from_slice(&self.block[self.read..]);
+            self.read = self.block.len();
+            match self.next() {
+                Ok(true) => return (data, Ok(())),
+                Ok(false) => {}
+                Err(err) => return (data, Err(err)),
+            }
+        }
+    }
+
+    // Loads the next block, returning whether the stream has ended. Errors are final.
+    fn next(&mut self) -> Result<bool, ChecksumError> {
+        if self.state != Ok(false) {
+            return self.state;
+        }
+        let block = self.next_block;
+        let offset = self.reader.position();
+        self.state = self.load_block().map_err(|err| match err {
+            BlockError::Truncated => ChecksumError::Truncated { block, offset },
+            BlockError::Corrupt => ChecksumError::Corrupt { block, offset },
+        });
+        if self.state.is_err() {
+            self.block.clear();
+        }
+        self.read = 0;
+        self.next_block += 1;
+        self.state
+    }
+
+    fn load_block(&mut self) -> Result<bool, BlockError> {
+        let mut len = [0u8; 4];
+        read_exact(&mut self.reader, &mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        let mut checksum = vec![0u8; self.algorithm.size()];
+        read_exact(&mut self.reader, &mut checksum)?;
+        if len == 0 && checksum.iter().all(|byte| *byte == 0) {
+            // Nothing was ever written here.
+            return Err(BlockError::Truncated);
+        }
+        if len > MAX_BLOCK_SIZE {
+            return Err(BlockError::Corrupt);
+        }
+        self.block.resize(len, 0);
+        read_exact(&mut self.reader, &mut self.block)?;
+        if self.algorithm.compute(self.next_block, &self.block) != checksum {
+            return Err(BlockError::Corrupt);
+        }
+        Ok(self.block.is_empty())
+    }
+}
+
+enum BlockError {
+    Truncated,
+    Corrupt,
+}
+
+impl<M: StableMemory> io::Read for ChecksummedReader<M> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.block.len() {
+            match self.next() {
+                Ok(true) => return Ok(0),
+                Ok(false) => {}
+                Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+            }
+        }
+        let len = buf.len().min(self.block.len() - self.read);
+        buf[..len].copy_from_slice(&self.block[self.read..self.read + len]);
+        self.read += len;
+        Ok(len)
+    }
+}
+
+// Fails if the memory ends before `buf` is filled.
+fn read_exact<M: StableMemory>(
+    reader: &mut StableReader<M>,
+    mut buf: &mut [u8],
+) -> Result<(), BlockError> {
+    while !buf.is_empty() {
+        match reader.read(buf) {
+            Ok(0) | Err(_) => return Err(BlockError::Truncated),
+            Ok(n) => buf = &mut buf[n..],
+        }
+    }
+    Ok(())
+}
+
+// CRC-32 (IEEE) of `bytes`.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32Hasher::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+// CRC-32 (IEEE), computed a byte at a time with a lookup table, over data fed in pieces.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Crc32Hasher(u32);
+
+impl Crc32Hasher {
+    pub(crate) fn new() -> Self {
+        Crc32Hasher(!0)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = CRC32_TABLE[((self.0 ^ *byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub(crate) fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
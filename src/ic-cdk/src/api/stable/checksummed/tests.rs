@@ -0,0 +1,104 @@
+use super::*;
+use crate::api::stable::VectorMemory;
+use std::io::{Read, Write};
+
+const HEADER: u64 = 5;
+// Length and CRC-32 of each block.
+const FRAMING: u64 = 8;
+
+fn write_stream(
+    memory: &VectorMemory,
+    data: &[u8],
+    block_size: usize,
+) -> ChecksummedWriter<VectorMemory> {
+    let writer = StableWriter::with_memory(memory.clone(), 0);
+    let mut writer = ChecksummedWriter::with_writer(writer, ChecksumAlgorithm::Crc32)
+        .unwrap()
+        .with_block_size(block_size);
+    writer.write_all(data).unwrap();
+    writer
+}
+
+fn reader(memory: &VectorMemory) -> ChecksummedReader<VectorMemory> {
+    ChecksummedReader::with_reader(StableReader::with_memory(memory.clone(), 0)).unwrap()
+}
+
+fn data() -> Vec<u8> {
+    (0..1000u32).map(|i| i as u8).collect()
+}
+
+#[test]
+fn crc32_matches_reference() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    let mut crc = Crc32Hasher::new();
+    crc.update(b"1234");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), 0xcbf4_3926);
+}
+
+#[test]
+fn round_trip() {
+    let memory = VectorMemory::default();
+    let mut writer = write_stream(&memory, &data(), 300).finish().unwrap();
+    writer.write(b"trailing").unwrap();
+
+    let mut read = Vec::new();
+    reader(&memory).read_to_end(&mut read).unwrap();
+    assert_eq!(read, data());
+    assert_eq!(reader(&memory).recover(), (data(), Ok(())));
+}
+
+#[test]
+fn reports_corrupt_block_and_recovers_prefix() {
+    let memory = VectorMemory::default();
+    write_stream(&memory, &data(), 300).finish().unwrap();
+    let third_block = HEADER + 2 * (FRAMING + 300);
+    let mut byte = [0u8];
+    memory.stable64_read(third_block + FRAMING + 7, &mut byte);
+    memory.stable64_write(third_block + FRAMING + 7, &[byte[0] ^ 1]);
+
+    let err = reader(&memory).read_to_end(&mut Vec::new()).unwrap_err();
+    let expected = ChecksumError::Corrupt {
+        block: 2,
+        offset: third_block,
+    };
+    assert_eq!(
+        err.get_ref().unwrap().downcast_ref::<ChecksumError>(),
+        Some(&expected)
+    );
+    assert_eq!(
+        reader(&memory).recover(),
+        (data()[..600].to_vec(), Err(expected))
+    );
+}
+
+#[test]
+fn reports_unfinished_stream() {
+    let memory = VectorMemory::default();
+    drop(write_stream(&memory, &data(), 300));
+    let (recovered, result) = reader(&memory).recover();
+    assert_eq!(recovered, data()[..900].to_vec());
+    assert!(matches!(
+        result,
+        Err(ChecksumError::Truncated { block: 3, .. })
+    ));
+}
+
+#[test]
+fn rejects_foreign_memory() {
+    let memory = VectorMemory::from_bytes(b"DIDL".to_vec());
+    let result = ChecksummedReader::with_reader(StableReader::with_memory(memory, 0));
+    assert_eq!(result.err(), Some(ChecksumError::BadHeader));
+}
+
+#[cfg(feature = "checksum-sha256")]
+#[test]
+fn round_trip_sha256() {
+    let memory = VectorMemory::default();
+    let writer = StableWriter::with_memory(memory.clone(), 0);
+    let mut writer = ChecksummedWriter::with_writer(writer, ChecksumAlgorithm::Sha256).unwrap();
+    writer.write_all(&data()).unwrap();
+    writer.finish().unwrap();
+    assert_eq!(reader(&memory).recover(), (data(), Ok(())));
+}
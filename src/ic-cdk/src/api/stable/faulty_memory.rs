@@ -0,0 +1,158 @@
+use super::{StableMemory, StableMemoryError, WASM_PAGE_SIZE_IN_BYTES};
+use std::cell::Cell;
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+/// A [StableMemory] wrapper that injects faults, to test how code handles running out of
+/// stable memory or finding it damaged.
+///
+/// Faults can be configured at any time, also after the memory has been moved into a
+/// structure: clones share their configuration.
+///
+/// ```rust
+/// use ic_cdk::api::stable::{FaultyMemory, StableMemory, StableWriter, VectorMemory};
+///
+/// let memory = FaultyMemory::new(VectorMemory::default());
+/// let mut writer = StableWriter::with_memory(memory.clone(), 0);
+/// memory.fail_grow_beyond(1);
+/// assert!(writer.write(&[0; 65536]).is_ok());
+/// assert!(writer.write(&[0]).is_err());
+/// ```
+#[derive(Clone)]
+pub struct FaultyMemory<M: StableMemory> {
+    inner: M,
+    max_pages: Rc<Cell<Option<u64>>>,
+    size: Rc<Cell<Option<u64>>>,
+}
+
+impl<M: StableMemory> FaultyMemory<M> {
+    /// Wraps `inner`, without any fault until one is configured.
+    pub fn new(inner: M) -> Self {
+        FaultyMemory {
+            inner,
+            max_pages: Rc::new(Cell::new(None)),
+            size: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Makes grows fail with [StableMemoryError::OutOfMemory] when they would take the
+    /// memory beyond `pages` pages.
+    pub fn fail_grow_beyond(&self, pages: u64) {
+        self.max_pages.set(Some(pages));
+    }
+
+    /// Simulates the loss of the contents from byte `len` on: the memory shrinks to the
+    /// pages needed for `len` bytes, and the rest of the last page is zeroed.
+    ///
+    /// The memory keeps its reduced size from then on, growing from there, and accesses
+    /// beyond it panic like accesses beyond the end of the stable memory trap. The pages
+    /// dropped are left untouched in the wrapped memory, but are zeroed before being
+    /// handed out again by a grow.
+    pub fn truncate(&self, len: u64) {
+        let page_size = WASM_PAGE_SIZE_IN_BYTES as u64;
+        let pages = self.stable64_size().min((len + page_size - 1) / page_size);
+        self.size.set(Some(pages));
+        self.zero(len, pages * page_size);
+    }
+
+    /// Removes the limit set with [fail_grow_beyond](Self::fail_grow_beyond).
+    ///
+    /// A truncation cannot be undone: the contents lost stay lost.
+    pub fn heal(&self) {
+        self.max_pages.set(None);
+    }
+
+    /// Returns the wrapped memory.
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+
+    fn check_grow(&self, new_pages: u64) -> Result<(), StableMemoryError> {
+        match self.max_pages.get() {
+            Some(max) if self.stable64_size().saturating_add(new_pages) > max => {
+                Err(StableMemoryError::OutOfMemory)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_bounds(&self, offset: u64, len: usize) {
+        let size = self.stable64_size() * WASM_PAGE_SIZE_IN_BYTES as u64;
+        if offset
+            .checked_add(len as u64)
+            .map_or(true, |end| end > size)
+        {
+            panic!("FaultyMemory: access out of bounds");
+        }
+    }
+
+    fn zero(&self, start: u64, end: u64) {
+        const CHUNK: u64 = WASM_PAGE_SIZE_IN_BYTES as u64;
+        let zeros = [0; CHUNK as usize];
+        let mut offset = start;
+        while offset < end {
+            let len = (end - offset).min(CHUNK);
+            self.inner.stable64_write(offset, &zeros[..len as usize]);
+            offset += len;
+        }
+    }
+}
+
+impl<M: StableMemory> StableMemory for FaultyMemory<M> {
+    fn stable_size(&self) -> u32 {
+        self.stable64_size().min(u32::MAX as u64) as u32
+    }
+
+    fn stable64_size(&self) -> u64 {
+        self.size
+            .get()
+            .unwrap_or_else(|| self.inner.stable64_size())
+    }
+
+    fn stable_grow(&self, new_pages: u32) -> Result<u32, StableMemoryError> {
+        if self.size.get().is_none() {
+            self.check_grow(new_pages as u64)?;
+            return self.inner.stable_grow(new_pages);
+        }
+        let old_pages = self.stable64_grow(new_pages as u64)?;
+        Ok(old_pages as u32)
+    }
+
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        self.check_grow(new_pages)?;
+        let old_pages = match self.size.get() {
+            Some(pages) => pages,
+            None => return self.inner.stable64_grow(new_pages),
+        };
+        let pages = old_pages + new_pages;
+        let inner_pages = self.inner.stable64_size();
+        if pages > inner_pages {
+            self.inner.stable64_grow(pages - inner_pages)?;
+        }
+        // Reused pages of the wrapped memory still hold what was lost.
+        let page_size = WASM_PAGE_SIZE_IN_BYTES as u64;
+        self.zero(old_pages * page_size, pages.min(inner_pages) * page_size);
+        self.size.set(Some(pages));
+        Ok(old_pages)
+    }
+
+    fn stable_write(&self, offset: u32, buf: &[u8]) {
+        self.stable64_write(offset as u64, buf)
+    }
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]) {
+        self.check_bounds(offset, buf.len());
+        self.inner.stable64_write(offset, buf)
+    }
+
+    fn stable_read(&self, offset: u32, buf: &mut [u8]) {
+        self.stable64_read(offset as u64, buf)
+    }
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]) {
+        self.check_bounds(offset, buf.len());
+        self.inner.stable64_read(offset, buf)
+    }
+}
@@ -0,0 +1,104 @@
+use super::*;
+use crate::api::stable::{
+    BufferedStableWriter, StableReader, StableVec, StableWriter, VectorMemory,
+};
+use std::io::{Read, Write};
+
+#[test]
+fn writer_reports_out_of_memory() {
+    let memory = FaultyMemory::new(VectorMemory::default());
+    memory.fail_grow_beyond(2);
+    let mut writer = StableWriter::with_memory(memory.clone(), 0);
+    writer.write_all(&[1; 2 * 65536]).unwrap();
+    assert!(matches!(
+        writer.write(&[1]),
+        Err(StableMemoryError::OutOfMemory)
+    ));
+
+    memory.heal();
+    writer.write_all(&[1]).unwrap();
+    assert_eq!(memory.into_inner().stable64_size(), 3);
+}
+
+#[test]
+fn buffered_writer_reports_out_of_memory_on_flush() {
+    let memory = FaultyMemory::new(VectorMemory::default());
+    memory.fail_grow_beyond(0);
+    let writer = StableWriter::with_memory(memory, 0);
+    let mut writer = BufferedStableWriter::with_writer(1024, writer);
+    writer.write_all(&[1; 10]).unwrap();
+    let err = writer.flush().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::OutOfMemory);
+}
+
+#[test]
+fn structures_report_out_of_memory() {
+    let memory = FaultyMemory::new(VectorMemory::default());
+    memory.fail_grow_beyond(1);
+    let mut vec: StableVec<[u8; 1000], _> = StableVec::init(memory);
+    let pushed = (0..100)
+        .take_while(|_| vec.push(&[7; 1000]).is_ok())
+        .count();
+    assert_eq!(pushed, 65);
+    assert_eq!(vec.len(), 65);
+}
+
+#[test]
+fn truncation_loses_the_tail() {
+    let vector = VectorMemory::default();
+    let mut writer = StableWriter::with_memory(vector.clone(), 0);
+    writer.write_all(&[1; 3 * 65536]).unwrap();
+
+    let memory = FaultyMemory::new(vector.clone());
+    memory.truncate(65536 + 10);
+    assert_eq!(memory.stable64_size(), 2);
+    let mut contents = Vec::new();
+    StableReader::with_memory(memory, 0)
+        .read_to_end(&mut contents)
+        .unwrap();
+    assert_eq!(contents.len(), 2 * 65536);
+    assert!(contents[..65546].iter().all(|byte| *byte == 1));
+    assert!(contents[65546..].iter().all(|byte| *byte == 0));
+    assert!(vector.to_vec()[2 * 65536..].iter().all(|byte| *byte == 1));
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn truncated_memory_traps_out_of_bounds() {
+    let vector = VectorMemory::default();
+    vector.stable64_grow(3).unwrap();
+    let memory = FaultyMemory::new(vector);
+    memory.truncate(10);
+    memory.stable64_read(65536, &mut [0; 1]);
+}
+
+#[test]
+fn truncated_memory_grows_from_its_size() {
+    let vector = VectorMemory::default();
+    let mut writer = StableWriter::with_memory(vector.clone(), 0);
+    writer.write_all(&[1; 2 * 65536]).unwrap();
+
+    let memory = FaultyMemory::new(vector.clone());
+    memory.truncate(10);
+    assert_eq!(memory.stable64_grow(2).unwrap(), 1);
+    assert_eq!(memory.stable64_size(), 3);
+    assert_eq!(vector.stable64_size(), 3);
+    let mut contents = vec![1; 3 * 65536 - 10];
+    memory.stable64_read(10, &mut contents);
+    assert!(contents.iter().all(|byte| *byte == 0));
+
+    memory.stable64_write(3 * 65536 - 1, &[2]);
+    memory.fail_grow_beyond(3);
+    assert!(matches!(
+        memory.stable64_grow(1),
+        Err(StableMemoryError::OutOfMemory)
+    ));
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn vector_memory_traps_out_of_bounds() {
+    let memory = VectorMemory::from_bytes(vec![1; 10]);
+    assert_eq!(memory.stable64_size(), 1);
+    memory.stable64_write(65530, &[0; 10]);
+}
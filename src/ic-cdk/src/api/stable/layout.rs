@@ -0,0 +1,60 @@
+//! Helpers shared by the structures that store their data in a [StableMemory].
+//!
+//! Every structure starts its memory with a 3-byte magic and a 1-byte layout version, so
+//! that reopening a memory written by another structure, or by an incompatible version,
+//! is detected before any data is read.
+use super::{StableMemory, StableMemoryError, WASM_PAGE_SIZE_IN_BYTES};
+
+pub(crate) fn write_magic<M: StableMemory>(memory: &M, magic: &[u8; 3], version: u8) {
+    let mut header = [0u8; 4];
+    header[..3].copy_from_slice(magic);
+    header[3] = version;
+    memory.stable64_write(0, &header);
+}
+
+/// Panics unless `memory` starts with `magic` and `version`. `name` names the structure in
+/// the panic message.
+pub(crate) fn check_magic<M: StableMemory>(memory: &M, magic: &[u8; 3], version: u8, name: &str) {
+    let mut header = [0u8; 4];
+    memory.stable64_read(0, &mut header);
+    if &header[..3] != magic {
+        panic!("Bad magic: the stable memory does not hold a {}.", name);
+    }
+    if header[3] != version {
+        panic!("Unsupported {} layout version {}.", name, header[3]);
+    }
+}
+
+pub(crate) fn read_u32<M: StableMemory>(memory: &M, offset: u64) -> u32 {
+    let mut bytes = [0u8; 4];
+    memory.stable64_read(offset, &mut bytes);
+    u32::from_le_bytes(bytes)
+}
+
+pub(crate) fn write_u32<M: StableMemory>(memory: &M, offset: u64, value: u32) {
+    memory.stable64_write(offset, &value.to_le_bytes());
+}
+
+pub(crate) fn read_u64<M: StableMemory>(memory: &M, offset: u64) -> u64 {
+    let mut bytes = [0u8; 8];
+    memory.stable64_read(offset, &mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+pub(crate) fn write_u64<M: StableMemory>(memory: &M, offset: u64, value: u64) {
+    memory.stable64_write(offset, &value.to_le_bytes());
+}
+
+/// Grows `memory` so that it holds at least `bytes` bytes.
+pub(crate) fn ensure_size<M: StableMemory>(
+    memory: &M,
+    bytes: u64,
+) -> Result<(), StableMemoryError> {
+    let page_size = WASM_PAGE_SIZE_IN_BYTES as u64;
+    let required_pages = (bytes + page_size - 1) / page_size;
+    let current_pages = memory.stable64_size();
+    if required_pages > current_pages {
+        memory.stable64_grow(required_pages - current_pages)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,186 @@
+//! An append-only log stored in stable memory.
+use super::{layout, StableMemory, StableMemoryError, Storable, WASM_PAGE_SIZE_IN_BYTES};
+use std::marker::PhantomData;
+
+#[cfg(test)]
+mod tests;
+
+const INDEX_MAGIC: &[u8; 3] = b"SLI";
+const DATA_MAGIC: &[u8; 3] = b"SLD";
+const LAYOUT_VERSION: u8 = 1;
+
+// Index layout: | magic (3) | version (1) | reserved (4) | length (8) | end of each entry (8 * length) |
+// Data layout: | magic (3) | version (1) | reserved (4) | entries |
+//
+// The end of each entry is an offset in the data memory, so entry `i` spans from the end
+// of entry `i - 1` (or DATA_OFFSET) to its own end.
+const LEN_OFFSET: u64 = 8;
+const ENTRIES_OFFSET: u64 = 16;
+const DATA_OFFSET: u64 = 8;
+
+/// A log of entries of any size, stored in stable memory.
+///
+/// Entries are encoded with [Storable](super::Storable) and can only be appended. The log
+/// uses two memories: one holds the entries back to back, the other the position of each
+/// entry, so that any entry can be read in constant time.
+///
+/// ```rust
+/// use ic_cdk::api::stable::{CanisterStableMemory, MemoryId, MemoryManager, StableLog};
+///
+/// let manager = MemoryManager::init(CanisterStableMemory::default());
+/// let mut events: StableLog<String, _> =
+///     StableLog::init(manager.get(MemoryId::new(0)), manager.get(MemoryId::new(1)));
+/// let index = events.append(&"deposit".to_string()).unwrap();
+/// assert_eq!(events.get(index).as_deref(), Some("deposit"));
+/// ```
+pub struct StableLog<T: Storable, M: StableMemory> {
+    index_memory: M,
+    data_memory: M,
+    len: u64,
+    // The end of the last entry in the data memory.
+    end: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Storable, M: StableMemory> StableLog<T, M> {
+    /// Loads the log stored in `index_memory` and `data_memory`, or initializes an empty
+    /// one if both are empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memories are not empty and do not hold a log, or if only one of them
+    /// is empty.
+    pub fn init(index_memory: M, data_memory: M) -> Self {
+        let index_empty = index_memory.stable64_size() == 0;
+        let data_empty = data_memory.stable64_size() == 0;
+        if index_empty != data_empty {
+            panic!(
+                "StableLog: the {} memory is empty but the {} memory is not.",
+                if index_empty { "index" } else { "data" },
+                if index_empty { "data" } else { "index" },
+            );
+        }
+        if index_empty {
+            layout::ensure_size(&index_memory, ENTRIES_OFFSET)
+                .and_then(|()| layout::ensure_size(&data_memory, DATA_OFFSET))
+                .unwrap_or_else(|_| panic!("StableLog: out of stable memory."));
+            layout::write_magic(&index_memory, INDEX_MAGIC, LAYOUT_VERSION);
+            layout::write_u64(&index_memory, LEN_OFFSET, 0);
+            layout::write_magic(&data_memory, DATA_MAGIC, LAYOUT_VERSION);
+            return StableLog {
+                index_memory,
+                data_memory,
+                len: 0,
+                end: DATA_OFFSET,
+                _marker: PhantomData,
+            };
+        }
+
+        layout::check_magic(
+            &index_memory,
+            INDEX_MAGIC,
+            LAYOUT_VERSION,
+            "StableLog index",
+        );
+        layout::check_magic(&data_memory, DATA_MAGIC, LAYOUT_VERSION, "StableLog");
+        let mut log = StableLog {
+            len: layout::read_u64(&index_memory, LEN_OFFSET),
+            index_memory,
+            data_memory,
+            end: DATA_OFFSET,
+            _marker: PhantomData,
+        };
+        let page_size = WASM_PAGE_SIZE_IN_BYTES as u64;
+        let index_size = log.index_memory.stable64_size() * page_size;
+        let index_end = log
+            .len
+            .checked_mul(8)
+            .map(|n| n.saturating_add(ENTRIES_OFFSET));
+        if index_end.map_or(true, |end| end > index_size) {
+            panic!(
+                "StableLog: the index memory is too small to hold {} entries.",
+                log.len
+            );
+        }
+        if log.len > 0 {
+            log.end = log.entry_end(log.len - 1);
+        }
+        if log.end < DATA_OFFSET || log.end > log.data_memory.stable64_size() * page_size {
+            panic!(
+                "StableLog: the data memory is too small for the entries of the index, which end at byte {}.",
+                log.end
+            );
+        }
+        log
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the log is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends an entry, returning its index.
+    ///
+    /// Fails, leaving the log unchanged, if stable memory cannot grow to hold it.
+    pub fn append(&mut self, entry: &T) -> Result<u64, StableMemoryError> {
+        let bytes = entry.to_bytes();
+        let end = self.end + bytes.len() as u64;
+        layout::ensure_size(&self.data_memory, end)?;
+        layout::ensure_size(&self.index_memory, ENTRIES_OFFSET + 8 * (self.len + 1))?;
+
+        self.data_memory.stable64_write(self.end, &bytes);
+        layout::write_u64(&self.index_memory, ENTRIES_OFFSET + 8 * self.len, end);
+        let index = self.len;
+        self.len += 1;
+        self.end = end;
+        layout::write_u64(&self.index_memory, LEN_OFFSET, self.len);
+        Ok(index)
+    }
+
+    /// Returns the entry at `index`, or `None` if `index` is out of bounds.
+    pub fn get(&self, index: u64) -> Option<T> {
+        if index >= self.len {
+            return None;
+        }
+        let start = match index {
+            0 => DATA_OFFSET,
+            _ => self.entry_end(index - 1),
+        };
+        let mut bytes = vec![0u8; (self.entry_end(index) - start) as usize];
+        self.data_memory.stable64_read(start, &mut bytes);
+        Some(T::from_bytes(bytes))
+    }
+
+    /// Iterates over the entries, reading each one from stable memory as it is reached.
+    pub fn iter(&self) -> Iter<'_, T, M> {
+        Iter {
+            log: self,
+            index: 0,
+        }
+    }
+
+    fn entry_end(&self, index: u64) -> u64 {
+        layout::read_u64(&self.index_memory, ENTRIES_OFFSET + 8 * index)
+    }
+}
+
+/// An iterator over the entries of a [StableLog].
+pub struct Iter<'a, T: Storable, M: StableMemory> {
+    log: &'a StableLog<T, M>,
+    index: u64,
+}
+
+impl<'a, T: Storable, M: StableMemory> Iterator for Iter<'a, T, M> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let entry = self.log.get(self.index)?;
+        self.index += 1;
+        Some(entry)
+    }
+}
@@ -0,0 +1,70 @@
+use super::*;
+use crate::api::stable::tests::TestStableMemory;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+fn memory() -> Rc<Mutex<Vec<u8>>> {
+    Rc::new(Mutex::new(Vec::new()))
+}
+
+#[test]
+fn entries_survive_reload() {
+    let (index, data) = (memory(), memory());
+    let entries = ["", "a", &"b".repeat(70_000), "c"];
+    {
+        let mut log = StableLog::init(
+            TestStableMemory::new(index.clone()),
+            TestStableMemory::new(data.clone()),
+        );
+        for (i, entry) in entries.iter().enumerate() {
+            assert_eq!(log.append(&entry.to_string()).unwrap(), i as u64);
+        }
+    }
+
+    let mut log: StableLog<String, _> =
+        StableLog::init(TestStableMemory::new(index), TestStableMemory::new(data));
+    assert_eq!(log.len(), 4);
+    assert!(log.iter().eq(entries.iter().map(|entry| entry.to_string())));
+    assert_eq!(log.get(4), None);
+    log.append(&"d".to_string()).unwrap();
+    assert_eq!(log.get(4).as_deref(), Some("d"));
+}
+
+#[test]
+#[should_panic(expected = "does not hold a StableLog index")]
+fn refuses_swapped_memories() {
+    let (index, data) = (memory(), memory());
+    StableLog::<String, _>::init(
+        TestStableMemory::new(index.clone()),
+        TestStableMemory::new(data.clone()),
+    );
+    StableLog::<String, _>::init(TestStableMemory::new(data), TestStableMemory::new(index));
+}
+
+#[test]
+#[should_panic(expected = "the index memory is empty but the data memory is not")]
+fn refuses_data_without_index() {
+    let (index, data) = (memory(), memory());
+    StableLog::<String, _>::init(
+        TestStableMemory::new(index),
+        TestStableMemory::new(data.clone()),
+    );
+    StableLog::<String, _>::init(TestStableMemory::new(memory()), TestStableMemory::new(data));
+}
+
+#[test]
+#[should_panic(expected = "the data memory is too small for the entries of the index")]
+fn refuses_index_pointing_past_data() {
+    let (index, data) = (memory(), memory());
+    let mut log = StableLog::init(
+        TestStableMemory::new(index.clone()),
+        TestStableMemory::new(data),
+    );
+    log.append(&"a".repeat(70_000)).unwrap();
+    let fresh = memory();
+    StableLog::<String, _>::init(
+        TestStableMemory::new(memory()),
+        TestStableMemory::new(fresh.clone()),
+    );
+    StableLog::<String, _>::init(TestStableMemory::new(index), TestStableMemory::new(fresh));
+}
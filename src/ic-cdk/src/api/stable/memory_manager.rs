@@ -0,0 +1,348 @@
+use super::{StableMemory, StableMemoryError, WASM_PAGE_SIZE_IN_BYTES};
+use std::cell::RefCell;
+use std::cmp::min;
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+const MAGIC: &[u8; 3] = b"MGR";
+const LAYOUT_VERSION: u8 = 1;
+
+/// The number of virtual memories a [MemoryManager] can hand out.
+pub const MAX_NUM_MEMORIES: u8 = 255;
+
+// Marks a bucket that is not allocated to any memory.
+const UNALLOCATED_BUCKET: u8 = MAX_NUM_MEMORIES;
+
+const MAX_NUM_BUCKETS: u64 = 32768;
+const DEFAULT_BUCKET_SIZE_IN_PAGES: u16 = 128;
+
+// Header layout, at offset 0 of the underlying memory:
+//
+// | magic (3) | version (1) | allocated buckets (2) | bucket size in pages (2) | reserved (32) |
+// | size in pages of each memory (8 * MAX_NUM_MEMORIES) |
+// | owner of each bucket (MAX_NUM_BUCKETS) |
+//
+// All integers are little-endian. The header fits in the first page; buckets start
+// at the second one.
+const ALLOCATED_BUCKETS_OFFSET: u64 = 4;
+const BUCKET_SIZE_OFFSET: u64 = 6;
+const MEMORY_SIZES_OFFSET: u64 = 40;
+const BUCKET_OWNERS_OFFSET: u64 = MEMORY_SIZES_OFFSET + 8 * MAX_NUM_MEMORIES as u64;
+const HEADER_SIZE: u64 = BUCKET_OWNERS_OFFSET + MAX_NUM_BUCKETS;
+const DATA_OFFSET: u64 = WASM_PAGE_SIZE_IN_BYTES as u64;
+
+/// The identifier of a [VirtualMemory], between 0 and [MAX_NUM_MEMORIES] (excluded).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemoryId(u8);
+
+impl MemoryId {
+    /// Creates a memory id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not less than [MAX_NUM_MEMORIES].
+    pub const fn new(id: u8) -> Self {
+        assert!(id < MAX_NUM_MEMORIES, "memory id out of range");
+        MemoryId(id)
+    }
+}
+
+/// Partitions a stable memory into independent [VirtualMemory] instances.
+///
+/// Virtual memories grow in buckets of pages (128 pages, i.e. 8MiB, by default) taken from
+/// the underlying memory as needed, so that several data structures can share stable memory
+/// without coordinating offsets. Which bucket belongs to which memory is recorded in a header
+/// at the start of the underlying memory, so the same memories are found again after an upgrade.
+///
+/// ```rust
+/// use ic_cdk::api::stable::{CanisterStableMemory, MemoryId, MemoryManager, StableMemory};
+///
+/// let manager = MemoryManager::init(CanisterStableMemory::default());
+/// let (users, log) = (manager.get(MemoryId::new(0)), manager.get(MemoryId::new(1)));
+/// users.stable64_grow(1).unwrap();
+/// log.stable64_grow(1).unwrap();
+/// users.stable64_write(0, b"alice");
+/// log.stable64_write(0, b"created alice");
+/// ```
+pub struct MemoryManager<M: StableMemory> {
+    inner: Rc<RefCell<Inner<M>>>,
+}
+
+impl<M: StableMemory> MemoryManager<M> {
+    /// Loads the memory manager from `memory`, or initializes one if `memory` is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `memory` is not empty and does not start with a memory manager header.
+    pub fn init(memory: M) -> Self {
+        Self::init_with_bucket_size(memory, DEFAULT_BUCKET_SIZE_IN_PAGES)
+    }
+
+    /// Like [init](MemoryManager::init), with buckets of `bucket_size_in_pages` pages.
+    ///
+    /// The bucket size is fixed when the manager is created; when loading an existing one,
+    /// the size stored in its header is used instead.
+    pub fn init_with_bucket_size(memory: M, bucket_size_in_pages: u16) -> Self {
+        assert!(bucket_size_in_pages > 0, "bucket size must not be zero");
+        let inner = if memory.stable64_size() == 0 {
+            Inner::new(memory, bucket_size_in_pages)
+        } else {
+            Inner::load(memory)
+        };
+        MemoryManager {
+            inner: Rc::new(RefCell::new(inner)),
+        }
+    }
+
+    /// Returns the virtual memory with the given id.
+    ///
+    /// Handles to the same id share the same contents.
+    pub fn get(&self, id: MemoryId) -> VirtualMemory<M> {
+        VirtualMemory {
+            id,
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+/// A memory handed out by a [MemoryManager].
+///
+/// It behaves like a stable memory of its own: it starts empty and must be grown before
+/// being written to.
+pub struct VirtualMemory<M: StableMemory> {
+    id: MemoryId,
+    inner: Rc<RefCell<Inner<M>>>,
+}
+
+impl<M: StableMemory> Clone for VirtualMemory<M> {
+    fn clone(&self) -> Self {
+        VirtualMemory {
+            id: self.id,
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+impl<M: StableMemory> StableMemory for VirtualMemory<M> {
+    fn stable_size(&self) -> u32 {
+        min(self.stable64_size(), u32::MAX as u64) as u32
+    }
+
+    fn stable64_size(&self) -> u64 {
+        self.inner.borrow().sizes_in_pages[self.id.0 as usize]
+    }
+
+    fn stable_grow(&self, new_pages: u32) -> Result<u32, StableMemoryError> {
+        self.stable64_grow(new_pages as u64)
+            .map(|old_size| old_size as u32)
+    }
+
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        self.inner.borrow_mut().grow(self.id, new_pages)
+    }
+
+    fn stable_write(&self, offset: u32, buf: &[u8]) {
+        self.stable64_write(offset as u64, buf)
+    }
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]) {
+        self.inner.borrow().write(self.id, offset, buf)
+    }
+
+    fn stable_read(&self, offset: u32, buf: &mut [u8]) {
+        self.stable64_read(offset as u64, buf)
+    }
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]) {
+        self.inner.borrow().read(self.id, offset, buf)
+    }
+}
+
+struct Inner<M: StableMemory> {
+    memory: M,
+    bucket_size_in_pages: u16,
+    allocated_buckets: u16,
+    sizes_in_pages: Vec<u64>,
+    // The buckets of each memory, in order.
+    buckets: Vec<Vec<u16>>,
+}
+
+impl<M: StableMemory> Inner<M> {
+    fn new(memory: M, bucket_size_in_pages: u16) -> Self {
+        if memory.stable64_grow(1).is_err() {
+            panic!("Could not allocate the memory manager header.");
+        }
+        let mut header = vec![0u8; HEADER_SIZE as usize];
+        header[..3].copy_from_slice(MAGIC);
+        header[3] = LAYOUT_VERSION;
+        let bucket_size_offset = BUCKET_SIZE_OFFSET as usize;
+        header[bucket_size_offset..bucket_size_offset + 2]
+            .copy_from_slice(&bucket_size_in_pages.to_le_bytes());
+        for owner in &mut header[BUCKET_OWNERS_OFFSET as usize..] {
+            *owner = UNALLOCATED_BUCKET;
+        }
+        memory.stable64_write(0, &header);
+
+        Inner {
+            memory,
+            bucket_size_in_pages,
+            allocated_buckets: 0,
+            sizes_in_pages: vec![0; MAX_NUM_MEMORIES as usize],
+            buckets: vec![Vec::new(); MAX_NUM_MEMORIES as usize],
+        }
+    }
+
+    fn load(memory: M) -> Self {
+        let mut header = vec![0u8; HEADER_SIZE as usize];
+        memory.stable64_read(0, &mut header);
+        if &header[..3] != MAGIC {
+            panic!("Bad magic: the stable memory is not managed by a MemoryManager.");
+        }
+        if header[3] != LAYOUT_VERSION {
+            panic!("Unsupported memory manager layout version {}.", header[3]);
+        }
+        let read_u16 = |offset: u64| {
+            let offset = offset as usize;
+            u16::from_le_bytes([header[offset], header[offset + 1]])
+        };
+        let allocated_buckets = read_u16(ALLOCATED_BUCKETS_OFFSET);
+        let bucket_size_in_pages = read_u16(BUCKET_SIZE_OFFSET);
+        if bucket_size_in_pages == 0 {
+            panic!("Corrupt memory manager header: the bucket size is 0.");
+        }
+        if allocated_buckets as u64 > MAX_NUM_BUCKETS {
+            panic!(
+                "Corrupt memory manager header: {} buckets allocated out of {}.",
+                allocated_buckets, MAX_NUM_BUCKETS
+            );
+        }
+
+        let sizes_in_pages: Vec<u64> = header
+            [MEMORY_SIZES_OFFSET as usize..BUCKET_OWNERS_OFFSET as usize]
+            .chunks(8)
+            .map(|chunk| {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(chunk);
+                u64::from_le_bytes(bytes)
+            })
+            .collect();
+        let mut buckets = vec![Vec::new(); MAX_NUM_MEMORIES as usize];
+        let owners = &header[BUCKET_OWNERS_OFFSET as usize..];
+        for (bucket, &owner) in owners.iter().enumerate() {
+            // The first `allocated_buckets` buckets have an owner, and the others none.
+            let allocated = bucket < allocated_buckets as usize;
+            if allocated != (owner < MAX_NUM_MEMORIES) {
+                panic!(
+                    "Corrupt memory manager header: bucket {} has owner {} but {} buckets are allocated.",
+                    bucket, owner, allocated_buckets
+                );
+            }
+            if allocated {
+                buckets[owner as usize].push(bucket as u16);
+            }
+        }
+        for (id, size) in sizes_in_pages.iter().enumerate() {
+            let capacity = buckets[id].len() as u64 * bucket_size_in_pages as u64;
+            if *size > capacity {
+                panic!(
+                    "Corrupt memory manager header: memory {} has {} pages but buckets for {}.",
+                    id, size, capacity
+                );
+            }
+        }
+
+        Inner {
+            memory,
+            bucket_size_in_pages,
+            allocated_buckets,
+            sizes_in_pages,
+            buckets,
+        }
+    }
+
+    fn bucket_size_in_bytes(&self) -> u64 {
+        self.bucket_size_in_pages as u64 * WASM_PAGE_SIZE_IN_BYTES as u64
+    }
+
+    fn grow(&mut self, id: MemoryId, new_pages: u64) -> Result<u64, StableMemoryError> {
+        let id = id.0 as usize;
+        let old_size = self.sizes_in_pages[id];
+        let new_size = old_size
+            .checked_add(new_pages)
+            .ok_or(StableMemoryError::OutOfMemory)?;
+        let bucket_size = self.bucket_size_in_pages as u64;
+        let required_buckets = (new_size + bucket_size - 1) / bucket_size;
+        let new_buckets = required_buckets.saturating_sub(self.buckets[id].len() as u64);
+        let total_buckets = self.allocated_buckets as u64 + new_buckets;
+        if total_buckets > MAX_NUM_BUCKETS {
+            return Err(StableMemoryError::OutOfMemory);
+        }
+
+        // Make room for the new buckets in the underlying memory.
+        let required_pages = 1 + total_buckets * bucket_size;
+        let current_pages = self.memory.stable64_size();
+        if required_pages > current_pages {
+            self.memory.stable64_grow(required_pages - current_pages)?;
+        }
+
+        for _ in 0..new_buckets {
+            let bucket = self.allocated_buckets;
+            self.memory
+                .stable64_write(BUCKET_OWNERS_OFFSET + bucket as u64, &[id as u8]);
+            self.buckets[id].push(bucket);
+            self.allocated_buckets += 1;
+        }
+        self.memory.stable64_write(
+            ALLOCATED_BUCKETS_OFFSET,
+            &self.allocated_buckets.to_le_bytes(),
+        );
+        self.sizes_in_pages[id] = new_size;
+        self.memory
+            .stable64_write(MEMORY_SIZES_OFFSET + 8 * id as u64, &new_size.to_le_bytes());
+        Ok(old_size)
+    }
+
+    fn write(&self, id: MemoryId, offset: u64, buf: &[u8]) {
+        self.check_bounds(id, offset, buf.len());
+        let mut done = 0;
+        while done < buf.len() {
+            let (address, len) = self.locate(id, offset + done as u64, buf.len() - done);
+            self.memory.stable64_write(address, &buf[done..done + len]);
+            done += len;
+        }
+    }
+
+    fn read(&self, id: MemoryId, offset: u64, buf: &mut [u8]) {
+        self.check_bounds(id, offset, buf.len());
+        let mut done = 0;
+        while done < buf.len() {
+            let (address, len) = self.locate(id, offset + done as u64, buf.len() - done);
+            self.memory
+                .stable64_read(address, &mut buf[done..done + len]);
+            done += len;
+        }
+    }
+
+    fn check_bounds(&self, id: MemoryId, offset: u64, len: usize) {
+        let size = self.sizes_in_pages[id.0 as usize] * WASM_PAGE_SIZE_IN_BYTES as u64;
+        if offset
+            .checked_add(len as u64)
+            .map_or(true, |end| end > size)
+        {
+            panic!("{:?}: access out of bounds", id);
+        }
+    }
+
+    // Maps a virtual offset to its address in the underlying memory, along with the number
+    // of bytes (up to `len`) that are contiguous from there.
+    fn locate(&self, id: MemoryId, offset: u64, len: usize) -> (u64, usize) {
+        let bucket_size = self.bucket_size_in_bytes();
+        let bucket = self.buckets[id.0 as usize][(offset / bucket_size) as usize];
+        let offset_in_bucket = offset % bucket_size;
+        let address = DATA_OFFSET + bucket as u64 * bucket_size + offset_in_bucket;
+        let contiguous = min(len as u64, bucket_size - offset_in_bucket) as usize;
+        (address, contiguous)
+    }
+}
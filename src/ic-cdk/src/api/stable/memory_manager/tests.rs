@@ -0,0 +1,128 @@
+use super::*;
+use crate::api::stable::tests::TestStableMemory;
+use std::sync::Mutex;
+
+const PAGE: usize = WASM_PAGE_SIZE_IN_BYTES;
+
+fn shared_memory() -> Rc<Mutex<Vec<u8>>> {
+    Rc::new(Mutex::new(Vec::new()))
+}
+
+#[test]
+fn memories_are_independent() {
+    let manager = MemoryManager::init_with_bucket_size(TestStableMemory::default(), 1);
+    let a = manager.get(MemoryId::new(0));
+    let b = manager.get(MemoryId::new(1));
+    assert_eq!(a.stable64_size(), 0);
+
+    assert_eq!(a.stable64_grow(2).unwrap(), 0);
+    assert_eq!(b.stable64_grow(1).unwrap(), 0);
+    assert_eq!(a.stable64_grow(1).unwrap(), 2);
+    assert_eq!((a.stable64_size(), b.stable64_size()), (3, 1));
+
+    a.stable64_write(0, &[1; 100]);
+    b.stable64_write(0, &[2; 100]);
+    let mut buf = [0u8; 100];
+    a.stable64_read(0, &mut buf);
+    assert_eq!(buf, [1; 100]);
+    b.stable64_read(0, &mut buf);
+    assert_eq!(buf, [2; 100]);
+}
+
+#[test]
+fn accesses_span_buckets() {
+    let manager = MemoryManager::init_with_bucket_size(TestStableMemory::default(), 1);
+    let a = manager.get(MemoryId::new(0));
+    let b = manager.get(MemoryId::new(1));
+    // Interleave the buckets of both memories.
+    for _ in 0..3 {
+        a.stable64_grow(1).unwrap();
+        b.stable64_grow(1).unwrap();
+    }
+
+    let data: Vec<u8> = (0..2 * PAGE).map(|i| i as u8).collect();
+    a.stable64_write(PAGE as u64 - 5, &data);
+    b.stable64_write(0, &vec![9; 3 * PAGE]);
+
+    let mut buf = vec![0u8; data.len()];
+    a.stable64_read(PAGE as u64 - 5, &mut buf);
+    assert_eq!(buf, data);
+}
+
+#[test]
+fn contents_survive_reload() {
+    let memory = shared_memory();
+    {
+        let manager =
+            MemoryManager::init_with_bucket_size(TestStableMemory::new(memory.clone()), 1);
+        let b = manager.get(MemoryId::new(7));
+        b.stable64_grow(2).unwrap();
+        b.stable64_write(PAGE as u64, b"persisted");
+    }
+
+    // The bucket size of the stored header wins.
+    let manager = MemoryManager::init(TestStableMemory::new(memory));
+    let b = manager.get(MemoryId::new(7));
+    assert_eq!(b.stable64_size(), 2);
+    let mut buf = [0u8; 9];
+    b.stable64_read(PAGE as u64, &mut buf);
+    assert_eq!(&buf, b"persisted");
+    assert_eq!(manager.get(MemoryId::new(0)).stable64_size(), 0);
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn access_beyond_size_panics() {
+    let manager = MemoryManager::init(TestStableMemory::default());
+    let a = manager.get(MemoryId::new(0));
+    a.stable64_grow(1).unwrap();
+    a.stable64_write(PAGE as u64 - 1, &[1, 2]);
+}
+
+#[test]
+#[should_panic(expected = "Bad magic")]
+fn refuses_unmanaged_memory() {
+    let memory = Rc::new(Mutex::new(vec![1; 10]));
+    MemoryManager::init(TestStableMemory::new(memory));
+}
+
+fn corrupt_header(corrupt: impl FnOnce(&mut [u8])) {
+    let memory = shared_memory();
+    {
+        let manager =
+            MemoryManager::init_with_bucket_size(TestStableMemory::new(memory.clone()), 1);
+        manager.get(MemoryId::new(3)).stable64_grow(2).unwrap();
+    }
+    corrupt(&mut memory.lock().unwrap());
+    MemoryManager::init(TestStableMemory::new(memory));
+}
+
+#[test]
+#[should_panic(expected = "Corrupt memory manager header")]
+fn refuses_unowned_allocated_bucket() {
+    corrupt_header(|header| header[BUCKET_OWNERS_OFFSET as usize + 1] = UNALLOCATED_BUCKET);
+}
+
+#[test]
+#[should_panic(expected = "Corrupt memory manager header")]
+fn refuses_owned_unallocated_bucket() {
+    corrupt_header(|header| header[BUCKET_OWNERS_OFFSET as usize + 2] = 3);
+}
+
+#[test]
+#[should_panic(expected = "Corrupt memory manager header")]
+fn refuses_too_many_allocated_buckets() {
+    corrupt_header(|header| {
+        let offset = ALLOCATED_BUCKETS_OFFSET as usize;
+        header[offset..offset + 2].copy_from_slice(&u16::MAX.to_le_bytes());
+    });
+}
+
+#[test]
+#[should_panic(expected = "Corrupt memory manager header")]
+fn refuses_memory_larger_than_its_buckets() {
+    corrupt_header(|header| {
+        let offset = MEMORY_SIZES_OFFSET as usize + 8 * 3;
+        header[offset..offset + 8].copy_from_slice(&3u64.to_le_bytes());
+    });
+}
@@ -0,0 +1,115 @@
+use crate::export::Principal;
+use std::borrow::Cow;
+use std::convert::TryInto;
+
+/// A type that can be stored in stable memory by the stable data structures.
+pub trait Storable {
+    /// Encodes the value as bytes.
+    fn to_bytes(&self) -> Cow<'_, [u8]>;
+
+    /// Decodes a value from the bytes produced by [to_bytes](Storable::to_bytes).
+    fn from_bytes(bytes: Vec<u8>) -> Self;
+}
+
+/// A [Storable] type whose encoding never exceeds [MAX_SIZE](BoundedStorable::MAX_SIZE) bytes.
+///
+/// Structures such as [StableBTreeMap](super::StableBTreeMap) reserve that many bytes for
+/// each value, so the bound should be tight. Storing a value whose encoding exceeds the bound
+/// panics.
+pub trait BoundedStorable: Storable {
+    /// The maximum size of the encoding, in bytes.
+    const MAX_SIZE: u32;
+}
+
+impl Storable for Vec<u8> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        bytes
+    }
+}
+
+impl Storable for String {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        String::from_utf8(bytes).expect("Invalid UTF-8 in stable memory.")
+    }
+}
+
+impl Storable for () {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&[])
+    }
+
+    fn from_bytes(_: Vec<u8>) -> Self {}
+}
+
+impl BoundedStorable for () {
+    const MAX_SIZE: u32 = 0;
+}
+
+impl<const N: usize> Storable for [u8; N] {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self[..])
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        bytes
+            .as_slice()
+            .try_into()
+            .expect("Invalid array length in stable memory.")
+    }
+}
+
+impl<const N: usize> BoundedStorable for [u8; N] {
+    const MAX_SIZE: u32 = N as u32;
+}
+
+impl Storable for Principal {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_slice())
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        Principal::from_slice(&bytes)
+    }
+}
+
+impl BoundedStorable for Principal {
+    const MAX_SIZE: u32 = 29;
+}
+
+// Integers are stored big-endian. The encodings of signed integers do not sort like their
+// values, since negative values have the high bit set: structures order keys by their decoded
+// values, with `K: Ord`, never by their encodings.
+macro_rules! impl_storable_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl Storable for $t {
+                fn to_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(self.to_be_bytes().to_vec())
+                }
+
+                fn from_bytes(bytes: Vec<u8>) -> Self {
+                    <$t>::from_be_bytes(
+                        bytes
+                            .as_slice()
+                            .try_into()
+                            .expect("Invalid integer length in stable memory."),
+                    )
+                }
+            }
+
+            impl BoundedStorable for $t {
+                const MAX_SIZE: u32 = std::mem::size_of::<$t>() as u32;
+            }
+        )*
+    };
+}
+
+impl_storable_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
@@ -1,4 +1,5 @@
 use super::*;
+use std::cell::Cell;
 use std::rc::Rc;
 use std::sync::Mutex;
 
@@ -72,6 +73,47 @@ impl StableMemory for TestStableMemory {
     }
 }
 
+// A huge memory that only records the offset of the last write.
+#[derive(Default)]
+struct OffsetRecorder {
+    pages: Cell<u64>,
+    last_offset: Cell<u64>,
+}
+
+impl StableMemory for &OffsetRecorder {
+    fn stable_size(&self) -> u32 {
+        self.pages.get() as u32
+    }
+
+    fn stable64_size(&self) -> u64 {
+        self.pages.get()
+    }
+
+    fn stable_grow(&self, new_pages: u32) -> Result<u32, StableMemoryError> {
+        self.stable64_grow(new_pages as u64).map(|old| old as u32)
+    }
+
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        Ok(self.pages.replace(self.pages.get() + new_pages))
+    }
+
+    fn stable_write(&self, offset: u32, buf: &[u8]) {
+        self.stable64_write(offset as u64, buf)
+    }
+
+    fn stable64_write(&self, offset: u64, _buf: &[u8]) {
+        self.last_offset.set(offset);
+    }
+
+    fn stable_read(&self, offset: u32, buf: &mut [u8]) {
+        self.stable64_read(offset as u64, buf)
+    }
+
+    fn stable64_read(&self, offset: u64, _buf: &mut [u8]) {
+        self.last_offset.set(offset);
+    }
+}
+
 fn pages_required(bytes_len: usize) -> usize {
     let page_size = WASM_PAGE_SIZE_IN_BYTES as usize;
     (bytes_len + page_size - 1) / page_size
@@ -80,7 +122,7 @@ fn pages_required(bytes_len: usize) -> usize {
 mod stable_writer_tests {
     use super::*;
     use rstest::rstest;
-    use std::io::Write;
+    use std::io::{Seek, SeekFrom, Write};
 
     #[rstest]
     #[case(None)]
@@ -153,6 +195,35 @@ mod stable_writer_tests {
         assert_eq!(capacity_pages, min_pages_required as u64);
     }
 
+    #[test]
+    fn seek_moves_next_write() {
+        let memory = Rc::new(Mutex::new(Vec::new()));
+        let mut writer = StableWriter::with_memory(TestStableMemory::new(memory.clone()), 0);
+        writer.write_all(&[1; 10]).unwrap();
+        assert_eq!(writer.seek(SeekFrom::Current(-4)).unwrap(), 6);
+        writer.write_all(&[2; 2]).unwrap();
+        assert_eq!(writer.position(), 8);
+        assert_eq!(writer.seek(SeekFrom::End(-1)).unwrap(), 65535);
+        assert!(writer.seek(SeekFrom::Current(-70000)).is_err());
+
+        writer.seek(SeekFrom::Start(70000)).unwrap();
+        writer.write_all(&[3]).unwrap();
+        assert_eq!(writer.capacity(), 2);
+        let result = &*memory.lock().unwrap();
+        assert_eq!(result[..10], [1, 1, 1, 1, 1, 1, 2, 2, 1, 1]);
+        assert_eq!(result[70000], 3);
+    }
+
+    #[test]
+    fn addresses_beyond_4gib() {
+        let memory = OffsetRecorder::default();
+        let mut writer = StableWriter::with_memory(&memory, 0);
+        writer.seek(SeekFrom::Start(5 << 30)).unwrap();
+        writer.write_all(&[0; 8]).unwrap();
+        assert_eq!(memory.last_offset.get(), 5 << 30);
+        assert_eq!(writer.capacity(), ((5 << 30) >> 16) + 1);
+    }
+
     fn build_writer(memory: TestStableMemory, buffer_size: Option<usize>) -> Box<dyn Write> {
         let writer = StableWriter::with_memory(memory, 0);
         if let Some(buffer_size) = buffer_size {
@@ -166,7 +237,7 @@ mod stable_writer_tests {
 mod stable_reader_tests {
     use super::*;
     use rstest::rstest;
-    use std::io::Read;
+    use std::io::{Read, Seek, SeekFrom};
 
     #[rstest]
     #[case(None)]
@@ -185,6 +256,33 @@ mod stable_reader_tests {
         assert_eq!(input, output[..input.len()]);
     }
 
+    #[rstest]
+    #[case(None)]
+    #[case(Some(10))]
+    fn seek_moves_next_read(#[case] buffer_size: Option<usize>) {
+        let input: Vec<u8> = (0..=255).collect();
+        let memory = TestStableMemory::new(Rc::new(Mutex::new(input)));
+        let reader = StableReader::with_memory(memory, 0);
+        let mut reader: Box<dyn SeekRead> = match buffer_size {
+            Some(buffer_size) => Box::new(BufferedStableReader::with_reader(buffer_size, reader)),
+            None => Box::new(reader),
+        };
+
+        let mut buf = [0u8; 2];
+        reader.read_exact(&mut buf).unwrap();
+        reader.seek(SeekFrom::Current(100)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [102, 103]);
+        reader.seek(SeekFrom::Start(7)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [7, 8]);
+        reader.seek(SeekFrom::End(0)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    trait SeekRead: Read + Seek {}
+    impl<T: Read + Seek> SeekRead for T {}
+
     fn build_reader(memory: TestStableMemory, buffer_size: Option<usize>) -> Box<dyn Read> {
         let reader = StableReader::with_memory(memory, 0);
         if let Some(buffer_size) = buffer_size {
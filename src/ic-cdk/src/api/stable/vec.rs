@@ -0,0 +1,177 @@
+//! A growable array stored in stable memory.
+use super::{layout, BoundedStorable, StableMemory, StableMemoryError};
+use std::marker::PhantomData;
+
+#[cfg(test)]
+mod tests;
+
+const MAGIC: &[u8; 3] = b"SVC";
+const LAYOUT_VERSION: u8 = 1;
+
+// Header layout, at offset 0 of the memory:
+//
+// | magic (3) | version (1) | max element size (4) | length (8) | reserved (16) |
+//
+// Elements follow the header, each in a slot of `4 + max element size` bytes holding its
+// length and its encoding.
+const MAX_SIZE_OFFSET: u64 = 4;
+const LEN_OFFSET: u64 = 8;
+const HEADER_SIZE: u64 = 32;
+
+/// A growable array whose elements live in stable memory.
+///
+/// Elements are encoded with [Storable](super::Storable) in fixed-size slots, so that any
+/// element can be read or replaced in constant time.
+///
+/// ```rust
+/// use ic_cdk::api::stable::{CanisterStableMemory, MemoryId, MemoryManager, StableVec};
+///
+/// let manager = MemoryManager::init(CanisterStableMemory::default());
+/// let mut prices: StableVec<u64, _> = StableVec::init(manager.get(MemoryId::new(0)));
+/// prices.push(&100).unwrap();
+/// assert_eq!(prices.get(0), Some(100));
+/// ```
+pub struct StableVec<T: BoundedStorable, M: StableMemory> {
+    memory: M,
+    len: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T: BoundedStorable, M: StableMemory> StableVec<T, M> {
+    /// Loads the vector stored in `memory`, or initializes an empty one if `memory` is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `memory` is not empty and does not hold a vector, or holds a vector whose
+    /// element bound differs from that of `T`.
+    pub fn init(memory: M) -> Self {
+        if memory.stable64_size() == 0 {
+            layout::ensure_size(&memory, HEADER_SIZE)
+                .unwrap_or_else(|_| panic!("StableVec: out of stable memory."));
+            layout::write_magic(&memory, MAGIC, LAYOUT_VERSION);
+            layout::write_u32(&memory, MAX_SIZE_OFFSET, T::MAX_SIZE);
+            layout::write_u64(&memory, LEN_OFFSET, 0);
+            return StableVec {
+                memory,
+                len: 0,
+                _marker: PhantomData,
+            };
+        }
+
+        layout::check_magic(&memory, MAGIC, LAYOUT_VERSION, "StableVec");
+        let max_size = layout::read_u32(&memory, MAX_SIZE_OFFSET);
+        if max_size != T::MAX_SIZE {
+            panic!(
+                "StableVec bound mismatch: stored {} bytes, expected {} bytes.",
+                max_size,
+                T::MAX_SIZE
+            );
+        }
+        StableVec {
+            len: layout::read_u64(&memory, LEN_OFFSET),
+            memory,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The number of elements.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the vector is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the element at `index`, or `None` if `index` is out of bounds.
+    pub fn get(&self, index: u64) -> Option<T> {
+        if index >= self.len {
+            return None;
+        }
+        let offset = slot_offset::<T>(index);
+        let len = layout::read_u32(&self.memory, offset);
+        let mut bytes = vec![0u8; len as usize];
+        self.memory.stable64_read(offset + 4, &mut bytes);
+        Some(T::from_bytes(bytes))
+    }
+
+    /// Replaces the element at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds, or if the encoding of `value` exceeds its bound.
+    pub fn set(&mut self, index: u64, value: &T) {
+        assert!(
+            index < self.len,
+            "index {} out of bounds for StableVec of length {}",
+            index,
+            self.len
+        );
+        self.write_slot(index, value);
+    }
+
+    /// Appends an element.
+    ///
+    /// Fails, leaving the vector unchanged, if stable memory cannot grow to hold it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoding of `value` exceeds its bound.
+    pub fn push(&mut self, value: &T) -> Result<(), StableMemoryError> {
+        layout::ensure_size(&self.memory, slot_offset::<T>(self.len + 1))?;
+        self.write_slot(self.len, value);
+        self.len += 1;
+        layout::write_u64(&self.memory, LEN_OFFSET, self.len);
+        Ok(())
+    }
+
+    /// Removes the last element and returns it, or `None` if the vector is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let value = self.get(self.len.checked_sub(1)?)?;
+        self.len -= 1;
+        layout::write_u64(&self.memory, LEN_OFFSET, self.len);
+        Some(value)
+    }
+
+    /// Iterates over the elements, reading each one from stable memory as it is reached.
+    pub fn iter(&self) -> Iter<'_, T, M> {
+        Iter {
+            vec: self,
+            index: 0,
+        }
+    }
+
+    fn write_slot(&self, index: u64, value: &T) {
+        let bytes = value.to_bytes();
+        assert!(
+            bytes.len() <= T::MAX_SIZE as usize,
+            "Element of {} bytes exceeds the bound of {} bytes.",
+            bytes.len(),
+            T::MAX_SIZE
+        );
+        let offset = slot_offset::<T>(index);
+        layout::write_u32(&self.memory, offset, bytes.len() as u32);
+        self.memory.stable64_write(offset + 4, &bytes);
+    }
+}
+
+fn slot_offset<T: BoundedStorable>(index: u64) -> u64 {
+    HEADER_SIZE + index * (4 + T::MAX_SIZE as u64)
+}
+
+/// An iterator over the elements of a [StableVec].
+pub struct Iter<'a, T: BoundedStorable, M: StableMemory> {
+    vec: &'a StableVec<T, M>,
+    index: u64,
+}
+
+impl<'a, T: BoundedStorable, M: StableMemory> Iterator for Iter<'a, T, M> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let value = self.vec.get(self.index)?;
+        self.index += 1;
+        Some(value)
+    }
+}
@@ -0,0 +1,62 @@
+use super::*;
+use crate::api::stable::tests::TestStableMemory;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+#[test]
+fn push_pop_get_set() {
+    let mut vec: StableVec<u32, _> = StableVec::init(TestStableMemory::default());
+    assert_eq!(vec.pop(), None);
+    for i in 0..20_000 {
+        vec.push(&i).unwrap();
+    }
+    vec.set(7, &70);
+    assert_eq!(vec.len(), 20_000);
+    assert_eq!(vec.get(7), Some(70));
+    assert_eq!(vec.get(20_000), None);
+    assert_eq!(vec.pop(), Some(19_999));
+    assert_eq!(
+        vec.iter().take(9).collect::<Vec<_>>(),
+        [0, 1, 2, 3, 4, 5, 6, 70, 8]
+    );
+}
+
+#[test]
+fn variable_size_elements() {
+    let mut vec: StableVec<crate::export::Principal, _> =
+        StableVec::init(TestStableMemory::default());
+    let principals = [
+        crate::export::Principal::anonymous(),
+        crate::export::Principal::management_canister(),
+    ];
+    for principal in &principals {
+        vec.push(principal).unwrap();
+    }
+    assert!(vec.iter().eq(principals.iter().cloned()));
+}
+
+#[test]
+fn contents_survive_reload() {
+    let memory = Rc::new(Mutex::new(Vec::new()));
+    let mut vec = StableVec::init(TestStableMemory::new(memory.clone()));
+    vec.push(&1u16).unwrap();
+    vec.push(&2u16).unwrap();
+
+    let vec: StableVec<u16, _> = StableVec::init(TestStableMemory::new(memory));
+    assert_eq!(vec.iter().collect::<Vec<_>>(), [1, 2]);
+}
+
+#[test]
+#[should_panic(expected = "bound mismatch")]
+fn refuses_other_bounds() {
+    let memory = Rc::new(Mutex::new(Vec::new()));
+    StableVec::<u16, _>::init(TestStableMemory::new(memory.clone()));
+    StableVec::<u32, _>::init(TestStableMemory::new(memory));
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn set_out_of_bounds_panics() {
+    let mut vec: StableVec<u8, _> = StableVec::init(TestStableMemory::default());
+    vec.set(0, &1);
+}
@@ -0,0 +1,107 @@
+use super::{StableMemory, StableMemoryError, WASM_PAGE_SIZE_IN_BYTES};
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+// The 32-bit API addresses at most 4GiB.
+const MAX_STABLE32_PAGES: u64 = 65536;
+
+/// A [StableMemory] backed by a vector on the heap.
+///
+/// It behaves like the stable memory of a canister, starting empty and growing page by page,
+/// which makes code generic over [StableMemory] testable natively. Clones share the same
+/// contents, so a test can keep a handle on a memory after moving it into a structure, and
+/// reopen the structure on it to simulate an upgrade.
+///
+/// ```rust
+/// use ic_cdk::api::stable::{StableMemory, VectorMemory};
+///
+/// let memory = VectorMemory::default();
+/// memory.stable64_grow(1).unwrap();
+/// memory.clone().stable64_write(0, b"hello");
+/// assert_eq!(&memory.to_vec()[..5], b"hello");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct VectorMemory {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl VectorMemory {
+    /// Creates an empty memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a memory holding `bytes`, padded with zeros to whole pages.
+    pub fn from_bytes(mut bytes: Vec<u8>) -> Self {
+        let page_size = WASM_PAGE_SIZE_IN_BYTES;
+        let len = (bytes.len() + page_size - 1) / page_size * page_size;
+        bytes.resize(len, 0);
+        VectorMemory {
+            bytes: Rc::new(RefCell::new(bytes)),
+        }
+    }
+
+    /// Returns a copy of the contents of the memory.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.bytes.borrow().clone()
+    }
+}
+
+impl StableMemory for VectorMemory {
+    fn stable_size(&self) -> u32 {
+        self.stable64_size().min(MAX_STABLE32_PAGES) as u32
+    }
+
+    fn stable64_size(&self) -> u64 {
+        (self.bytes.borrow().len() / WASM_PAGE_SIZE_IN_BYTES) as u64
+    }
+
+    fn stable_grow(&self, new_pages: u32) -> Result<u32, StableMemoryError> {
+        if self.stable64_size() + new_pages as u64 > MAX_STABLE32_PAGES {
+            return Err(StableMemoryError::OutOfMemory);
+        }
+        self.stable64_grow(new_pages as u64)
+            .map(|old_pages| old_pages as u32)
+    }
+
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        let old_pages = self.stable64_size();
+        let new_len = old_pages
+            .checked_add(new_pages)
+            .and_then(|pages| pages.checked_mul(WASM_PAGE_SIZE_IN_BYTES as u64))
+            .filter(|len| *len <= usize::MAX as u64)
+            .ok_or(StableMemoryError::OutOfMemory)?;
+        self.bytes.borrow_mut().resize(new_len as usize, 0);
+        Ok(old_pages)
+    }
+
+    fn stable_write(&self, offset: u32, buf: &[u8]) {
+        self.stable64_write(offset as u64, buf)
+    }
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]) {
+        let mut bytes = self.bytes.borrow_mut();
+        let range = checked_range(offset, buf.len(), bytes.len());
+        bytes[range].copy_from_slice(buf);
+    }
+
+    fn stable_read(&self, offset: u32, buf: &mut [u8]) {
+        self.stable64_read(offset as u64, buf)
+    }
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]) {
+        let bytes = self.bytes.borrow();
+        let range = checked_range(offset, buf.len(), bytes.len());
+        buf.copy_from_slice(&bytes[range]);
+    }
+}
+
+// Like the system API, accesses beyond the size of the memory trap.
+fn checked_range(offset: u64, len: usize, size: usize) -> Range<usize> {
+    usize::try_from(offset)
+        .ok()
+        .and_then(|start| Some(start..start.checked_add(len)?))
+        .filter(|range| range.end <= size)
+        .unwrap_or_else(|| panic!("stable memory out of bounds"))
+}
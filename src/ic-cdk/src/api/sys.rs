@@ -0,0 +1,327 @@
+//! Safe, Rust-typed wrappers around the System API imports in [ic0](super::ic0).
+//!
+//! Inside a canister (`wasm32`) every function here is a thin shim over the
+//! corresponding `ic0` import, turning slices into pointer/length pairs.
+//!
+//! On any other target the raw imports do not exist, so the calls are dispatched to the
+//! [SystemApi](super::backend::SystemApi) backend installed for the current thread
+//! (an in-memory [MockSystemApi](super::backend::MockSystemApi) by default).
+//! This keeps the rest of the crate free of `cfg` switches and makes it usable in native tests.
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) use self::canister::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use self::native::*;
+
+#[cfg(target_arch = "wasm32")]
+mod canister {
+    use crate::api::ic0;
+
+    #[inline]
+    fn split_u128(value: u128) -> (i64, i64) {
+        let high = (value >> 64) as u64;
+        let low = (value & u64::MAX as u128) as u64;
+        (high as i64, low as i64)
+    }
+
+    pub(crate) fn msg_arg_data_size() -> usize {
+        unsafe { ic0::msg_arg_data_size() as usize }
+    }
+
+    pub(crate) fn msg_arg_data_copy(dst: &mut [u8], offset: usize) {
+        unsafe { ic0::msg_arg_data_copy(dst.as_mut_ptr() as i32, offset as i32, dst.len() as i32) }
+    }
+
+    pub(crate) fn msg_caller_size() -> usize {
+        unsafe { ic0::msg_caller_size() as usize }
+    }
+
+    pub(crate) fn msg_caller_copy(dst: &mut [u8], offset: usize) {
+        unsafe { ic0::msg_caller_copy(dst.as_mut_ptr() as i32, offset as i32, dst.len() as i32) }
+    }
+
+    pub(crate) fn msg_reject_code() -> i32 {
+        unsafe { ic0::msg_reject_code() }
+    }
+
+    pub(crate) fn msg_reject_msg_size() -> usize {
+        unsafe { ic0::msg_reject_msg_size() as usize }
+    }
+
+    pub(crate) fn msg_reject_msg_copy(dst: &mut [u8], offset: usize) {
+        unsafe {
+            ic0::msg_reject_msg_copy(dst.as_mut_ptr() as i32, offset as i32, dst.len() as i32)
+        }
+    }
+
+    pub(crate) fn msg_reply_data_append(data: &[u8]) {
+        unsafe { ic0::msg_reply_data_append(data.as_ptr() as i32, data.len() as i32) }
+    }
+
+    pub(crate) fn msg_reply() {
+        unsafe { ic0::msg_reply() }
+    }
+
+    pub(crate) fn msg_reject(message: &[u8]) {
+        unsafe { ic0::msg_reject(message.as_ptr() as i32, message.len() as i32) }
+    }
+
+    pub(crate) fn msg_cycles_available() -> u64 {
+        unsafe { ic0::msg_cycles_available() as u64 }
+    }
+
+    pub(crate) fn msg_cycles_available128() -> u128 {
+        let mut recv = 0u128;
+        unsafe { ic0::msg_cycles_available128(&mut recv as *mut u128 as i32) }
+        recv
+    }
+
+    pub(crate) fn msg_cycles_refunded() -> u64 {
+        unsafe { ic0::msg_cycles_refunded() as u64 }
+    }
+
+    pub(crate) fn msg_cycles_refunded128() -> u128 {
+        let mut recv = 0u128;
+        unsafe { ic0::msg_cycles_refunded128(&mut recv as *mut u128 as i32) }
+        recv
+    }
+
+    pub(crate) fn msg_cycles_accept(max_amount: u64) -> u64 {
+        unsafe { ic0::msg_cycles_accept(max_amount as i64) as u64 }
+    }
+
+    pub(crate) fn msg_cycles_accept128(max_amount: u128) -> u128 {
+        let (high, low) = split_u128(max_amount);
+        let mut recv = 0u128;
+        unsafe { ic0::msg_cycles_accept128(high, low, &mut recv as *mut u128 as i32) }
+        recv
+    }
+
+    pub(crate) fn canister_self_size() -> usize {
+        unsafe { ic0::canister_self_size() as usize }
+    }
+
+    pub(crate) fn canister_self_copy(dst: &mut [u8], offset: usize) {
+        unsafe { ic0::canister_self_copy(dst.as_mut_ptr() as i32, offset as i32, dst.len() as i32) }
+    }
+
+    pub(crate) fn canister_cycle_balance() -> u64 {
+        unsafe { ic0::canister_cycle_balance() as u64 }
+    }
+
+    pub(crate) fn canister_cycle_balance128() -> u128 {
+        let mut recv = 0u128;
+        unsafe { ic0::canister_cycle_balance128(&mut recv as *mut u128 as i32) }
+        recv
+    }
+
+    pub(crate) fn canister_status() -> u32 {
+        unsafe { ic0::canister_status() as u32 }
+    }
+
+    pub(crate) fn msg_method_name_size() -> usize {
+        unsafe { ic0::msg_method_name_size() as usize }
+    }
+
+    pub(crate) fn msg_method_name_copy(dst: &mut [u8], offset: usize) {
+        unsafe {
+            ic0::msg_method_name_copy(dst.as_mut_ptr() as i32, offset as i32, dst.len() as i32)
+        }
+    }
+
+    pub(crate) fn accept_message() {
+        unsafe { ic0::accept_message() }
+    }
+
+    pub(crate) fn call_new(
+        callee: &[u8],
+        method: &[u8],
+        reply_fun: usize,
+        reply_env: usize,
+        reject_fun: usize,
+        reject_env: usize,
+    ) {
+        unsafe {
+            ic0::call_new(
+                callee.as_ptr() as i32,
+                callee.len() as i32,
+                method.as_ptr() as i32,
+                method.len() as i32,
+                reply_fun as i32,
+                reply_env as i32,
+                reject_fun as i32,
+                reject_env as i32,
+            )
+        }
+    }
+
+    pub(crate) fn call_on_cleanup(fun: usize, env: usize) {
+        unsafe { ic0::call_on_cleanup(fun as i32, env as i32) }
+    }
+
+    pub(crate) fn call_data_append(data: &[u8]) {
+        unsafe { ic0::call_data_append(data.as_ptr() as i32, data.len() as i32) }
+    }
+
+    pub(crate) fn call_cycles_add(amount: u64) {
+        unsafe { ic0::call_cycles_add(amount as i64) }
+    }
+
+    pub(crate) fn call_cycles_add128(amount: u128) {
+        let (high, low) = split_u128(amount);
+        unsafe { ic0::call_cycles_add128(high, low) }
+    }
+
+    pub(crate) fn call_perform() -> i32 {
+        unsafe { ic0::call_perform() }
+    }
+
+    pub(crate) fn stable_size() -> u32 {
+        unsafe { ic0::stable_size() as u32 }
+    }
+
+    pub(crate) fn stable_grow(new_pages: u32) -> i32 {
+        unsafe { ic0::stable_grow(new_pages as i32) }
+    }
+
+    pub(crate) fn stable_write(offset: u32, src: &[u8]) {
+        unsafe { ic0::stable_write(offset as i32, src.as_ptr() as i32, src.len() as i32) }
+    }
+
+    pub(crate) fn stable_read(dst: &mut [u8], offset: u32) {
+        unsafe { ic0::stable_read(dst.as_mut_ptr() as i32, offset as i32, dst.len() as i32) }
+    }
+
+    pub(crate) fn stable64_size() -> u64 {
+        unsafe { ic0::stable64_size() as u64 }
+    }
+
+    pub(crate) fn stable64_grow(new_pages: u64) -> i64 {
+        unsafe { ic0::stable64_grow(new_pages as i64) }
+    }
+
+    pub(crate) fn stable64_write(offset: u64, src: &[u8]) {
+        unsafe { ic0::stable64_write(offset as i64, src.as_ptr() as i64, src.len() as i64) }
+    }
+
+    pub(crate) fn stable64_read(dst: &mut [u8], offset: u64) {
+        unsafe { ic0::stable64_read(dst.as_mut_ptr() as i64, offset as i64, dst.len() as i64) }
+    }
+
+    pub(crate) fn certified_data_set(data: &[u8]) {
+        unsafe { ic0::certified_data_set(data.as_ptr() as i32, data.len() as i32) }
+    }
+
+    pub(crate) fn data_certificate_present() -> bool {
+        unsafe { ic0::data_certificate_present() != 0 }
+    }
+
+    pub(crate) fn data_certificate_size() -> usize {
+        unsafe { ic0::data_certificate_size() as usize }
+    }
+
+    pub(crate) fn data_certificate_copy(dst: &mut [u8], offset: usize) {
+        unsafe {
+            ic0::data_certificate_copy(dst.as_mut_ptr() as i32, offset as i32, dst.len() as i32)
+        }
+    }
+
+    pub(crate) fn time() -> u64 {
+        unsafe { ic0::time() as u64 }
+    }
+
+    pub(crate) fn global_timer_set(timestamp: u64) -> u64 {
+        unsafe { ic0::global_timer_set(timestamp as i64) as u64 }
+    }
+
+    pub(crate) fn performance_counter(counter_type: u32) -> u64 {
+        unsafe { ic0::performance_counter(counter_type as i32) as u64 }
+    }
+
+    pub(crate) fn is_controller(principal: &[u8]) -> bool {
+        unsafe { ic0::is_controller(principal.as_ptr() as i32, principal.len() as i32) != 0 }
+    }
+
+    pub(crate) fn debug_print(message: &[u8]) {
+        unsafe { ic0::debug_print(message.as_ptr() as i32, message.len() as i32) }
+    }
+
+    pub(crate) fn trap(message: &[u8]) {
+        unsafe { ic0::trap(message.as_ptr() as i32, message.len() as i32) }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use crate::api::backend::with_system_api;
+
+    // Forwards each function to the method of the same name on the current backend.
+    macro_rules! forward {
+        ( $( fn $name: ident ( $( $arg: ident : $argtype: ty ),* ) $( -> $ret: ty )? ; )+ ) => {
+            $(
+            #[inline]
+            pub(crate) fn $name( $( $arg: $argtype ),* ) $( -> $ret )? {
+                with_system_api(|api| api.$name( $( $arg ),* ))
+            }
+            )+
+        };
+    }
+
+    forward! {
+        fn msg_arg_data_size() -> usize;
+        fn msg_arg_data_copy(dst: &mut [u8], offset: usize);
+        fn msg_caller_size() -> usize;
+        fn msg_caller_copy(dst: &mut [u8], offset: usize);
+        fn msg_reject_code() -> i32;
+        fn msg_reject_msg_size() -> usize;
+        fn msg_reject_msg_copy(dst: &mut [u8], offset: usize);
+        fn msg_reply_data_append(data: &[u8]);
+        fn msg_reply();
+        fn msg_reject(message: &[u8]);
+        fn msg_cycles_available() -> u64;
+        fn msg_cycles_available128() -> u128;
+        fn msg_cycles_refunded() -> u64;
+        fn msg_cycles_refunded128() -> u128;
+        fn msg_cycles_accept(max_amount: u64) -> u64;
+        fn msg_cycles_accept128(max_amount: u128) -> u128;
+        fn canister_self_size() -> usize;
+        fn canister_self_copy(dst: &mut [u8], offset: usize);
+        fn canister_cycle_balance() -> u64;
+        fn canister_cycle_balance128() -> u128;
+        fn canister_status() -> u32;
+        fn msg_method_name_size() -> usize;
+        fn msg_method_name_copy(dst: &mut [u8], offset: usize);
+        fn accept_message();
+        fn call_new(
+            callee: &[u8],
+            method: &[u8],
+            reply_fun: usize,
+            reply_env: usize,
+            reject_fun: usize,
+            reject_env: usize
+        );
+        fn call_on_cleanup(fun: usize, env: usize);
+        fn call_data_append(data: &[u8]);
+        fn call_cycles_add(amount: u64);
+        fn call_cycles_add128(amount: u128);
+        fn call_perform() -> i32;
+        fn stable_size() -> u32;
+        fn stable_grow(new_pages: u32) -> i32;
+        fn stable_write(offset: u32, src: &[u8]);
+        fn stable_read(dst: &mut [u8], offset: u32);
+        fn stable64_size() -> u64;
+        fn stable64_grow(new_pages: u64) -> i64;
+        fn stable64_write(offset: u64, src: &[u8]);
+        fn stable64_read(dst: &mut [u8], offset: u64);
+        fn certified_data_set(data: &[u8]);
+        fn data_certificate_present() -> bool;
+        fn data_certificate_size() -> usize;
+        fn data_certificate_copy(dst: &mut [u8], offset: usize);
+        fn time() -> u64;
+        fn global_timer_set(timestamp: u64) -> u64;
+        fn performance_counter(counter_type: u32) -> u64;
+        fn is_controller(principal: &[u8]) -> bool;
+        fn debug_print(message: &[u8]);
+        fn trap(message: &[u8]);
+    }
+}
@@ -0,0 +1,204 @@
+//! Cycles accounting for methods that charge fees and forward cycles.
+//!
+//! A [CyclesBudget] accepts cycles from the caller, attaches cycles to inter-canister calls
+//! and keeps track of the refunds, so that a method knows what it actually spent:
+//!
+//! ```rust,no_run
+//! use ic_cdk::cycles::CyclesBudget;
+//! use ic_cdk::export::Principal;
+//!
+//! const FEE: u128 = 1_000_000;
+//!
+//! async fn forward(callee: Principal) -> Result<u128, String> {
+//!     let budget = CyclesBudget::new();
+//!     budget.accept_fee(FEE).map_err(|err| err.to_string())?;
+//!     // Accept what is left to attach it to the call.
+//!     let forwarded = budget.accept(u128::MAX);
+//!     let _: () = budget
+//!         .call(callee, "deposit", (), forwarded)
+//!         .await
+//!         .map_err(|err| err.to_string())?;
+//!     Ok(budget.refunded())
+//! }
+//! ```
+//!
+//! [set_low_balance_threshold] makes the update methods exported with `#[update]` trap or
+//! reject new calls while the cycle balance of the canister is below a threshold, keeping
+//! the cycles left for the calls already in flight and for upgrades. The threshold is kept
+//! in heap memory and does not survive upgrades; set it again in `#[post_upgrade]`.
+use crate::api::call::{self, CallError, CallResult};
+use crate::export::Principal;
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use std::cell::Cell;
+use std::fmt;
+
+#[cfg(test)]
+mod tests;
+
+/// Error returned by [CyclesBudget::accept_fee] when the caller attached too few cycles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InsufficientCycles {
+    /// The cycles required.
+    pub required: u128,
+    /// The cycles available in the message.
+    pub available: u128,
+}
+
+impl fmt::Display for InsufficientCycles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cycles are required, but only {} were attached",
+            self.required, self.available
+        )
+    }
+}
+
+impl std::error::Error for InsufficientCycles {}
+
+/// The cycles a method accepted from its caller, attached to its calls and got refunded.
+///
+/// All the methods take `&self`, so several calls made with the same budget can be awaited
+/// at once, for instance with [join_all](crate::futures::join_all).
+#[derive(Debug, Default)]
+pub struct CyclesBudget {
+    accepted: Cell<u128>,
+    attached: Cell<u128>,
+    refunded: Cell<u128>,
+}
+
+impl CyclesBudget {
+    /// Creates an empty budget for the current message.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cycles attached by the caller that have not been accepted yet.
+    pub fn available(&self) -> u128 {
+        call::msg_cycles_available128()
+    }
+
+    /// Accepts up to `max_amount` cycles from the caller, returning the amount accepted.
+    pub fn accept(&self, max_amount: u128) -> u128 {
+        let accepted = call::msg_cycles_accept128(max_amount);
+        self.accepted.set(self.accepted.get() + accepted);
+        accepted
+    }
+
+    /// Accepts exactly `fee` cycles from the caller, or none if fewer are available.
+    pub fn accept_fee(&self, fee: u128) -> Result<(), InsufficientCycles> {
+        let available = self.available();
+        if available < fee {
+            return Err(InsufficientCycles {
+                required: fee,
+                available,
+            });
+        }
+        self.accept(fee);
+        Ok(())
+    }
+
+    /// Calls `method` on `id` with `cycles` taken from the canister balance, recording them
+    /// as attached and what comes back as refunded.
+    ///
+    /// The cycles of a call that could not be sent are refunded in full.
+    pub async fn call<T: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(
+        &self,
+        id: Principal,
+        method: &str,
+        args: T,
+        cycles: u128,
+    ) -> CallResult<R> {
+        self.attached.set(self.attached.get() + cycles);
+        let result = call::call_with_payment128(id, method, args, cycles).await;
+        let refunded = match result {
+            Err(CallError::SyncPerformFailed { .. }) => cycles,
+            Err(CallError::CleanupInvoked) => 0,
+            // The reply or reject of the call is being handled.
+            _ => call::msg_cycles_refunded128(),
+        };
+        self.refunded.set(self.refunded.get() + refunded);
+        result
+    }
+
+    /// The cycles accepted from the caller so far.
+    pub fn accepted(&self) -> u128 {
+        self.accepted.get()
+    }
+
+    /// The cycles attached to calls so far, including the refunded ones.
+    pub fn attached(&self) -> u128 {
+        self.attached.get()
+    }
+
+    /// The cycles refunded by the calls so far.
+    pub fn refunded(&self) -> u128 {
+        self.refunded.get()
+    }
+
+    /// The cycles attached to calls and not refunded, that is kept by the callees.
+    pub fn spent(&self) -> u128 {
+        self.attached() - self.refunded()
+    }
+}
+
+/// What the update methods do while the cycle balance is below the threshold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LowBalanceAction {
+    /// Trap, which rolls back the message.
+    Trap,
+    /// Reject the call, which lets the caller tell why it failed.
+    Reject,
+}
+
+thread_local! {
+    static LOW_BALANCE: Cell<Option<(u128, LowBalanceAction)>> = Cell::default();
+}
+
+/// Makes the update methods exported with `#[update]` refuse new calls while the cycle
+/// balance of the canister is below `threshold`, by taking `action`.
+///
+/// Query methods, lifecycle hooks, heartbeats, timers and the callbacks of calls already
+/// made are not affected.
+///
+/// The threshold is cleared by an upgrade, so call this from `#[post_upgrade]` as well as
+/// from `#[init]`:
+///
+/// ```rust,no_run
+/// use ic_cdk::cycles::{set_low_balance_threshold, LowBalanceAction};
+///
+/// fn init() {
+///     set_low_balance_threshold(1_000_000_000_000, LowBalanceAction::Reject);
+/// }
+///
+/// fn post_upgrade() {
+///     init();
+/// }
+/// ```
+pub fn set_low_balance_threshold(threshold: u128, action: LowBalanceAction) {
+    LOW_BALANCE.with(|low_balance| low_balance.set(Some((threshold, action))));
+}
+
+/// Lets the update methods run whatever the cycle balance.
+pub fn clear_low_balance_threshold() {
+    LOW_BALANCE.with(|low_balance| low_balance.set(None));
+}
+
+/// Checks the cycle balance against the threshold set with [set_low_balance_threshold],
+/// trapping or returning the message to reject the call with if it is below.
+///
+/// Called by the update methods exported with `#[update]` before their guard.
+pub fn check_low_balance() -> Result<(), String> {
+    let (threshold, action) = match LOW_BALANCE.with(Cell::get) {
+        Some(low_balance) => low_balance,
+        None => return Ok(()),
+    };
+    if crate::api::canister_balance128() >= threshold {
+        return Ok(());
+    }
+    let message = "The canister is low on cycles and does not accept new update calls.";
+    match action {
+        LowBalanceAction::Trap => crate::trap(message),
+        LowBalanceAction::Reject => Err(message.to_string()),
+    }
+}
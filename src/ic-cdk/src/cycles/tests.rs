@@ -0,0 +1,66 @@
+use super::*;
+use crate::api::backend::{poll_once, set_system_api, MockSystemApi};
+use crate::api::call::RejectionCode;
+use std::rc::Rc;
+
+fn install_mock() -> Rc<MockSystemApi> {
+    let mock = Rc::new(MockSystemApi::new());
+    set_system_api(mock.clone());
+    clear_low_balance_threshold();
+    mock
+}
+
+#[test]
+fn budget_tracks_accepted_attached_and_refunded_cycles() {
+    let mock = install_mock();
+    mock.set_balance(1_000);
+    mock.set_msg_cycles_available(300);
+    let budget = CyclesBudget::new();
+
+    assert_eq!(
+        budget.accept_fee(500),
+        Err(InsufficientCycles {
+            required: 500,
+            available: 300
+        })
+    );
+    budget.accept_fee(100).unwrap();
+    assert_eq!(budget.accept(u128::MAX), 200);
+    assert_eq!(budget.available(), 0);
+
+    // A call that cannot be sent gets all its cycles back.
+    mock.set_call_perform_result(RejectionCode::SysTransient as i32);
+    let callee = Principal::from_slice(&[7]);
+    let result: CallResult<()> = poll_once(budget.call(callee, "deposit", (), 200)).unwrap();
+    assert!(result.is_err());
+
+    assert_eq!(budget.accepted(), 300);
+    assert_eq!(budget.attached(), 200);
+    assert_eq!(budget.refunded(), 200);
+    assert_eq!(budget.spent(), 0);
+    assert_eq!(mock.balance(), 1_300);
+}
+
+#[test]
+fn low_balance_rejects_below_threshold() {
+    let mock = install_mock();
+    mock.set_balance(1_000);
+    assert!(check_low_balance().is_ok());
+
+    set_low_balance_threshold(1_000, LowBalanceAction::Reject);
+    assert!(check_low_balance().is_ok());
+    mock.set_balance(999);
+    assert!(check_low_balance().is_err());
+
+    clear_low_balance_threshold();
+    assert!(check_low_balance().is_ok());
+}
+
+#[test]
+#[should_panic(expected = "low on cycles")]
+fn low_balance_traps_below_threshold() {
+    let mock = install_mock();
+    mock.set_balance(0);
+    set_low_balance_threshold(1, LowBalanceAction::Trap);
+    let _ = check_low_balance();
+}
@@ -1,95 +1,374 @@
+//! The executor of the canister, and combinators to await several futures at once.
+//!
+//! Futures are run as tasks with [spawn](crate::spawn). A task is polled when it is spawned,
+//! and again each time it is woken, typically by the callback of an inter-canister call, in
+//! the same message as the wake-up. [JoinHandle] retrieves the output of a task, and [join],
+//! [join_all] and [select] await several futures within one task:
+//!
+//! ```rust,no_run
+//! use ic_cdk::api::call::CallResult;
+//! use ic_cdk::export::Principal;
+//! use ic_cdk::futures::join_all;
+//!
+//! async fn total_supply(ledgers: Vec<Principal>) -> u64 {
+//!     // The calls are all sent before any of the replies is awaited.
+//!     let calls = ledgers.into_iter().map(|ledger| ic_cdk::call(ledger, "total_supply", ()));
+//!     let results: Vec<CallResult<(u64,)>> = join_all(calls).await;
+//!     results.into_iter().filter_map(Result::ok).map(|(supply,)| supply).sum()
+//! }
+//! ```
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::future::Future;
+use std::mem;
 use std::pin::Pin;
-use std::sync::atomic::AtomicBool;
-use std::task::Context;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
 
-/// Must be called on every top-level future corresponding to a method call of a
-/// canister by the IC.
-///
-/// Saves the pointer to the future on the heap and kickstarts the future by
-/// polling it once. During the polling we also need to provide the waker
-/// callback which is triggered after the future made progress.
-/// The waker would then poll the future one last time to advance it to
-/// the final state. For that, we pass the future pointer to the waker, so that
-/// it can be restored into a box from a raw pointer and then dropped if not
-/// needed anymore.
+#[cfg(test)]
+mod tests;
+
+struct Task {
+    // `None` once the future has completed or has been dropped by a cleanup.
+    future: RefCell<Option<Pin<Box<dyn Future<Output = ()>>>>>,
+    queued: Cell<bool>,
+}
+
+thread_local! {
+    static QUEUE: RefCell<VecDeque<Rc<Task>>> = RefCell::default();
+    static RUNNING: Cell<bool> = Cell::default();
+    static CLEANUP: Cell<bool> = Cell::default();
+}
+
+/// Runs `future` as a task, polling it once right away, or as soon as the task being
+/// polled yields if called from a task.
+pub(crate) fn spawn<F: 'static + Future>(future: F) -> JoinHandle<F::Output> {
+    let state = Rc::new(RefCell::new(JoinState {
+        output: None,
+        finished: false,
+        waker: None,
+    }));
+    let handle = JoinHandle {
+        state: Rc::clone(&state),
+    };
+    let completion = Completion { state };
+    let task = Rc::new(Task {
+        future: RefCell::new(Some(Box::pin(async move {
+            let output = future.await;
+            completion.complete(output);
+        }))),
+        queued: Cell::new(false),
+    });
+    schedule(task);
+    handle
+}
+
+/// Drops the task woken by `waker` without executing any more of it, releasing the locks
+/// and guards held by its future.
 ///
-/// Technically, we store 2 pointers on the heap: the pointer to the future
-/// itself, and a pointer to that pointer. The reason for this is that the waker
-/// API requires us to pass one thin pointer, while a a pointer to a `dyn Trait`
-/// can only be fat. So we create one additional thin pointer, pointing to the
-/// fat pointer and pass it instead.
-pub fn spawn<F: 'static + Future<Output = ()>>(future: F) {
-    let future_ptr = Box::into_raw(Box::new(future));
-    let future_ptr_ptr: *mut *mut dyn Future<Output = ()> = Box::into_raw(Box::new(future_ptr));
-    let mut pinned_future = unsafe { Pin::new_unchecked(&mut *future_ptr) };
-    if pinned_future
-        .as_mut()
-        .poll(&mut Context::from_waker(&waker::waker(
-            future_ptr_ptr as *const (),
-        )))
-        .is_ready()
-    {
-        unsafe {
-            let _ = Box::from_raw(future_ptr);
-            let _ = Box::from_raw(future_ptr_ptr);
-        }
+/// Called when recovering from a callback trap. The tasks woken as the future is dropped
+/// are dropped as well: they cannot run in a cleanup callback, and would otherwise resume
+/// in whatever message runs the executor next, outside of their own call context.
+pub(crate) fn cleanup(waker: Waker) {
+    CLEANUP.with(|cleanup| cleanup.set(true));
+    waker.wake();
+    CLEANUP.with(|cleanup| cleanup.set(false));
+}
+
+// Queues `task` to be polled and runs the queue, unless it is already being run further up
+// the stack.
+fn schedule(task: Rc<Task>) {
+    if CLEANUP.with(Cell::get) {
+        let future = task.future.borrow_mut().take();
+        let was_running = RUNNING.with(|running| running.replace(true));
+        drop(future);
+        RUNNING.with(|running| running.set(was_running));
+        return;
     }
+    if task.queued.replace(true) {
+        return;
+    }
+    QUEUE.with(|queue| queue.borrow_mut().push_back(task));
+    run();
 }
 
-pub(crate) static CLEANUP: AtomicBool = AtomicBool::new(false);
+fn run() {
+    if RUNNING.with(|running| running.replace(true)) {
+        return;
+    }
+    // Resets the flag even if a task panics, which only matters outside of a canister.
+    struct Running;
+    impl Drop for Running {
+        fn drop(&mut self) {
+            RUNNING.with(|running| running.set(false));
+        }
+    }
+    let _running = Running;
+
+    while let Some(task) = QUEUE.with(|queue| queue.borrow_mut().pop_front()) {
+        task.queued.set(false);
+        let waker = waker::waker(Rc::clone(&task));
+        let mut future = task.future.borrow_mut();
+        if let Some(pinned) = future.as_mut() {
+            if pinned
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                *future = None;
+            }
+        }
+    }
+}
 
-// This module contains the implementation of a waker we're using for waking
-// top-level futures (the ones returned by canister methods). The waker polls
-// the future once and re-pins it on the heap, if it's pending. If the future is
-// done, we do nothing. Hence, it will be unallocated once we exit the scope and
-// we're not interested in the result, as it can only be a unit `()` if the
-// waker was used as intended.
+// The waker of a task holds a reference to it, so waking a task that has completed, for
+// instance from the callback of a call whose future was dropped by `select`, does nothing.
 mod waker {
     use super::*;
-    use std::{
-        sync::atomic::Ordering,
-        task::{RawWaker, RawWakerVTable, Waker},
-    };
-    type FuturePtr = *mut dyn Future<Output = ()>;
+    use std::task::{RawWaker, RawWakerVTable};
 
     static MY_VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
 
-    fn raw_waker(ptr: *const ()) -> RawWaker {
-        RawWaker::new(ptr, &MY_VTABLE)
+    fn raw_waker(task: Rc<Task>) -> RawWaker {
+        RawWaker::new(Rc::into_raw(task) as *const (), &MY_VTABLE)
     }
 
-    fn clone(ptr: *const ()) -> RawWaker {
-        raw_waker(ptr)
+    unsafe fn clone(ptr: *const ()) -> RawWaker {
+        let task = mem::ManuallyDrop::new(Rc::from_raw(ptr as *const Task));
+        raw_waker(Rc::clone(&task))
     }
 
-    // Our waker will be called only if one of the response callbacks is triggered.
-    // Then, the waker will restore the future from the pointer we passed into the
-    // waker inside the `kickstart` method and poll the future again. If the future
-    // is pending, we leave it on the heap. If it's ready, we deallocate the
-    // pointer. If CLEANUP is set, then we're recovering from a callback trap, and
-    // want to drop the future without executing any more of it.
     unsafe fn wake(ptr: *const ()) {
-        let boxed_future_ptr_ptr = Box::from_raw(ptr as *mut FuturePtr);
-        let future_ptr: FuturePtr = *boxed_future_ptr_ptr;
-        let boxed_future = Box::from_raw(future_ptr);
-        let mut pinned_future = Pin::new_unchecked(&mut *future_ptr);
-        if !super::CLEANUP.load(Ordering::Relaxed)
-            && pinned_future
-                .as_mut()
-                .poll(&mut Context::from_waker(&waker::waker(ptr)))
-                .is_pending()
-        {
-            Box::into_raw(boxed_future_ptr_ptr);
-            Box::into_raw(boxed_future);
+        schedule(Rc::from_raw(ptr as *const Task));
+    }
+
+    unsafe fn wake_by_ref(ptr: *const ()) {
+        let task = mem::ManuallyDrop::new(Rc::from_raw(ptr as *const Task));
+        schedule(Rc::clone(&task));
+    }
+
+    unsafe fn drop(ptr: *const ()) {
+        mem::drop(Rc::from_raw(ptr as *const Task));
+    }
+
+    pub(super) fn waker(task: Rc<Task>) -> Waker {
+        unsafe { Waker::from_raw(raw_waker(task)) }
+    }
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+// Owned by the future of a task: stores its output and wakes the task awaiting its handle.
+// If the future is dropped before completing, the awaiting task is woken all the same, so
+// that the cleanup which dropped the future drops that task in turn.
+struct Completion<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> Completion<T> {
+    fn complete(&self, output: T) {
+        let waker = {
+            let mut state = self.state.borrow_mut();
+            state.output = Some(output);
+            state.finished = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let waker = self.state.borrow_mut().waker.take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// A handle to a task started with [spawn](crate::spawn), which can be awaited to get the
+/// output of the task.
+///
+/// Dropping the handle detaches the task, which keeps running. If the task is dropped
+/// before completing, because a callback trapped while it was waiting for a call, the
+/// handle never completes, and the tasks awaiting it are dropped as well, releasing the
+/// guards they hold.
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Whether the task has completed.
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().finished
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.borrow_mut();
+        if let Some(output) = state.output.take() {
+            Poll::Ready(output)
+        } else if state.finished {
+            panic!("JoinHandle polled after completion");
+        } else {
+            state.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+enum MaybeDone<F: Future> {
+    Pending(Pin<Box<F>>),
+    Done(F::Output),
+    Taken,
+}
+
+impl<F: Future> MaybeDone<F> {
+    // Polls the future if it has not completed yet, and returns whether it has.
+    fn poll(&mut self, context: &mut Context<'_>) -> bool {
+        if let MaybeDone::Pending(future) = self {
+            match future.as_mut().poll(context) {
+                Poll::Ready(output) => *self = MaybeDone::Done(output),
+                Poll::Pending => return false,
+            }
+        }
+        true
+    }
+
+    fn take(&mut self) -> F::Output {
+        match mem::replace(self, MaybeDone::Taken) {
+            MaybeDone::Done(output) => output,
+            _ => panic!("future polled after completion"),
+        }
+    }
+}
+
+/// Future for the [join] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Join<A: Future, B: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+}
+
+// The outputs are never pinned, and the futures are pinned in their own boxes.
+impl<A: Future, B: Future> Unpin for Join<A, B> {}
+
+impl<A: Future, B: Future> Future for Join<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let a_done = self.a.poll(context);
+        let b_done = self.b.poll(context);
+        if a_done && b_done {
+            Poll::Ready((self.a.take(), self.b.take()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Awaits two futures concurrently, returning both outputs.
+pub fn join<A: Future, B: Future>(a: A, b: B) -> Join<A, B> {
+    Join {
+        a: MaybeDone::Pending(Box::pin(a)),
+        b: MaybeDone::Pending(Box::pin(b)),
+    }
+}
+
+/// Future for the [join_all] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct JoinAll<F: Future> {
+    futures: Vec<MaybeDone<F>>,
+}
+
+// The outputs are never pinned, and the futures are pinned in their own boxes.
+impl<F: Future> Unpin for JoinAll<F> {}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut all_done = true;
+        for future in self.futures.iter_mut() {
+            all_done &= future.poll(context);
+        }
+        if all_done {
+            Poll::Ready(self.futures.iter_mut().map(MaybeDone::take).collect())
+        } else {
+            Poll::Pending
         }
     }
+}
+
+/// Awaits all the futures concurrently, returning their outputs in the same order.
+pub fn join_all<I>(futures: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    JoinAll {
+        futures: futures
+            .into_iter()
+            .map(|future| MaybeDone::Pending(Box::pin(future)))
+            .collect(),
+    }
+}
+
+/// The output of [select], telling which future completed first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Either<A, B> {
+    /// The first future completed first.
+    Left(A),
+    /// The second future completed first.
+    Right(B),
+}
+
+/// Future for the [select] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Select<A: Future, B: Future> {
+    a: Pin<Box<A>>,
+    b: Pin<Box<B>>,
+}
 
-    fn wake_by_ref(_: *const ()) {}
+impl<A: Future, B: Future> Future for Select<A, B> {
+    type Output = Either<A::Output, B::Output>;
 
-    fn drop(_: *const ()) {}
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(output) = self.a.as_mut().poll(context) {
+            return Poll::Ready(Either::Left(output));
+        }
+        if let Poll::Ready(output) = self.b.as_mut().poll(context) {
+            return Poll::Ready(Either::Right(output));
+        }
+        Poll::Pending
+    }
+}
 
-    pub fn waker(ptr: *const ()) -> Waker {
-        unsafe { Waker::from_raw(raw_waker(ptr)) }
+/// Awaits two futures concurrently, returning the output of the first one to complete and
+/// dropping the other.
+///
+/// If both are ready when polled, the first one wins. Dropping a call future does not cancel
+/// the call: the callee still runs it, and its reply is ignored.
+pub fn select<A: Future, B: Future>(a: A, b: B) -> Select<A, B> {
+    Select {
+        a: Box::pin(a),
+        b: Box::pin(b),
     }
 }
@@ -0,0 +1,157 @@
+use super::*;
+
+// The value of a reply once delivered, and the waker of the task awaiting it.
+type ReplyState<T> = Rc<RefCell<(Option<T>, Option<Waker>)>>;
+
+// A future completed by hand, standing in for the reply to an inter-canister call.
+struct Reply<T> {
+    state: ReplyState<T>,
+}
+
+impl<T> Reply<T> {
+    fn new() -> (Self, ReplyState<T>) {
+        let state = Rc::new(RefCell::new((None, None)));
+        (
+            Reply {
+                state: Rc::clone(&state),
+            },
+            state,
+        )
+    }
+}
+
+impl<T> Future for Reply<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.borrow_mut();
+        match state.0.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                state.1 = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// Delivers `value`, waking the task waiting for it, like the callback of a call.
+fn reply<T>(state: &ReplyState<T>, value: T) {
+    let waker = {
+        let mut state = state.borrow_mut();
+        state.0 = Some(value);
+        state.1.take()
+    };
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+#[test]
+fn join_handle_returns_output_of_task() {
+    let (future, sender) = Reply::new();
+    let child = spawn(async move { future.await * 2 });
+    let result = Rc::new(Cell::new(0));
+    let parent = spawn({
+        let result = Rc::clone(&result);
+        async move { result.set(child.await) }
+    });
+
+    assert!(!parent.is_finished());
+    reply(&sender, 21);
+    assert!(parent.is_finished());
+    assert_eq!(result.get(), 42);
+}
+
+#[test]
+fn join_all_waits_for_every_future_in_order() {
+    let (futures, senders): (Vec<_>, Vec<_>) = (0..3).map(|_| Reply::new()).unzip();
+    let handle = spawn(join_all(futures));
+
+    reply(&senders[2], "c");
+    reply(&senders[0], "a");
+    assert!(!handle.is_finished());
+    reply(&senders[1], "b");
+    assert_eq!(
+        handle.state.borrow_mut().output.take(),
+        Some(vec!["a", "b", "c"])
+    );
+}
+
+#[test]
+fn join_returns_both_outputs() {
+    let (a, a_sender) = Reply::new();
+    let (b, b_sender) = Reply::new();
+    let handle = spawn(join(a, b));
+
+    reply(&b_sender, "b");
+    reply(&a_sender, 1);
+    assert_eq!(handle.state.borrow_mut().output.take(), Some((1, "b")));
+}
+
+#[test]
+fn select_returns_first_output_and_ignores_late_wakeups() {
+    let (a, a_sender) = Reply::<u32>::new();
+    let (b, b_sender) = Reply::new();
+    let handle = spawn(select(a, b));
+
+    reply(&b_sender, "b");
+    assert_eq!(
+        handle.state.borrow_mut().output.take(),
+        Some(Either::Right("b"))
+    );
+    // The loser was dropped, but the waker it registered outlives it.
+    reply(&a_sender, 1);
+}
+
+#[test]
+fn cleanup_drops_task_without_polling_it() {
+    struct DropFlag(Rc<Cell<bool>>);
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    let dropped = Rc::new(Cell::new(false));
+    let (future, sender) = Reply::<()>::new();
+    let flag = DropFlag(Rc::clone(&dropped));
+    let handle = spawn(async move {
+        let _flag = flag;
+        future.await;
+        panic!("polled during cleanup");
+    });
+
+    let waker = sender.borrow_mut().1.take().unwrap();
+    cleanup(waker);
+    assert!(dropped.get());
+    assert!(!handle.is_finished());
+}
+
+#[test]
+fn cleanup_drops_tasks_woken_by_the_drop() {
+    // Replies to the second task when dropped, like the release of what it waits for.
+    struct ReplyOnDrop(ReplyState<()>);
+    impl Drop for ReplyOnDrop {
+        fn drop(&mut self) {
+            reply(&self.0, ());
+        }
+    }
+
+    let (first_future, first_sender) = Reply::<()>::new();
+    let (second_future, second_sender) = Reply::<()>::new();
+    let on_drop = ReplyOnDrop(second_sender);
+    let first = spawn(async move {
+        let _on_drop = on_drop;
+        first_future.await;
+    });
+    let second = spawn(second_future);
+
+    let waker = first_sender.borrow_mut().1.take().unwrap();
+    cleanup(waker);
+    assert!(!first.is_finished());
+    // The second task is not left queued for an unrelated message either.
+    spawn(async {});
+    assert!(!second.is_finished());
+    assert!(QUEUE.with(|queue| queue.borrow().is_empty()));
+}
@@ -10,9 +10,14 @@
 compile_error!("This version of the CDK does not support multithreading.");
 
 pub mod api;
-mod futures;
+pub mod cycles;
+pub mod futures;
+pub mod log;
 mod printer;
 pub mod storage;
+pub mod sync;
+#[cfg(feature = "timers")]
+pub mod timer;
 
 pub use api::call::call;
 pub use api::call::notify;
@@ -44,13 +49,16 @@ pub fn setup() {
     note = "Use the spawn() function instead, it does the same thing but is more appropriately named."
 )]
 pub fn block_on<F: 'static + std::future::Future<Output = ()>>(future: F) {
-    futures::spawn(future);
+    spawn(future);
 }
 
 /// Spawn an asynchronous task that drives the provided future to
 /// completion.
-pub fn spawn<F: 'static + std::future::Future<Output = ()>>(future: F) {
-    futures::spawn(future);
+///
+/// The returned [JoinHandle](futures::JoinHandle) can be awaited to get the output of the
+/// future, or dropped to let the task run on its own.
+pub fn spawn<F: 'static + std::future::Future>(future: F) -> futures::JoinHandle<F::Output> {
+    futures::spawn(log::InMethod::new(future))
 }
 
 /// Format and then print the formatted message
@@ -84,3 +92,51 @@ macro_rules! eprintln {
     ($fmt:expr) => (std::eprintln!($fmt));
     ($fmt:expr, $($arg:tt)*) => (std::eprintln!($fmt, $($arg)*));
 }
+
+/// Format a message and add it to the canister [log](crate::log) at the given level.
+///
+/// ```rust,no_run
+/// ic_cdk::log!(ic_cdk::log::Level::Warn, "balance below {} cycles", 1_000_000);
+/// ```
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => ($crate::log::log($level, format!($($arg)+)));
+}
+
+/// Export a query method that pages through the canister [log](crate::log).
+///
+/// The method is named `get_log` unless another name is given, and has the Candid signature
+/// `(opt nat64, nat32) -> (LogPage) query`, taking the arguments of [entries](crate::log::entries).
+/// A guard function, with the same signature as the guards of `ic-cdk-macros`, can restrict
+/// who may read the log:
+///
+/// ```rust,no_run
+/// fn is_controller() -> Result<(), String> {
+///     // ...
+/// #   Ok(())
+/// }
+///
+/// ic_cdk::export_log_query!("canister_log", guard = is_controller);
+/// ```
+#[macro_export]
+macro_rules! export_log_query {
+    () => {
+        $crate::export_log_query!("get_log");
+    };
+    ($name:literal $(, guard = $guard:path)?) => {
+        #[export_name = concat!("canister_query ", $name)]
+        fn __ic_cdk_export_log_query() {
+            $crate::setup();
+            $crate::log::set_method(Some($name));
+            $(
+                let r: Result<(), String> = $guard();
+                if let Err(e) = r {
+                    $crate::api::call::reject(&e);
+                    return;
+                }
+            )?
+            let (start, limit): (Option<u64>, u32) = $crate::api::call::arg_data();
+            $crate::api::call::reply(($crate::log::entries(start, limit),));
+        }
+    };
+}
@@ -0,0 +1,419 @@
+//! A log of recent events that can be read back from a deployed canister.
+//!
+//! [print](crate::api::print) only reaches the debug log of the replica, which is not
+//! visible on mainnet. This module keeps the most recent entries in a ring buffer instead,
+//! each one with the time, the level, the message and the method that produced it, and
+//! [export_log_query](crate::export_log_query) exports a query method to page through them.
+//!
+//! ```rust,no_run
+//! use ic_cdk::log::Level;
+//!
+//! ic_cdk::export_log_query!("get_log");
+//!
+//! fn transfer(amount: u64) {
+//!     ic_cdk::log!(Level::Info, "transferring {} tokens", amount);
+//! }
+//! ```
+//!
+//! The log is kept in heap memory by default, where it does not survive upgrades. Use
+//! [init_stable] to keep it in stable memory instead.
+//!
+//! Like any other change to the state of the canister, entries are discarded if the
+//! message that logged them traps, and entries logged by a query method are never kept.
+use crate::api::stable::{layout, StableMemory};
+use candid::CandidType;
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[cfg(any(feature = "log", feature = "tracing"))]
+pub(crate) mod facade;
+#[cfg(test)]
+mod tests;
+
+#[cfg(any(feature = "log", feature = "tracing"))]
+pub use facade::set_print_level;
+
+/// The number of entries kept by the log until [init_heap] or [init_stable] is called.
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+/// The length, in bytes, beyond which messages are truncated until [init_heap] or
+/// [init_stable] is called.
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 1024;
+
+/// The maximum number of entries returned by [entries].
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// The severity of a log entry.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    /// Fine-grained tracing.
+    Trace,
+    /// Information useful when debugging.
+    Debug,
+    /// Normal operation.
+    Info,
+    /// Something unexpected that the canister recovered from.
+    Warn,
+    /// A failure.
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        })
+    }
+}
+
+/// An entry of the log.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The position of the entry in the log, counting from the first entry ever logged.
+    pub index: u64,
+    /// The [time](crate::api::time) at which the entry was logged, in nanoseconds.
+    pub timestamp: u64,
+    /// The severity of the entry.
+    pub level: Level,
+    /// The method that logged the entry, if it was logged from a method exported with the
+    /// macros of `ic-cdk-macros`.
+    pub method: Option<String>,
+    /// The message.
+    pub message: String,
+}
+
+/// A range of entries of the log, returned by [entries].
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LogPage {
+    /// The entries, oldest first.
+    pub entries: Vec<LogEntry>,
+    /// The index of the oldest entry still in the log.
+    pub first_index: u64,
+    /// The index the next entry will get.
+    pub next_index: u64,
+}
+
+trait Store {
+    fn first_index(&self) -> u64;
+    fn next_index(&self) -> u64;
+    fn get(&self, index: u64) -> Option<LogEntry>;
+    // Appends `entry`, ignoring its index, and evicts the oldest entry if the log is full.
+    fn push(&mut self, entry: LogEntry);
+}
+
+struct HeapStore {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    max_message_len: usize,
+    next_index: u64,
+}
+
+impl Store for HeapStore {
+    fn first_index(&self) -> u64 {
+        self.next_index - self.entries.len() as u64
+    }
+
+    fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    fn get(&self, index: u64) -> Option<LogEntry> {
+        let position = index.checked_sub(self.first_index())?;
+        self.entries.get(position as usize).cloned()
+    }
+
+    fn push(&mut self, mut entry: LogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        let len = truncate(&entry.message, self.max_message_len).len();
+        if len < entry.message.len() {
+            entry.message.truncate(len);
+            entry.message.shrink_to_fit();
+        }
+        entry.index = self.next_index;
+        self.entries.push_back(entry);
+        self.next_index += 1;
+    }
+}
+
+const MAGIC: &[u8; 3] = b"CLG";
+const LAYOUT_VERSION: u8 = 1;
+
+// Header layout, at offset 0 of the memory:
+//
+// | magic (3) | version (1) | entry size (4) | capacity (8) | next index (8) | reserved (8) |
+//
+// Entry `i` is stored in slot `i % capacity`, each slot holding the length of the entry and
+// `entry size` bytes:
+//
+// | timestamp (8) | level (1) | method length (1) | method | message |
+const ENTRY_SIZE_OFFSET: u64 = 4;
+const CAPACITY_OFFSET: u64 = 8;
+const NEXT_INDEX_OFFSET: u64 = 16;
+const HEADER_SIZE: u64 = 32;
+const ENTRY_HEADER_SIZE: usize = 10;
+
+struct StableStore<M: StableMemory> {
+    memory: M,
+    capacity: u64,
+    entry_size: u32,
+    next_index: u64,
+}
+
+impl<M: StableMemory> StableStore<M> {
+    fn slot_offset(&self, index: u64) -> u64 {
+        HEADER_SIZE + (index % self.capacity) * (4 + self.entry_size as u64)
+    }
+}
+
+impl<M: StableMemory> Store for StableStore<M> {
+    fn first_index(&self) -> u64 {
+        self.next_index.saturating_sub(self.capacity)
+    }
+
+    fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    fn get(&self, index: u64) -> Option<LogEntry> {
+        if index < self.first_index() || index >= self.next_index {
+            return None;
+        }
+        let offset = self.slot_offset(index);
+        let mut bytes = vec![0u8; layout::read_u32(&self.memory, offset) as usize];
+        self.memory.stable64_read(offset + 4, &mut bytes);
+        Some(decode_entry(index, &bytes))
+    }
+
+    fn push(&mut self, entry: LogEntry) {
+        let offset = self.slot_offset(self.next_index);
+        let end = offset + 4 + self.entry_size as u64;
+        // Logging must not trap, so an entry that does not fit in stable memory is dropped.
+        if layout::ensure_size(&self.memory, end).is_err() {
+            return;
+        }
+        let bytes = encode_entry(&entry, self.entry_size as usize);
+        layout::write_u32(&self.memory, offset, bytes.len() as u32);
+        self.memory.stable64_write(offset + 4, &bytes);
+        self.next_index += 1;
+        layout::write_u64(&self.memory, NEXT_INDEX_OFFSET, self.next_index);
+    }
+}
+
+// Encodes `entry` in at most `max_size` bytes, truncating the method and the message if needed.
+fn encode_entry(entry: &LogEntry, max_size: usize) -> Vec<u8> {
+    let method = truncate(entry.method.as_deref().unwrap_or(""), u8::MAX as usize);
+    let method = truncate(method, max_size - ENTRY_HEADER_SIZE);
+    let message = truncate(&entry.message, max_size - ENTRY_HEADER_SIZE - method.len());
+    let mut bytes = Vec::with_capacity(ENTRY_HEADER_SIZE + method.len() + message.len());
+    bytes.extend_from_slice(&entry.timestamp.to_le_bytes());
+    bytes.push(entry.level as u8);
+    bytes.push(method.len() as u8);
+    bytes.extend_from_slice(method.as_bytes());
+    bytes.extend_from_slice(message.as_bytes());
+    bytes
+}
+
+fn decode_entry(index: u64, bytes: &[u8]) -> LogEntry {
+    let mut timestamp = [0u8; 8];
+    timestamp.copy_from_slice(&bytes[..8]);
+    let level = match bytes[8] {
+        0 => Level::Trace,
+        1 => Level::Debug,
+        2 => Level::Info,
+        3 => Level::Warn,
+        _ => Level::Error,
+    };
+    let (method, message) = bytes[ENTRY_HEADER_SIZE..].split_at(bytes[9] as usize);
+    LogEntry {
+        index,
+        timestamp: u64::from_le_bytes(timestamp),
+        level,
+        method: match method {
+            [] => None,
+            _ => Some(String::from_utf8_lossy(method).into_owned()),
+        },
+        message: String::from_utf8_lossy(message).into_owned(),
+    }
+}
+
+// Returns the longest prefix of `s` of at most `max_len` bytes that ends on a char boundary.
+fn truncate(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+thread_local! {
+    static LOG: RefCell<Box<dyn Store>> = RefCell::new(Box::new(HeapStore {
+        entries: VecDeque::new(),
+        capacity: DEFAULT_CAPACITY,
+        max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+        next_index: 0,
+    }));
+    static METHOD: Cell<Option<&'static str>> = Cell::default();
+}
+
+/// Keeps the log in heap memory, holding at most `capacity` entries whose messages are
+/// truncated to `max_message_len` bytes.
+///
+/// The entries logged so far are discarded.
+pub fn init_heap(capacity: usize, max_message_len: usize) {
+    LOG.with(|log| {
+        *log.borrow_mut() = Box::new(HeapStore {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            max_message_len,
+            next_index: 0,
+        })
+    });
+}
+
+/// Keeps the log in `memory`, holding at most `capacity` entries of at most `entry_size`
+/// bytes each, or reopens the log kept there before an upgrade.
+///
+/// Each entry takes the 10 bytes of its timestamp, level and method length, followed by the
+/// method name and the message, which are truncated to fit. The memory grows as entries are
+/// logged, up to about `capacity * (entry_size + 4)` bytes; entries that do not fit once
+/// stable memory is exhausted are dropped.
+///
+/// ```rust,no_run
+/// use ic_cdk::api::stable::{CanisterStableMemory, MemoryId, MemoryManager};
+///
+/// let manager = MemoryManager::init(CanisterStableMemory::default());
+/// ic_cdk::log::init_stable(manager.get(MemoryId::new(0)), 10_000, 256);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0, if `entry_size` is smaller than 10 bytes, if `memory` is not
+/// empty and does not hold a log, or if it holds a log with a different capacity or entry
+/// size.
+pub fn init_stable<M: StableMemory + 'static>(memory: M, capacity: u64, entry_size: u32) {
+    assert!(capacity > 0, "The capacity of the log must not be 0.");
+    assert!(
+        entry_size as usize >= ENTRY_HEADER_SIZE,
+        "The entries of the log must be at least {} bytes.",
+        ENTRY_HEADER_SIZE
+    );
+    let next_index = if memory.stable64_size() == 0 {
+        layout::ensure_size(&memory, HEADER_SIZE)
+            .unwrap_or_else(|_| panic!("Log: out of stable memory."));
+        layout::write_magic(&memory, MAGIC, LAYOUT_VERSION);
+        layout::write_u32(&memory, ENTRY_SIZE_OFFSET, entry_size);
+        layout::write_u64(&memory, CAPACITY_OFFSET, capacity);
+        layout::write_u64(&memory, NEXT_INDEX_OFFSET, 0);
+        0
+    } else {
+        layout::check_magic(&memory, MAGIC, LAYOUT_VERSION, "log");
+        let stored_capacity = layout::read_u64(&memory, CAPACITY_OFFSET);
+        let stored_entry_size = layout::read_u32(&memory, ENTRY_SIZE_OFFSET);
+        if stored_capacity != capacity || stored_entry_size != entry_size {
+            panic!(
+                "Log mismatch: stored {} entries of {} bytes, expected {} entries of {} bytes.",
+                stored_capacity, stored_entry_size, capacity, entry_size
+            );
+        }
+        layout::read_u64(&memory, NEXT_INDEX_OFFSET)
+    };
+    LOG.with(|log| {
+        *log.borrow_mut() = Box::new(StableStore {
+            memory,
+            capacity,
+            entry_size,
+            next_index,
+        })
+    });
+}
+
+/// Logs `message` at `level`, and prints it to the debug log.
+///
+/// The [log](crate::log!) macro formats the message in place.
+pub fn log(level: Level, message: impl Into<String>) {
+    let message = message.into();
+    crate::api::print(format!("[{}] {}", level, message));
+    let entry = LogEntry {
+        index: 0,
+        timestamp: crate::api::time(),
+        level,
+        method: METHOD.with(|method| method.get()).map(str::to_string),
+        message,
+    };
+    LOG.with(|log| log.borrow_mut().push(entry));
+}
+
+/// Returns at most `limit` entries, starting at index `start`, or the most recent entries if
+/// `start` is `None`.
+///
+/// `limit` is capped at [MAX_PAGE_SIZE]. Entries evicted from the log are skipped, so the
+/// first entry returned may have an index greater than `start`.
+pub fn entries(start: Option<u64>, limit: u32) -> LogPage {
+    LOG.with(|log| {
+        let log = log.borrow();
+        let (first_index, next_index) = (log.first_index(), log.next_index());
+        let limit = limit.min(MAX_PAGE_SIZE) as u64;
+        let start = start
+            .unwrap_or_else(|| next_index.saturating_sub(limit))
+            .max(first_index)
+            .min(next_index);
+        let end = start.saturating_add(limit).min(next_index);
+        LogPage {
+            entries: (start..end).filter_map(|index| log.get(index)).collect(),
+            first_index,
+            next_index,
+        }
+    })
+}
+
+/// Records the method being executed, so that entries are attributed to it.
+///
+/// Called by the entry points generated by `ic-cdk-macros`.
+#[doc(hidden)]
+pub fn set_method(method: Option<&'static str>) {
+    METHOD.with(|current| current.set(method));
+}
+
+/// A future that attributes the entries logged while polling it to the method that spawned
+/// it, even when it is resumed in the callback of an inter-canister call.
+pub(crate) struct InMethod<F> {
+    method: Option<&'static str>,
+    future: F,
+}
+
+impl<F> InMethod<F> {
+    pub(crate) fn new(future: F) -> Self {
+        InMethod {
+            method: METHOD.with(|method| method.get()),
+            future,
+        }
+    }
+}
+
+impl<F: Future> Future for InMethod<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // The inner future is never moved out of `self`, so it stays pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        set_method(this.method);
+        unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx)
+    }
+}
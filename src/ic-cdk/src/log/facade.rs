@@ -0,0 +1,180 @@
+//! Backends for the `log` and `tracing` crates, printing to the debug log of the replica.
+//!
+//! They are installed by [setup](crate::setup), so that messages emitted by dependencies
+//! through either crate are printed with [print](crate::api::print) instead of being dropped.
+use super::Level;
+use std::cell::Cell;
+
+#[cfg(test)]
+mod tests;
+
+thread_local! {
+    static PRINT_LEVEL: Cell<Level> = Cell::new(Level::Info);
+}
+
+/// Sets the most verbose level printed by the `log` and `tracing` backends installed by
+/// [setup](crate::setup). Defaults to [Level::Info].
+///
+/// Call it from `#[init]` and `#[post_upgrade]`, since the level is reset when the canister
+/// is upgraded.
+pub fn set_print_level(level: Level) {
+    PRINT_LEVEL.with(|current| current.set(level));
+    #[cfg(feature = "log")]
+    ::log::set_max_level(logger::level_filter(level));
+}
+
+fn is_printed(level: Level) -> bool {
+    PRINT_LEVEL.with(|current| level >= current.get())
+}
+
+fn print(level: Level, target: &str, message: &str) {
+    crate::api::print(format!("[{}] {}: {}", level, target, message));
+}
+
+pub(crate) fn install() {
+    #[cfg(feature = "log")]
+    logger::install();
+    #[cfg(feature = "tracing")]
+    subscriber::install();
+}
+
+#[cfg(feature = "log")]
+mod logger {
+    use super::Level;
+
+    struct PrintLogger;
+
+    static LOGGER: PrintLogger = PrintLogger;
+
+    fn level(level: ::log::Level) -> Level {
+        match level {
+            ::log::Level::Error => Level::Error,
+            ::log::Level::Warn => Level::Warn,
+            ::log::Level::Info => Level::Info,
+            ::log::Level::Debug => Level::Debug,
+            ::log::Level::Trace => Level::Trace,
+        }
+    }
+
+    pub(super) fn level_filter(level: Level) -> ::log::LevelFilter {
+        match level {
+            Level::Error => ::log::LevelFilter::Error,
+            Level::Warn => ::log::LevelFilter::Warn,
+            Level::Info => ::log::LevelFilter::Info,
+            Level::Debug => ::log::LevelFilter::Debug,
+            Level::Trace => ::log::LevelFilter::Trace,
+        }
+    }
+
+    impl ::log::Log for PrintLogger {
+        fn enabled(&self, metadata: &::log::Metadata<'_>) -> bool {
+            super::is_printed(level(metadata.level()))
+        }
+
+        fn log(&self, record: &::log::Record<'_>) {
+            if self.enabled(record.metadata()) {
+                let message = record.args().to_string();
+                super::print(level(record.level()), record.target(), &message);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    pub(super) fn install() {
+        // Another logger may have been installed by the canister, in which case it is kept.
+        if ::log::set_logger(&LOGGER).is_ok() {
+            ::log::set_max_level(level_filter(super::PRINT_LEVEL.with(|level| level.get())));
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+mod subscriber {
+    use super::Level;
+    use std::fmt::{self, Write};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tracing::field::{Field, Visit};
+    use tracing::subscriber::Interest;
+    use tracing::{span, Event, Metadata, Subscriber};
+
+    // Prints events; spans are given identifiers but otherwise ignored.
+    struct PrintSubscriber {
+        next_span: AtomicU64,
+    }
+
+    fn level(level: &tracing::Level) -> Level {
+        match *level {
+            tracing::Level::ERROR => Level::Error,
+            tracing::Level::WARN => Level::Warn,
+            tracing::Level::INFO => Level::Info,
+            tracing::Level::DEBUG => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+
+    // Formats the `message` field first, followed by the other fields as `name=value`.
+    #[derive(Default)]
+    struct Fields {
+        message: String,
+        others: String,
+    }
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                let _ = write!(self.message, "{:?}", value);
+            } else {
+                let _ = write!(self.others, " {}={:?}", field.name(), value);
+            }
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "message" {
+                self.message.push_str(value);
+            } else {
+                self.record_debug(field, &value);
+            }
+        }
+    }
+
+    impl Subscriber for PrintSubscriber {
+        // The level can change at any time, so the callsites must not cache whether they
+        // are enabled.
+        fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+            Interest::sometimes()
+        }
+
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            super::is_printed(level(metadata.level()))
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let metadata = event.metadata();
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            fields.message.push_str(&fields.others);
+            super::print(level(metadata.level()), metadata.target(), &fields.message);
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    pub(super) fn install() {
+        // Another subscriber may have been installed by the canister, in which case it is kept.
+        let _ = tracing::subscriber::set_global_default(PrintSubscriber {
+            // Span identifiers must not be 0.
+            next_span: AtomicU64::new(1),
+        });
+    }
+}
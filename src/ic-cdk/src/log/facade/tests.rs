@@ -0,0 +1,36 @@
+use super::*;
+use crate::api::backend::{set_system_api, MockSystemApi};
+use std::rc::Rc;
+
+fn mock() -> Rc<MockSystemApi> {
+    let mock = Rc::new(MockSystemApi::new());
+    set_system_api(mock.clone());
+    install();
+    mock
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_records_are_printed_above_level() {
+    let mock = mock();
+    set_print_level(Level::Warn);
+    ::log::info!("hidden");
+    ::log::warn!("shown {}", 1);
+    assert_eq!(
+        mock.debug_prints(),
+        ["[WARN] ic_cdk::log::facade::tests: shown 1"]
+    );
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_events_are_printed_above_level() {
+    let mock = mock();
+    set_print_level(Level::Debug);
+    tracing::trace!("hidden");
+    tracing::debug!(amount = 5, "shown");
+    assert_eq!(
+        mock.debug_prints(),
+        ["[DEBUG] ic_cdk::log::facade::tests: shown amount=5"]
+    );
+}
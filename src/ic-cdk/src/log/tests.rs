@@ -0,0 +1,137 @@
+use super::*;
+use crate::api::backend::{poll_once, set_system_api, MockSystemApi};
+use crate::api::stable::VectorMemory;
+use std::rc::Rc;
+
+// Tests may share a thread, so each one starts from a fresh backend and method.
+fn mock_at(time: u64) -> Rc<MockSystemApi> {
+    let mock = Rc::new(MockSystemApi::new());
+    mock.set_time(time);
+    set_system_api(mock.clone());
+    set_method(None);
+    mock
+}
+
+fn messages(page: &LogPage) -> Vec<&str> {
+    page.entries
+        .iter()
+        .map(|entry| entry.message.as_str())
+        .collect()
+}
+
+#[test]
+fn heap_log_keeps_most_recent_entries() {
+    let mock = mock_at(42);
+    init_heap(3, DEFAULT_MAX_MESSAGE_LEN);
+    set_method(Some("greet"));
+    for i in 0..5 {
+        crate::log!(Level::Info, "entry {}", i);
+    }
+
+    let page = entries(None, 10);
+    assert_eq!(messages(&page), ["entry 2", "entry 3", "entry 4"]);
+    assert_eq!((page.first_index, page.next_index), (2, 5));
+    assert_eq!(
+        page.entries[0],
+        LogEntry {
+            index: 2,
+            timestamp: 42,
+            level: Level::Info,
+            method: Some("greet".to_string()),
+            message: "entry 2".to_string(),
+        }
+    );
+    assert_eq!(mock.debug_prints().last().unwrap(), "[INFO] entry 4");
+}
+
+#[test]
+fn heap_log_truncates_long_messages() {
+    mock_at(0);
+    init_heap(2, 5);
+    log(Level::Info, "ab\u{e9}cdef");
+    log(Level::Info, "abcd\u{e9}");
+    assert_eq!(messages(&entries(None, 2)), ["ab\u{e9}c", "abcd"]);
+}
+
+#[test]
+fn entries_pages_from_start_or_most_recent() {
+    mock_at(0);
+    init_heap(10, DEFAULT_MAX_MESSAGE_LEN);
+    for i in 0..6 {
+        log(Level::Debug, format!("entry {}", i));
+    }
+
+    assert_eq!(messages(&entries(None, 2)), ["entry 4", "entry 5"]);
+    assert_eq!(messages(&entries(Some(1), 2)), ["entry 1", "entry 2"]);
+    assert_eq!(messages(&entries(Some(5), 2)), ["entry 5"]);
+    assert!(entries(Some(9), 2).entries.is_empty());
+}
+
+#[test]
+fn stable_log_survives_reopening() {
+    mock_at(7);
+    let memory = VectorMemory::default();
+    init_stable(memory.clone(), 2, 64);
+    set_method(Some("transfer"));
+    log(Level::Warn, "first");
+    log(Level::Error, "second");
+    log(Level::Info, "third");
+
+    init_heap(10, DEFAULT_MAX_MESSAGE_LEN);
+    init_stable(memory, 2, 64);
+    let page = entries(None, 10);
+    assert_eq!(messages(&page), ["second", "third"]);
+    assert_eq!(page.entries[0].level, Level::Error);
+    assert_eq!(page.entries[0].method.as_deref(), Some("transfer"));
+    assert_eq!((page.first_index, page.next_index), (1, 3));
+}
+
+#[test]
+fn stable_log_truncates_long_messages() {
+    mock_at(0);
+    init_stable(VectorMemory::default(), 4, 16);
+    log(Level::Info, "ééééé");
+
+    // 10 bytes of header leave room for 3 of the 2-byte chars.
+    assert_eq!(messages(&entries(None, 1)), ["ééé"]);
+}
+
+#[test]
+#[should_panic(expected = "Log mismatch")]
+fn stable_log_rejects_different_capacity() {
+    let memory = VectorMemory::default();
+    init_stable(memory.clone(), 2, 64);
+    init_stable(memory, 3, 64);
+}
+
+#[test]
+fn spawned_futures_keep_their_method() {
+    mock_at(0);
+    init_heap(10, DEFAULT_MAX_MESSAGE_LEN);
+    set_method(Some("update"));
+    let future = InMethod::new(async { log(Level::Info, "resumed") });
+
+    set_method(Some("other"));
+    poll_once(future).unwrap();
+    assert_eq!(
+        entries(None, 1).entries[0].method.as_deref(),
+        Some("update")
+    );
+}
+
+#[test]
+fn log_query_attributes_entries_of_its_guard() {
+    let mock = mock_at(0);
+    init_heap(10, DEFAULT_MAX_MESSAGE_LEN);
+    set_method(Some("transfer"));
+
+    fn deny() -> Result<(), String> {
+        log(Level::Warn, "denied");
+        Err("denied".to_string())
+    }
+    crate::export_log_query!("test_log", guard = deny);
+    __ic_cdk_export_log_query();
+    assert_eq!(mock.rejection().unwrap(), "denied");
+    let page = entries(None, 1);
+    assert_eq!(page.entries[0].method.as_deref(), Some("test_log"));
+}
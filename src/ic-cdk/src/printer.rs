@@ -2,6 +2,12 @@ use crate::api;
 use std::panic;
 
 /// Sets a custom panic hook, uses debug.trace
+///
+/// The hook cannot turn a panic into a reject: panics abort on wasm32, and the trap that
+/// follows discards every effect of the message, including a reply or reject made by the
+/// hook, so the caller always sees `CANISTER_ERROR`. Methods that want to reject cleanly
+/// must do so without panicking, for instance through a guard, or by calling
+/// [reject](crate::api::call::reject) from a `manual_reply` method.
 pub fn set_panic_hook() {
     panic::set_hook(Box::new(|info| {
         let file = info.location().unwrap().file();
@@ -18,11 +24,15 @@ pub fn set_panic_hook() {
 
         let err_info = format!("Panicked at '{}', {}:{}:{}", msg, file, line, col);
         api::print(&err_info);
+        // Outside of a canister the panic unwinds as usual, so that tests can observe it.
+        #[cfg(target_arch = "wasm32")]
         api::trap(&err_info);
     }));
 }
 
-/// Sets stdout, stderr, and a custom panic hook
+/// Sets a custom panic hook, and the backends of the `log` and `tracing` crates if enabled
 pub fn hook() {
     set_panic_hook();
+    #[cfg(any(feature = "log", feature = "tracing"))]
+    crate::log::facade::install();
 }
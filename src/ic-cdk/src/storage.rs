@@ -1,27 +1,336 @@
 //! Tools for managing stable storage of data in a canister.
 use crate::api::stable;
+use std::convert::TryInto;
+use std::io::{self, Read, Seek, Write};
+
+#[cfg(test)]
+mod tests;
+
+const WASM_PAGE_SIZE_IN_BYTES: u64 = 64 * 1024;
+
+// Layout written by `stable_save`, at offset 0 of stable memory:
+//
+// | magic (4) | version (1) | reserved (3) | payload length (8) | payload |
+//
+// The length is little-endian. Older versions wrote the bare payload, which starts with the
+// Candid magic "DIDL" rather than SAVE_MAGIC. The magic is compared in full, since it only
+// differs from VERSIONED_MAGIC in its last byte.
+const SAVE_MAGIC: &[u8; 4] = b"CDKB";
+const SAVE_VERSION: u8 = 1;
+const SAVE_HEADER_SIZE: usize = 16;
+const CANDID_MAGIC: &[u8; 4] = b"DIDL";
+const BUFFER_SIZE: usize = 64 * 1024;
 
 /// Saves the storage into the stable memory.
 ///
-/// This will override any value previously stored in stable memory.
+/// The value is streamed to stable memory as it is encoded, after a header holding its
+/// length. This will override any value previously stored in stable memory.
 pub fn stable_save<T>(t: T) -> Result<(), candid::Error>
 where
     T: candid::utils::ArgumentEncoder,
 {
-    candid::write_args(&mut stable::StableWriter::default(), t)
+    let writer = stable::StableWriter::with_memory(
+        stable::CanisterStableMemory::default(),
+        SAVE_HEADER_SIZE as u64,
+    );
+    let mut writer = CountingWriter {
+        inner: stable::BufferedStableWriter::with_writer(BUFFER_SIZE, writer),
+        count: 0,
+    };
+    candid::write_args(&mut writer, t)?;
+    writer.inner.flush().map_err(candid::Error::msg)?;
+    let mut header = [0u8; SAVE_HEADER_SIZE];
+    header[..4].copy_from_slice(SAVE_MAGIC);
+    header[4] = SAVE_VERSION;
+    header[8..].copy_from_slice(&writer.count.to_le_bytes());
+    stable::stable64_write(0, &header);
+    Ok(())
 }
 
 /// Restores a value from the stable memory to the storage.
 ///
-/// There can only be one value in stable memory, currently.
+/// There can only be one value in stable memory, currently. Only the bytes written by
+/// [stable_save] are read, through a [BufferedStableReader](stable::BufferedStableReader),
+/// rather than the whole of stable memory. Candid decodes from a contiguous slice, so the
+/// payload itself is still copied to the heap, into a buffer of exactly its saved length,
+/// before being decoded. Values saved by older versions, without a length, are read along
+/// with the rest of stable memory.
 pub fn stable_restore<T>() -> Result<T, String>
 where
     T: for<'de> candid::utils::ArgumentDecoder<'de>,
 {
-    let bytes = stable::stable_bytes();
+    let mut reader = stable::BufferedStableReader::new(BUFFER_SIZE);
+    let mut header = [0u8; SAVE_HEADER_SIZE];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| "stable memory is empty".to_string())?;
+
+    let mut bytes = Vec::new();
+    if &header[..4] == CANDID_MAGIC {
+        // Saved by an older version, without a header: the payload runs up to an unknown
+        // point of stable memory.
+        reader
+            .seek(io::SeekFrom::Start(0))
+            .and_then(|_| reader.read_to_end(&mut bytes))
+            .map_err(|e| format!("{:?}", e))?;
+    } else if &header[..4] == SAVE_MAGIC {
+        if header[4] != SAVE_VERSION {
+            return Err(format!("unsupported stable_save version {}", header[4]));
+        }
+        let length = u64::from_le_bytes(header[8..].try_into().unwrap());
+        let available = stable::stable64_size() * WASM_PAGE_SIZE_IN_BYTES - SAVE_HEADER_SIZE as u64;
+        if length > available {
+            return Err(format!(
+                "saved length of {} bytes exceeds the {} bytes of stable memory",
+                length, available
+            ));
+        }
+        bytes.reserve_exact(length as usize);
+        reader
+            .take(length)
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("{:?}", e))?;
+    } else {
+        return Err("stable memory does not hold a value saved by stable_save".to_string());
+    }
 
     let mut de =
         candid::de::IDLDeserialize::new(bytes.as_slice()).map_err(|e| format!("{:?}", e))?;
     let res = candid::utils::ArgumentDecoder::decode(&mut de).map_err(|e| format!("{:?}", e))?;
     Ok(res)
 }
+
+// Counts the bytes written through it, to record the length of the payload.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+const VERSIONED_MAGIC: &[u8; 4] = b"CDKS";
+
+// Layout written by `stable_save_versioned`, at offset 0 of stable memory:
+//
+// | magic (4) | schema version (4) | payload length (8) | payload CRC32 (4) | payload |
+//
+// All integers are little-endian.
+const VERSIONED_HEADER_SIZE: usize = 20;
+
+/// Saves the storage into the stable memory, tagged with the `version` of its schema.
+///
+/// The payload is preceded by a header holding the version, its length and its checksum,
+/// so that [stable_restore_versioned] can tell which schema was written and detect
+/// corruption. Like with [stable_save], the payload is streamed to stable memory as it is
+/// encoded. This will override any value previously stored in stable memory.
+pub fn stable_save_versioned<T>(version: u32, t: T) -> Result<(), candid::Error>
+where
+    T: candid::utils::ArgumentEncoder,
+{
+    let writer = stable::StableWriter::with_memory(
+        stable::CanisterStableMemory::default(),
+        VERSIONED_HEADER_SIZE as u64,
+    );
+    let mut writer = ChecksummingWriter {
+        inner: stable::BufferedStableWriter::with_writer(BUFFER_SIZE, writer),
+        count: 0,
+        crc: stable::Crc32Hasher::new(),
+    };
+    candid::write_args(&mut writer, t)?;
+    writer.inner.flush().map_err(candid::Error::msg)?;
+    let mut header = [0u8; VERSIONED_HEADER_SIZE];
+    header[..4].copy_from_slice(VERSIONED_MAGIC);
+    header[4..8].copy_from_slice(&version.to_le_bytes());
+    header[8..16].copy_from_slice(&writer.count.to_le_bytes());
+    header[16..].copy_from_slice(&writer.crc.finish().to_le_bytes());
+    stable::stable64_write(0, &header);
+    Ok(())
+}
+
+// Counts and checksums the bytes written through it.
+struct ChecksummingWriter<W> {
+    inner: W,
+    count: u64,
+    crc: stable::Crc32Hasher,
+}
+
+impl<W: Write> Write for ChecksummingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        self.crc.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+type Decoder<T> = Box<dyn Fn(&[u8]) -> Result<T, candid::Error>>;
+
+/// The decoders of the schema versions that [stable_restore_versioned] accepts.
+///
+/// ```rust,no_run
+/// # use candid::{CandidType, Deserialize};
+/// # #[derive(CandidType, Deserialize)]
+/// # struct StateV1 { users: Vec<String> }
+/// # #[derive(CandidType, Deserialize)]
+/// # struct StateV2 { users: Vec<(String, u64)> }
+/// use ic_cdk::storage::{stable_restore_versioned, Migrations};
+///
+/// let (state,): (StateV2,) = stable_restore_versioned(
+///     Migrations::new(2).with_migration(1, |(old,): (StateV1,)| {
+///         (StateV2 { users: old.users.into_iter().map(|user| (user, 0)).collect() },)
+///     }),
+/// )
+/// .expect("failed to restore the state");
+/// ```
+pub struct Migrations<T> {
+    current_version: u32,
+    migrations: Vec<(u32, Decoder<T>)>,
+}
+
+impl<T> Migrations<T>
+where
+    T: for<'de> candid::utils::ArgumentDecoder<'de>,
+{
+    /// Accepts payloads of `current_version`, decoded as `T`.
+    pub fn new(current_version: u32) -> Self {
+        Migrations {
+            current_version,
+            migrations: Vec::new(),
+        }
+    }
+}
+
+impl<T> Migrations<T> {
+    /// Also accepts payloads of `version`, decoded as `Old` and converted by `migrate`.
+    pub fn with_migration<Old, F>(mut self, version: u32, migrate: F) -> Self
+    where
+        Old: for<'de> candid::utils::ArgumentDecoder<'de>,
+        F: Fn(Old) -> T + 'static,
+    {
+        let decode = move |bytes: &[u8]| candid::utils::decode_args(bytes).map(&migrate);
+        self.migrations.push((version, Box::new(decode)));
+        self
+    }
+}
+
+/// An error restoring a value saved with [stable_save_versioned].
+#[derive(Debug)]
+pub enum StableRestoreError {
+    /// The stable memory does not start with the header written by [stable_save_versioned].
+    BadMagic,
+    /// The payload was written with a version that has no decoder in the [Migrations].
+    UnknownVersion(u32),
+    /// The header announces a payload longer than the stable memory.
+    Truncated {
+        /// The announced payload length, in bytes.
+        length: u64,
+        /// The bytes available after the header.
+        available: u64,
+    },
+    /// The payload does not match the checksum of the header.
+    ChecksumMismatch {
+        /// The checksum recorded in the header.
+        expected: u32,
+        /// The checksum of the payload found in stable memory.
+        actual: u32,
+    },
+    /// The payload could not be decoded.
+    Decode(candid::Error),
+}
+
+impl std::fmt::Display for StableRestoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadMagic => f.write_str("stable memory does not hold a versioned save"),
+            Self::UnknownVersion(version) => write!(f, "unknown schema version {}", version),
+            Self::Truncated { length, available } => write!(
+                f,
+                "payload of {} bytes exceeds the {} bytes of stable memory",
+                length, available
+            ),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "payload checksum {:#010x} does not match {:#010x}",
+                actual, expected
+            ),
+            Self::Decode(e) => write!(f, "failed to decode the payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StableRestoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Restores a value saved with [stable_save_versioned].
+///
+/// Payloads of the current version of `migrations` are decoded as `T`; payloads of older
+/// versions go through the matching migration.
+pub fn stable_restore_versioned<T>(migrations: Migrations<T>) -> Result<T, StableRestoreError>
+where
+    T: for<'de> candid::utils::ArgumentDecoder<'de>,
+{
+    let size = stable::stable64_size() * WASM_PAGE_SIZE_IN_BYTES;
+    if size < VERSIONED_HEADER_SIZE as u64 {
+        return Err(StableRestoreError::BadMagic);
+    }
+    let mut header = [0u8; VERSIONED_HEADER_SIZE];
+    stable::stable64_read(0, &mut header);
+    if &header[..4] != VERSIONED_MAGIC {
+        return Err(StableRestoreError::BadMagic);
+    }
+    let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    let length = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let expected = u32::from_le_bytes(header[16..].try_into().unwrap());
+
+    let available = size - VERSIONED_HEADER_SIZE as u64;
+    if length > available {
+        return Err(StableRestoreError::Truncated { length, available });
+    }
+    // Candid decodes from a contiguous slice, so the payload is read into a buffer of
+    // exactly its saved length.
+    let reader = stable::StableReader::with_memory(
+        stable::CanisterStableMemory::default(),
+        VERSIONED_HEADER_SIZE as u64,
+    );
+    let reader = stable::BufferedStableReader::with_reader(BUFFER_SIZE, reader);
+    let mut payload = Vec::new();
+    payload.reserve_exact(length as usize);
+    reader
+        .take(length)
+        .read_to_end(&mut payload)
+        .map_err(|_| StableRestoreError::Truncated { length, available })?;
+    let actual = stable::crc32(&payload);
+    if actual != expected {
+        return Err(StableRestoreError::ChecksumMismatch { expected, actual });
+    }
+
+    if version == migrations.current_version {
+        return candid::utils::decode_args(&payload).map_err(StableRestoreError::Decode);
+    }
+    let (_, decode) = migrations
+        .migrations
+        .iter()
+        .find(|(v, _)| *v == version)
+        .ok_or(StableRestoreError::UnknownVersion(version))?;
+    decode(&payload).map_err(StableRestoreError::Decode)
+}
@@ -0,0 +1,132 @@
+use super::*;
+use crate::api::backend::{set_system_api, MockSystemApi};
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn mock() -> Rc<MockSystemApi> {
+    let mock = Rc::new(MockSystemApi::new());
+    set_system_api(mock.clone());
+    mock
+}
+
+#[test]
+fn restores_current_version() {
+    let mock = mock();
+    stable_save_versioned(3, ()).unwrap();
+    assert_eq!(&mock.stable_memory()[..8], b"CDKS\x03\x00\x00\x00");
+    stable_restore_versioned::<()>(Migrations::new(3)).unwrap();
+}
+
+#[test]
+fn versioned_save_records_length_and_checksum_of_payload() {
+    let mock = mock();
+    stable_save_versioned(1, (7u64, "seven")).unwrap();
+    let payload = candid::utils::encode_args((7u64, "seven")).unwrap();
+    let bytes = mock.stable_memory();
+    assert_eq!(&bytes[8..16], &(payload.len() as u64).to_le_bytes());
+    assert_eq!(&bytes[16..20], &stable::crc32(&payload).to_le_bytes());
+    assert_eq!(
+        &bytes[VERSIONED_HEADER_SIZE..VERSIONED_HEADER_SIZE + payload.len()],
+        &payload[..]
+    );
+}
+
+#[test]
+fn dispatches_to_migration_of_stored_version() {
+    mock();
+    stable_save_versioned(1, ()).unwrap();
+    let migrated = Rc::new(Cell::new(None));
+    let migrations = Migrations::new(3)
+        .with_migration(1, {
+            let migrated = migrated.clone();
+            move |()| migrated.set(Some(1))
+        })
+        .with_migration(2, {
+            let migrated = migrated.clone();
+            move |()| migrated.set(Some(2))
+        });
+    stable_restore_versioned::<()>(migrations).unwrap();
+    assert_eq!(migrated.get(), Some(1));
+
+    stable_save_versioned(4, ()).unwrap();
+    assert!(matches!(
+        stable_restore_versioned::<()>(Migrations::new(3)),
+        Err(StableRestoreError::UnknownVersion(4))
+    ));
+}
+
+#[test]
+fn detects_foreign_and_corrupted_memory() {
+    let mock = mock();
+    assert!(matches!(
+        stable_restore_versioned::<()>(Migrations::new(1)),
+        Err(StableRestoreError::BadMagic)
+    ));
+
+    stable_save_versioned(1, ()).unwrap();
+    let mut bytes = mock.stable_memory();
+    bytes[VERSIONED_HEADER_SIZE] ^= 1;
+    mock.set_stable_memory(bytes.clone());
+    assert!(matches!(
+        stable_restore_versioned::<()>(Migrations::new(1)),
+        Err(StableRestoreError::ChecksumMismatch { .. })
+    ));
+
+    bytes[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+    mock.set_stable_memory(bytes);
+    assert!(matches!(
+        stable_restore_versioned::<()>(Migrations::new(1)),
+        Err(StableRestoreError::Truncated {
+            length: u64::MAX,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn save_records_header_and_payload_length() {
+    let mock = mock();
+    stable_save(()).unwrap();
+    let bytes = mock.stable_memory();
+    assert_eq!(&bytes[..8], b"CDKB\x01\x00\x00\x00");
+    assert_eq!(&bytes[8..SAVE_HEADER_SIZE], &4u64.to_le_bytes());
+    assert_eq!(&bytes[SAVE_HEADER_SIZE..SAVE_HEADER_SIZE + 4], b"DIDL");
+    stable_restore::<()>().unwrap();
+}
+
+#[test]
+fn restores_unprefixed_saves() {
+    let mock = mock();
+    mock.set_stable_memory(b"DIDL\x00\x00".to_vec());
+    stable_restore::<()>().unwrap();
+}
+
+#[test]
+fn rejects_foreign_memory_and_length_beyond_stable_memory() {
+    let mock = mock();
+    assert!(stable_restore::<()>().is_err());
+    mock.set_stable_memory(vec![0xff; 16]);
+    let err = stable_restore::<()>().unwrap_err();
+    assert!(err.contains("does not hold"), "{}", err);
+
+    // The low 32 bits of this length read "DIDL", which no longer makes it a legacy save.
+    let mut header = b"CDKB\x01\x00\x00\x00".to_vec();
+    header.extend_from_slice(&0x4C44_4944u64.to_le_bytes());
+    mock.set_stable_memory(header);
+    let err = stable_restore::<()>().unwrap_err();
+    assert!(err.contains("exceeds"), "{}", err);
+}
+
+#[test]
+fn does_not_mistake_versioned_saves_for_plain_ones() {
+    mock();
+    stable_save_versioned(1, ()).unwrap();
+    let err = stable_restore::<()>().unwrap_err();
+    assert!(err.contains("does not hold"), "{}", err);
+
+    stable_save(()).unwrap();
+    assert!(matches!(
+        stable_restore_versioned::<()>(Migrations::new(1)),
+        Err(StableRestoreError::BadMagic)
+    ));
+}
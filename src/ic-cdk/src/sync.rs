@@ -0,0 +1,299 @@
+//! Locks and guards for state shared across `await` points.
+//!
+//! While a method awaits the reply to an inter-canister call, other messages run and may
+//! modify the same state. [Mutex] and [RwLock] guards can be held across `await` points to
+//! keep them out, and [CallerGuard] rejects a second call from a principal whose first call
+//! is still running.
+//!
+//! Taking a lock never waits: a call that finds the lock held fails right away, and its
+//! error is meant to be returned to the caller, who can retry. A call cannot wait for a lock
+//! released in another message, since it would then resume in the call context of that
+//! message, replying to the wrong caller.
+//!
+//! ```rust,no_run
+//! use ic_cdk::sync::{CallerGuard, Mutex};
+//! use std::collections::BTreeMap;
+//!
+//! thread_local! {
+//!     static BALANCES: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+//! }
+//!
+//! async fn withdraw(account: String, amount: u64) -> Result<(), String> {
+//!     let _guard = CallerGuard::new(ic_cdk::caller())?;
+//!     let mut balances = BALANCES.with(Mutex::try_lock)?;
+//!     // ... transfer `amount` with an inter-canister call, holding the lock ...
+//!     *balances.entry(account).or_default() -= amount;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Guards are released when they are dropped, including when a callback traps: the future
+//! of the call is then dropped by the cleanup of the call, which drops the guards it holds.
+use crate::export::Principal;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+struct MutexInner<T> {
+    locked: Cell<bool>,
+    value: UnsafeCell<T>,
+}
+
+/// A lock that can be held across `await` points.
+///
+/// The mutex is a handle: clones share the same value, and [try_lock](Mutex::try_lock)
+/// returns a guard that does not borrow the handle, so that a mutex kept in a
+/// `thread_local!` can be locked with `STATE.with(Mutex::try_lock)?`.
+pub struct Mutex<T> {
+    inner: Rc<MutexInner<T>>,
+}
+
+impl<T> Mutex<T> {
+    /// Creates an unlocked mutex holding `value`.
+    pub fn new(value: T) -> Self {
+        Mutex {
+            inner: Rc::new(MutexInner {
+                locked: Cell::new(false),
+                value: UnsafeCell::new(value),
+            }),
+        }
+    }
+
+    /// Locks the mutex, or fails if it is locked already.
+    ///
+    /// The error message is meant to be returned to the caller.
+    pub fn try_lock(&self) -> Result<MutexGuard<T>, String> {
+        if self.inner.locked.replace(true) {
+            return Err("The state is locked by another call.".to_string());
+        }
+        Ok(MutexGuard {
+            inner: Rc::clone(&self.inner),
+        })
+    }
+}
+
+impl<T> Clone for Mutex<T> {
+    fn clone(&self) -> Self {
+        Mutex {
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex")
+            .field("locked", &self.inner.locked.get())
+            .finish()
+    }
+}
+
+/// Holds a [Mutex] locked until dropped, giving access to its value.
+pub struct MutexGuard<T> {
+    inner: Rc<MutexInner<T>>,
+}
+
+impl<T> Deref for MutexGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // The guard is the only way to the value while the mutex is locked.
+        unsafe { &*self.inner.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.inner.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<T> {
+    fn drop(&mut self) {
+        self.inner.locked.set(false);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MutexGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+struct RwLockInner<T> {
+    readers: Cell<usize>,
+    writer: Cell<bool>,
+    value: UnsafeCell<T>,
+}
+
+impl<T> RwLockInner<T> {
+    // Takes the lock for reading or writing, if possible.
+    fn try_acquire(&self, write: bool) -> bool {
+        if self.writer.get() || (write && self.readers.get() > 0) {
+            return false;
+        }
+        if write {
+            self.writer.set(true);
+        } else {
+            self.readers.set(self.readers.get() + 1);
+        }
+        true
+    }
+}
+
+/// A reader-writer lock that can be held across `await` points.
+///
+/// Any number of readers, or a single writer, can hold the lock at a time. Like [Mutex], the
+/// lock is a handle whose clones share the same value.
+pub struct RwLock<T> {
+    inner: Rc<RwLockInner<T>>,
+}
+
+impl<T> RwLock<T> {
+    /// Creates an unlocked lock holding `value`.
+    pub fn new(value: T) -> Self {
+        RwLock {
+            inner: Rc::new(RwLockInner {
+                readers: Cell::new(0),
+                writer: Cell::new(false),
+                value: UnsafeCell::new(value),
+            }),
+        }
+    }
+
+    /// Locks the lock for reading, or fails if it is held for writing.
+    ///
+    /// The error message is meant to be returned to the caller.
+    pub fn try_read(&self) -> Result<RwLockReadGuard<T>, String> {
+        if !self.inner.try_acquire(false) {
+            return Err("The state is being modified by another call.".to_string());
+        }
+        Ok(RwLockReadGuard {
+            inner: Rc::clone(&self.inner),
+        })
+    }
+
+    /// Locks the lock for writing, or fails if it is held at all.
+    ///
+    /// The error message is meant to be returned to the caller.
+    pub fn try_write(&self) -> Result<RwLockWriteGuard<T>, String> {
+        if !self.inner.try_acquire(true) {
+            return Err("The state is locked by another call.".to_string());
+        }
+        Ok(RwLockWriteGuard {
+            inner: Rc::clone(&self.inner),
+        })
+    }
+}
+
+impl<T> Clone for RwLock<T> {
+    fn clone(&self) -> Self {
+        RwLock {
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RwLock")
+            .field("readers", &self.inner.readers.get())
+            .field("writer", &self.inner.writer.get())
+            .finish()
+    }
+}
+
+/// Holds a [RwLock] locked for reading until dropped, giving access to its value.
+pub struct RwLockReadGuard<T> {
+    inner: Rc<RwLockInner<T>>,
+}
+
+impl<T> Deref for RwLockReadGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Only readers hold the lock while a read guard exists.
+        unsafe { &*self.inner.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<T> {
+    fn drop(&mut self) {
+        self.inner.readers.set(self.inner.readers.get() - 1);
+    }
+}
+
+/// Holds a [RwLock] locked for writing until dropped, giving access to its value.
+pub struct RwLockWriteGuard<T> {
+    inner: Rc<RwLockInner<T>>,
+}
+
+impl<T> Deref for RwLockWriteGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // The guard is the only way to the value while the lock is held for writing.
+        unsafe { &*self.inner.value.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.inner.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<T> {
+    fn drop(&mut self) {
+        self.inner.writer.set(false);
+    }
+}
+
+thread_local! {
+    static CALLERS: RefCell<BTreeSet<Principal>> = RefCell::default();
+}
+
+/// Rejects concurrent calls from the same principal.
+///
+/// Only one guard can exist for a principal at a time: create one at the start of a method
+/// to make a second call from the same caller fail while the first one awaits.
+#[derive(Debug)]
+pub struct CallerGuard {
+    caller: Principal,
+}
+
+impl CallerGuard {
+    /// Registers a call from `caller`, or fails if a call from `caller` holds a guard already.
+    ///
+    /// The error message is meant to be returned to the caller, for instance from a guard
+    /// function.
+    pub fn new(caller: Principal) -> Result<Self, String> {
+        if !CALLERS.with(|callers| callers.borrow_mut().insert(caller)) {
+            return Err(format!("Already processing a call from {}.", caller));
+        }
+        Ok(CallerGuard { caller })
+    }
+}
+
+impl Drop for CallerGuard {
+    fn drop(&mut self) {
+        CALLERS.with(|callers| callers.borrow_mut().remove(&self.caller));
+    }
+}
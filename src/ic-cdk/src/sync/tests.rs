@@ -0,0 +1,139 @@
+use super::*;
+use crate::futures::spawn;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+// The state of a gate: whether it is open, and the waker of the task waiting for it.
+type GateState = Rc<RefCell<(bool, Option<Waker>)>>;
+
+// A future completed by hand, standing in for the reply to an inter-canister call.
+struct Gate {
+    state: GateState,
+}
+
+struct GateSender {
+    state: GateState,
+}
+
+impl Gate {
+    fn new() -> (Self, GateSender) {
+        let state = GateState::default();
+        let sender = GateSender {
+            state: Rc::clone(&state),
+        };
+        (Gate { state }, sender)
+    }
+}
+
+impl Future for Gate {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.borrow_mut();
+        if state.0 {
+            return Poll::Ready(());
+        }
+        state.1 = Some(context.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl GateSender {
+    fn open(self) {
+        self.state.borrow_mut().0 = true;
+        self.waker().wake();
+    }
+
+    fn waker(&self) -> Waker {
+        self.state.borrow_mut().1.take().unwrap()
+    }
+}
+
+#[test]
+fn mutex_rejects_other_calls_across_await_points() {
+    let value = Mutex::new(0);
+    let (gate, gate_sender) = Gate::new();
+    let first = spawn({
+        let value = value.clone();
+        async move {
+            let mut value = value.try_lock().unwrap();
+            // Stands in for an inter-canister call.
+            gate.await;
+            *value += 1;
+        }
+    });
+    assert_eq!(
+        value.try_lock().unwrap_err(),
+        "The state is locked by another call."
+    );
+
+    gate_sender.open();
+    assert!(first.is_finished());
+    assert_eq!(*value.try_lock().unwrap(), 1);
+}
+
+#[test]
+fn cleanup_releases_mutex_guard() {
+    let value = Mutex::new(0);
+    let (gate, gate_sender) = Gate::new();
+    let first = spawn({
+        let value = value.clone();
+        async move {
+            let _value = value.try_lock().unwrap();
+            gate.await;
+        }
+    });
+    assert!(value.try_lock().is_err());
+
+    // The waker stands in for the one the cleanup of a call would get.
+    crate::futures::cleanup(gate_sender.waker());
+    assert!(!first.is_finished());
+    *value.try_lock().unwrap() += 1;
+}
+
+#[test]
+fn cleanup_of_spawned_task_releases_caller_guard_of_parent() {
+    let alice = Principal::from_slice(&[3]);
+    let (gate, gate_sender) = Gate::new();
+    let parent = spawn(async move {
+        let _guard = CallerGuard::new(alice).unwrap();
+        spawn(gate).await;
+    });
+    assert!(CallerGuard::new(alice).is_err());
+
+    // The child waits for the gate, whose waker stands in for the one of a call.
+    crate::futures::cleanup(gate_sender.waker());
+    assert!(!parent.is_finished());
+    assert!(CallerGuard::new(alice).is_ok());
+}
+
+#[test]
+fn rwlock_shares_reads_and_excludes_writes() {
+    let lock = RwLock::new(1);
+    let reader = lock.try_read().unwrap();
+    let second_reader = lock.try_read().unwrap();
+    assert_eq!(*reader + *second_reader, 2);
+    assert!(lock.try_write().is_err());
+
+    drop(reader);
+    drop(second_reader);
+    let mut writer = lock.try_write().unwrap();
+    *writer = 5;
+    assert!(lock.try_read().is_err());
+    assert!(lock.try_write().is_err());
+    drop(writer);
+    assert_eq!(*lock.try_read().unwrap(), 5);
+}
+
+#[test]
+fn caller_guard_rejects_concurrent_calls() {
+    let alice = Principal::from_slice(&[1]);
+    let bob = Principal::from_slice(&[2]);
+    let guard = CallerGuard::new(alice).unwrap();
+    assert!(CallerGuard::new(alice).is_err());
+    assert!(CallerGuard::new(bob).is_ok());
+
+    drop(guard);
+    assert!(CallerGuard::new(alice).is_ok());
+}
@@ -0,0 +1,297 @@
+//! Timers for scheduling work in the future.
+//!
+//! Timers are driven by the global timer of the canister: the earliest pending deadline is
+//! registered with `ic0.global_timer_set`, and the `canister_global_timer` entry point
+//! exported by this module starts every timer that has expired.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! ic_cdk::timer::set_timer(Duration::from_secs(10), || ic_cdk::print("ten seconds later"));
+//! ic_cdk::timer::set_timer_interval_async(Duration::from_secs(60), || async {
+//!     let _: ic_cdk::api::call::CallResult<()> =
+//!         ic_cdk::call(ic_cdk::id(), "refresh", ()).await;
+//! });
+//! ```
+//!
+//! Each timer runs in its own message, a call the canister makes to itself, so a timer that
+//! traps only rolls back its own changes: the other timers due in the same round still run,
+//! and the global timer stays set for the next deadline. A one-shot timer that traps is not
+//! retried, and an interval timer runs again at its next deadline.
+//!
+//! Where the global timer is not delivered reliably, [heartbeat] can also be called from
+//! the `#[heartbeat]` method of the canister, to start the expired timers every round.
+//!
+//! Timers are kept in heap memory and do not survive upgrades; set them again in
+//! `#[post_upgrade]` if needed.
+use crate::api::sys;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::future::Future;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// The identifier of a timer, returned by [set_timer] and [set_timer_interval].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId(u64);
+
+enum Task {
+    Once(Box<dyn FnOnce()>),
+    Repeated {
+        func: Rc<RefCell<dyn FnMut()>>,
+        interval: Duration,
+    },
+}
+
+// A scheduled run of a task. Ordered so that the earliest deadline is at the top of the heap.
+#[derive(PartialEq, Eq)]
+struct Timer {
+    time: u64,
+    task: TimerId,
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .time
+            .cmp(&self.time)
+            .then_with(|| other.task.cmp(&self.task))
+    }
+}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Default)]
+struct Timers {
+    next_id: u64,
+    tasks: BTreeMap<TimerId, Task>,
+    queue: BinaryHeap<Timer>,
+}
+
+thread_local! {
+    static TIMERS: RefCell<Timers> = RefCell::new(Timers::default());
+}
+
+/// Sets `func` to be executed once, after `delay` has passed.
+///
+/// The delay is measured from the current [time](crate::api::time); the timer runs in the
+/// first round at or after the deadline.
+pub fn set_timer(delay: Duration, func: impl FnOnce() + 'static) -> TimerId {
+    schedule(delay, Task::Once(Box::new(func)))
+}
+
+/// Sets `func` to be executed every `interval`, until the timer is cleared.
+///
+/// Each run is scheduled `interval` after the start of the previous one.
+pub fn set_timer_interval(interval: Duration, func: impl FnMut() + 'static) -> TimerId {
+    let func = Rc::new(RefCell::new(func));
+    schedule(interval, Task::Repeated { func, interval })
+}
+
+/// Cancels a timer set with [set_timer] or [set_timer_interval].
+///
+/// Clearing a timer that has already run or has been cleared does nothing.
+pub fn clear_timer(id: TimerId) {
+    TIMERS.with(|timers| timers.borrow_mut().tasks.remove(&id));
+}
+
+/// Like [set_timer], with a function returning a future that is run with
+/// [spawn](crate::spawn).
+///
+/// The timer counts as run once the future is spawned: the parts of the future after its
+/// first `await` run in the callbacks of the calls it makes.
+pub fn set_timer_async<F: Future<Output = ()> + 'static>(
+    delay: Duration,
+    func: impl FnOnce() -> F + 'static,
+) -> TimerId {
+    set_timer(delay, move || {
+        crate::spawn(func());
+    })
+}
+
+/// Like [set_timer_interval], with a function returning a future that is run with
+/// [spawn](crate::spawn) every `interval`.
+///
+/// A run does not wait for the future of the previous one to complete.
+pub fn set_timer_interval_async<F: Future<Output = ()> + 'static>(
+    interval: Duration,
+    mut func: impl FnMut() -> F + 'static,
+) -> TimerId {
+    set_timer_interval(interval, move || {
+        crate::spawn(func());
+    })
+}
+
+fn schedule(delay: Duration, task: Task) -> TimerId {
+    let time = deadline(crate::api::time(), delay);
+    let id = TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        let id = TimerId(timers.next_id);
+        timers.next_id += 1;
+        timers.tasks.insert(id, task);
+        timers.queue.push(Timer { time, task: id });
+        id
+    });
+    update_global_timer();
+    id
+}
+
+fn deadline(now: u64, delay: Duration) -> u64 {
+    let delay = u64::try_from(delay.as_nanos()).unwrap_or(u64::MAX);
+    now.saturating_add(delay)
+}
+
+// Points the global timer at the earliest pending deadline, dropping cleared timers on the way.
+fn update_global_timer() {
+    let next = TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        let timers = &mut *timers;
+        while let Some(timer) = timers.queue.peek() {
+            if timers.tasks.contains_key(&timer.task) {
+                return Some(timer.time);
+            }
+            timers.queue.pop();
+        }
+        None
+    });
+    // A deadline of 0 deactivates the global timer.
+    sys::global_timer_set(next.map_or(0, |time| time.max(1)));
+}
+
+/// Starts the timers that have expired, like the global timer does.
+///
+/// Opt-in fallback for canisters that cannot rely on the global timer being delivered: call
+/// it from the `#[heartbeat]` method. Each timer still runs in its own message, and runs
+/// once even if the global timer fires in the same round. Heartbeats are charged every
+/// round, so only use this where it is needed.
+pub fn heartbeat() {
+    start_expired_timers();
+}
+
+// The method the canister calls on itself to run one timer.
+const EXECUTOR_METHOD: &str = "<ic-cdk internal> timer_executor";
+
+// Removes the expired timers from the queue, scheduling the next run of interval timers,
+// and returns them in the order of their deadlines.
+fn take_expired_timers() -> Vec<TimerId> {
+    let now = crate::api::time();
+    // Timers set while running these are left for a later round, even if already due.
+    let expired = TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        let timers = &mut *timers;
+        let mut expired = Vec::new();
+        while let Some(timer) = timers.queue.peek() {
+            if timer.time > now {
+                break;
+            }
+            let id = timers.queue.pop().unwrap().task;
+            match timers.tasks.get(&id) {
+                Some(Task::Once(_)) => expired.push(id),
+                Some(Task::Repeated { interval, .. }) => {
+                    let time = deadline(now, *interval);
+                    timers.queue.push(Timer { time, task: id });
+                    expired.push(id);
+                }
+                None => {}
+            }
+        }
+        expired
+    });
+    update_global_timer();
+    expired
+}
+
+// Starts each expired timer in its own message with a call to the canister itself.
+fn start_expired_timers() {
+    let now = crate::api::time();
+    for id in take_expired_timers() {
+        let call =
+            crate::api::call::call_raw(crate::api::id(), EXECUTOR_METHOD, &id.0.to_le_bytes(), 0);
+        crate::spawn(async move {
+            let result = call.await;
+            TIMERS.with(|timers| {
+                let mut timers = timers.borrow_mut();
+                if let Err(crate::api::call::CallError::SyncPerformFailed { .. }) = result {
+                    // The timer did not run: try again in the next round.
+                    if let Some(Task::Once(_)) = timers.tasks.get(&id) {
+                        timers.queue.push(Timer {
+                            time: now,
+                            task: id,
+                        });
+                    }
+                } else if let Some(Task::Once(_)) = timers.tasks.get(&id) {
+                    // Still there only if the timer trapped, rolling back its removal.
+                    timers.tasks.remove(&id);
+                }
+            });
+            update_global_timer();
+        });
+    }
+}
+
+// Runs the timer `id`, unless it has been cleared since it was started.
+fn run_timer(id: TimerId) {
+    // Take the task out before running it, so that it can set or clear timers itself.
+    let task = TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        match timers.tasks.get(&id)? {
+            Task::Once(_) => timers.tasks.remove(&id),
+            Task::Repeated { func, interval } => Some(Task::Repeated {
+                func: Rc::clone(func),
+                interval: *interval,
+            }),
+        }
+    });
+    match task {
+        Some(Task::Once(func)) => func(),
+        Some(Task::Repeated { func, .. }) => (func.borrow_mut())(),
+        None => {}
+    }
+}
+
+// Runs the expired timers one after the other in the current message.
+#[cfg(test)]
+pub(crate) fn run_expired_timers() {
+    for id in take_expired_timers() {
+        run_timer(id);
+    }
+}
+
+// The entry points are only exported from canisters, but are also called by native tests.
+#[cfg_attr(target_arch = "wasm32", export_name = "canister_global_timer")]
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+extern "C" fn global_timer() {
+    crate::setup();
+    crate::log::set_method(None);
+    start_expired_timers();
+}
+
+#[cfg_attr(
+    target_arch = "wasm32",
+    export_name = "canister_update <ic-cdk internal> timer_executor"
+)]
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+extern "C" fn timer_executor() {
+    use crate::api::call;
+
+    crate::setup();
+    crate::log::set_method(None);
+    if crate::api::caller() != crate::api::id() {
+        call::reject("Timers can only be run by the canister itself.");
+        return;
+    }
+    let id = match call::arg_data_raw().try_into() {
+        Ok(bytes) => TimerId(u64::from_le_bytes(bytes)),
+        Err(_) => crate::trap("Invalid timer id."),
+    };
+    run_timer(id);
+    call::reply_raw(&[]);
+}
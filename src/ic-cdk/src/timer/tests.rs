@@ -0,0 +1,184 @@
+use super::*;
+use crate::api::backend::{set_system_api, MockSystemApi};
+use std::cell::Cell;
+
+const SECOND: u64 = 1_000_000_000;
+
+// Tests may share a thread, so each one starts from a fresh backend and no timers.
+fn mock_at(time: u64) -> Rc<MockSystemApi> {
+    TIMERS.with(|timers| *timers.borrow_mut() = Timers::default());
+    let mock = Rc::new(MockSystemApi::new());
+    mock.set_time(time);
+    set_system_api(mock.clone());
+    mock
+}
+
+fn counter() -> (Rc<Cell<u32>>, impl FnMut() + Clone) {
+    let count = Rc::new(Cell::new(0));
+    let inc = {
+        let count = count.clone();
+        move || count.set(count.get() + 1)
+    };
+    (count, inc)
+}
+
+#[test]
+fn one_shot_timer_runs_once_after_delay() {
+    let mock = mock_at(100 * SECOND);
+    let (count, inc) = counter();
+    set_timer(Duration::from_secs(5), inc);
+    assert_eq!(mock.global_timer(), 105 * SECOND);
+
+    run_expired_timers();
+    assert_eq!(count.get(), 0);
+
+    mock.set_time(105 * SECOND);
+    run_expired_timers();
+    assert_eq!(count.get(), 1);
+    assert_eq!(mock.global_timer(), 0);
+
+    mock.set_time(200 * SECOND);
+    run_expired_timers();
+    assert_eq!(count.get(), 1);
+}
+
+#[test]
+fn global_timer_tracks_earliest_deadline() {
+    let mock = mock_at(0);
+    let late = set_timer(Duration::from_secs(20), || {});
+    set_timer(Duration::from_secs(30), || {});
+    assert_eq!(mock.global_timer(), 20 * SECOND);
+
+    let early = set_timer(Duration::from_secs(10), || {});
+    assert_eq!(mock.global_timer(), 10 * SECOND);
+
+    clear_timer(early);
+    clear_timer(late);
+    mock.set_time(SECOND);
+    run_expired_timers();
+    assert_eq!(mock.global_timer(), 30 * SECOND);
+}
+
+#[test]
+fn interval_timer_repeats_until_cleared() {
+    let mock = mock_at(0);
+    let (count, inc) = counter();
+    let id = set_timer_interval(Duration::from_secs(10), inc);
+
+    for round in 1..=3 {
+        mock.set_time(round * 10 * SECOND);
+        run_expired_timers();
+        assert_eq!(count.get() as u64, round);
+        assert_eq!(mock.global_timer(), (round + 1) * 10 * SECOND);
+    }
+
+    clear_timer(id);
+    mock.set_time(100 * SECOND);
+    run_expired_timers();
+    assert_eq!(count.get(), 3);
+    assert_eq!(mock.global_timer(), 0);
+}
+
+#[test]
+fn timers_can_be_set_and_cleared_from_a_timer() {
+    let mock = mock_at(0);
+    let (count, inc) = counter();
+    let id = Rc::new(Cell::new(None));
+    let interval = {
+        let id = id.clone();
+        move || {
+            set_timer(Duration::ZERO, inc.clone());
+            clear_timer(id.get().unwrap());
+        }
+    };
+    id.set(Some(set_timer_interval(Duration::from_secs(1), interval)));
+
+    mock.set_time(SECOND);
+    run_expired_timers();
+    // The timer set from within the interval is due, but runs in the next round.
+    assert_eq!(count.get(), 0);
+    assert_eq!(mock.global_timer(), SECOND);
+
+    run_expired_timers();
+    assert_eq!(count.get(), 1);
+    assert_eq!(mock.global_timer(), 0);
+}
+
+#[test]
+fn global_timer_starts_each_timer_in_its_own_message() {
+    let mock = mock_at(0);
+    let (count, inc) = counter();
+    let first = set_timer(Duration::from_secs(1), inc.clone());
+    let second = set_timer(Duration::from_secs(2), inc);
+    set_timer(Duration::from_secs(10), || {});
+
+    mock.set_time(2 * SECOND);
+    global_timer();
+    let calls = mock.calls();
+    assert_eq!(calls.len(), 2);
+    assert!(calls
+        .iter()
+        .all(|call| call.callee == crate::api::id() && call.method == EXECUTOR_METHOD));
+    assert_eq!(calls[0].args, first.0.to_le_bytes());
+    assert_eq!(calls[1].args, second.0.to_le_bytes());
+    assert_eq!(mock.global_timer(), 10 * SECOND);
+
+    // Each call runs one timer, and only the canister may make it.
+    mock.set_arg_data(calls[0].args.clone());
+    timer_executor();
+    assert_eq!(
+        mock.rejection().unwrap(),
+        "Timers can only be run by the canister itself."
+    );
+    assert_eq!(count.get(), 0);
+
+    mock.reset_response();
+    mock.set_caller(crate::api::id());
+    timer_executor();
+    assert_eq!(mock.reply(), Some(vec![]));
+    assert_eq!(count.get(), 1);
+}
+
+#[test]
+fn heartbeat_starts_expired_timers_once() {
+    let mock = mock_at(0);
+    set_timer(Duration::from_secs(1), || {});
+    set_timer_interval(Duration::from_secs(5), || {});
+
+    heartbeat();
+    assert_eq!(mock.calls().len(), 0);
+
+    mock.set_time(SECOND);
+    heartbeat();
+    global_timer();
+    assert_eq!(mock.calls().len(), 1);
+    assert_eq!(mock.global_timer(), 5 * SECOND);
+}
+
+#[test]
+fn timer_that_cannot_be_started_runs_in_next_round() {
+    let mock = mock_at(0);
+    mock.set_call_perform_result(crate::api::call::RejectionCode::SysTransient as i32);
+    set_timer(Duration::from_secs(1), || {});
+
+    mock.set_time(SECOND);
+    global_timer();
+    assert_eq!(mock.calls().len(), 0);
+    assert_eq!(mock.global_timer(), SECOND);
+
+    mock.set_call_perform_result(0);
+    global_timer();
+    assert_eq!(mock.calls().len(), 1);
+    assert_eq!(mock.global_timer(), 0);
+}
+
+#[test]
+fn async_timer_spawns_future() {
+    let mock = mock_at(0);
+    let (count, mut inc) = counter();
+    set_timer_async(Duration::from_secs(1), move || async move { inc() });
+
+    mock.set_time(SECOND);
+    run_expired_timers();
+    assert_eq!(count.get(), 1);
+}